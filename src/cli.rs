@@ -3,6 +3,7 @@ use clap::{ArgAction, ValueHint};
 use clap_complete::Shell;
 use dotenv::dotenv;
 use std::path::PathBuf;
+use wm::library::commands::init::PRESETS;
 
 use crate::CONFIG_PATH_STR;
 
@@ -55,6 +56,13 @@ pub fn build() -> Command {
             .required(false)
             .action(ArgAction::SetTrue),
         )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
         // Allow passing direct args to the command
         .arg(
             arg!(
@@ -89,6 +97,455 @@ pub fn build() -> Command {
             .required(false)
             .action(ArgAction::SetTrue)
         )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("stop")
+        .about("Stop services without removing their containers")
+        .arg(
+            arg!([service] "Service to stop")
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(-a --all "Stop all services")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("status")
+        .about("Show the status of the services")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                -o --output <FORMAT> "Output format"
+            )
+            .default_value("text")
+            .required(false)
+            .value_parser(["text", "json"]),
+        )
+    )
+    .subcommand(Command::new("logs")
+        .about("Show the logs of a service")
+        .arg(
+            arg!([service] "Service to show the logs of")
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(-f --follow "Follow the log output")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(--since <SINCE> "Show logs since a timestamp (e.g. 2024-01-02T13:23:37Z) or relative time (e.g. 42m). Container services only")
+            .required(false)
+            .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(-n --tail <LINES> "Number of lines to show from the end of the logs")
+            .required(false)
+            .value_parser(value_parser!(String))
+        )
+        .arg(
+            arg!(-a --all "Show the logs of all services")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+    )
+    .subcommand(Command::new("env")
+        .about("Print the environment the commands run with")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                -f --format <FORMAT> "Output format"
+            )
+            .default_value("dotenv")
+            .required(false)
+            .value_parser(["dotenv", "sh", "fish", "json", "direnv"]),
+        )
+        .arg(
+            arg!(
+                --"no-services" "Does not start the services defined in the configuration file"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"no-features" "Do not use features"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"no-requirements" "Do not install requirements"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("shell")
+        .about("Start a shell with the environment of the machine")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                --"no-services" "Does not start the services defined in the configuration file"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"no-features" "Do not use features"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"no-requirements" "Do not install requirements"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --clean "Clean the docker environment before starting the shell"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --stop "Stop the services when the shell exits"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("exec")
+        .about("Run a command in a service")
+        .arg_required_else_help(true)
+        .arg(
+            arg!([service] "Service to run the command in")
+            .required(true)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                [command_args] ... "Command to run, passed after --"
+            )
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::CommandWithArguments)
+            .allow_hyphen_values(true)
+            .last(true)
+        )
+    )
+    .subcommand(Command::new("init")
+        .about("Create a configuration file for the project in the current directory")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to create."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                -p --preset <SERVICE> "Add a preconfigured service. Can be used multiple times."
+            )
+            .required(false)
+            .action(ArgAction::Append)
+            .value_parser(PRESETS)
+        )
+        .arg(
+            arg!(
+                -f --force "Overwrite the configuration file if it already exists."
+            )
+            .required(false)
+        )
+    )
+    .subcommand(Command::new("import")
+        .about("Import services from other tools into the configuration file")
+        .subcommand_required(true)
+        .subcommand(Command::new("compose")
+            .about("Import the services of a docker compose file")
+            .arg(
+                arg!(
+                    [file] "Compose file to import."
+                )
+                .default_value("docker-compose.yml")
+                .value_parser(value_parser!(PathBuf))
+                .value_hint(ValueHint::FilePath),
+            )
+            .arg(
+                arg!(
+                    -c --config <FILE> "Configuration file to add the services to."
+                )
+                .default_value(*CONFIG_PATH_STR)
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .value_hint(ValueHint::AnyPath),
+            )
+            .arg(
+                arg!(
+                    --force "Write the configuration file even when it has errors"
+                )
+                .required(false)
+                .action(ArgAction::SetTrue),
+            )
+        )
+    )
+    .subcommand(Command::new("export")
+        .about("Export the container services for docker compose, dev containers or GitHub Actions")
+        .arg(
+            arg!(
+                -t --target <TARGET> "Tool to export the services for."
+            )
+            .required(true)
+            .value_parser(["compose", "devcontainer", "github-actions"])
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                -o --output <FILE> "File to write to instead of stdout."
+            )
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::FilePath),
+        )
+    )
+    .subcommand(Command::new("graph")
+        .about("Show the services, their dependencies and startup order")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                -f --format <FORMAT> "Output format."
+            )
+            .default_value("text")
+            .required(false)
+            .value_parser(["text", "dot", "mermaid"])
+        )
+    )
+    .subcommand(Command::new("ports")
+        .about("Show the ports allocated for this project")
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(-a --all "Show the ports of every project on this machine")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+    )
+    .subcommand(Command::new("build")
+        .about("Rebuild the images of the services built from a Dockerfile")
+        .arg(
+            arg!([service] "Service to build, every service with a build section when not set")
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("pull")
+        .about("Pull the images of the container services in parallel")
+        .arg(
+            arg!([service] "Service to pull, every container service when not set")
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("lock")
+        .about("Lock the images of the container services to their digests in war_machine.lock")
+        .arg(
+            arg!([service] "Service to lock, every container service when not set")
+            .required(false)
+            .value_parser(value_parser!(String))
+            .value_hint(ValueHint::Other),
+        )
+        .arg(
+            arg!(
+                -c --config <FILE> "Configuration file to use."
+            )
+            .default_value(*CONFIG_PATH_STR)
+            .required(false)
+            .value_parser(value_parser!(PathBuf))
+            .value_hint(ValueHint::AnyPath),
+        )
+        .arg(
+            arg!(-u --update "Refresh the digests of images that are already locked")
+            .required(false)
+            .action(ArgAction::SetTrue)
+        )
+        .arg(
+            arg!(
+                --"dry-run" "Print what would be done without executing anything"
+            )
+            .required(false)
+            .action(ArgAction::SetTrue),
+        )
+    )
+    .subcommand(Command::new("config")
+        .about("Work with the configuration file")
+        .subcommand_required(true)
+        .subcommand(Command::new("validate")
+            .about("Validate the configuration file")
+            .arg(
+                arg!(
+                    -c --config <FILE> "Configuration file to use."
+                )
+                .default_value(*CONFIG_PATH_STR)
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .value_hint(ValueHint::AnyPath),
+            )
+        )
+        .subcommand(Command::new("services")
+            .about("List the service names, the shell completions use it")
+            .hide(true)
+            .arg(
+                arg!(
+                    -c --config <FILE> "Configuration file to use."
+                )
+                .default_value(*CONFIG_PATH_STR)
+                .required(false)
+                .value_parser(value_parser!(PathBuf))
+                .value_hint(ValueHint::AnyPath),
+            )
+        )
     )
     .subcommand(Command::new("secret")
         .about("Add or remove a secret")
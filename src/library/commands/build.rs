@@ -0,0 +1,85 @@
+use crate::{
+    library::{system::docker, utils::logging},
+    models::{
+        config::{Config, Source},
+        machine_state::MachineState,
+    },
+};
+
+use super::dry_run::{self, PlanSection};
+
+/// Rebuilds the images of the services that have a build section, or only the given one.
+/// With `dry_run`, the `docker build` commands are printed instead, after the port conflicts
+/// it holds.
+pub async fn build(
+    machine_state: &MachineState,
+    config: &Config,
+    service_name: Option<&str>,
+    dry_run: Option<Vec<String>>,
+) {
+    if let Some(service_name) = service_name {
+        if !config.services.iter().any(|s| s.name == service_name) {
+            logging::error(&format!("Service {} not found", service_name)).await;
+            std::process::exit(1);
+        }
+    }
+
+    let mut built_services = 0;
+    let mut failed = false;
+    let mut plan: Vec<PlanSection> = vec![];
+    if let Some(port_conflicts) = &dry_run {
+        plan.push(dry_run::port_conflicts_section(port_conflicts.clone()));
+    }
+    for service in &config.services {
+        if service_name.is_some_and(|name| name != service.name) {
+            continue;
+        }
+
+        let source = match &service.source {
+            Source::Container(source) if source.build.is_some() => source,
+            _ => {
+                if service_name.is_some() {
+                    logging::error(&format!(
+                        "{} isn't built from a Dockerfile, it has no build section",
+                        service.name
+                    ))
+                    .await;
+                    std::process::exit(1);
+                }
+                continue;
+            }
+        };
+
+        built_services += 1;
+        if dry_run.is_some() {
+            let steps =
+                match docker::build_command(machine_state, config, &service.name, source).await {
+                    Ok(build_command) => vec![build_command],
+                    Err(e) => vec![format!("# {} can't be built: {}", service.name, e)],
+                };
+            plan.push((format!("Build {}", service.name), steps));
+            continue;
+        }
+
+        match docker::build_image(machine_state, config, &service.name, source, true).await {
+            Ok(_) => logging::info(&format!("✅ Built {}", service.name)).await,
+            Err(e) => {
+                logging::error(&format!("🛑 Failed to build {}: {}", service.name, e)).await;
+                failed = true;
+            }
+        }
+    }
+
+    if built_services == 0 {
+        logging::warn("No service has a build section, there is nothing to build").await;
+    }
+
+    if dry_run.is_some() {
+        dry_run::print("build", &plan).await;
+        return;
+    }
+
+    if failed {
+        std::process::exit(1);
+    }
+}
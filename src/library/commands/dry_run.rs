@@ -0,0 +1,343 @@
+use std::{collections::HashMap, env};
+
+use crate::{
+    library::{
+        config::{dependencies, services},
+        utils::logging,
+    },
+    models::{
+        config::{Config, Feature, Requirement, Service},
+        machine_state::MachineState,
+    },
+};
+
+use super::graph::service_exposed_values;
+
+/// The steps `run` and `shell` leave out, the plan leaves them out too
+pub struct PlanOptions {
+    pub no_services: bool,
+    pub no_features: bool,
+    pub no_requirements: bool,
+    pub clean_mode: bool,
+}
+
+/// A titled group of steps, each step is a command or a `#` note
+pub type PlanSection = (String, Vec<String>);
+
+fn requirement_name(requirement: &Requirement) -> &'static str {
+    match requirement {
+        Requirement::Brew => "brew",
+        Requirement::Docker => "docker",
+        Requirement::Python => "python",
+        Requirement::Pipx => "pipx",
+        Requirement::Poetry => "poetry",
+    }
+}
+
+/// What `requirements::check` checks, it installs what is missing
+pub fn requirement_steps(config: &Config) -> Vec<String> {
+    config
+        .requirements
+        .iter()
+        .map(|requirement| {
+            format!(
+                "# check that {} is installed, install it when it isn't",
+                requirement_name(requirement)
+            )
+        })
+        .collect()
+}
+
+/// The networks are created along with the docker requirement
+pub fn network_steps(config: &Config) -> Vec<String> {
+    if config.networks.is_empty() {
+        return vec![];
+    }
+
+    if !config.requirements.contains(&Requirement::Docker) {
+        return vec!["# the networks are only created with the docker requirement".to_string()];
+    }
+
+    config
+        .networks
+        .iter()
+        .map(|network| format!("docker network create {}  # unless it exists", network))
+        .collect()
+}
+
+/// What the features check and load before the services start
+pub fn feature_steps(config: &Config) -> Vec<String> {
+    config
+        .features
+        .iter()
+        .map(|feature| match feature {
+            Feature::PythonPath(pythonpath_feature) => format!(
+                "# add PYTHONPATH={} to {} when it isn't set",
+                pythonpath_feature.pythonpath_value, pythonpath_feature.env_file_path
+            ),
+            Feature::Bitwarden => {
+                "# check that BWS_ACCESS_TOKEN is set and load the secrets from bitwarden"
+                    .to_string()
+            }
+        })
+        .collect()
+}
+
+/// Logins before the services start and logouts once they started. The credentials come from
+/// environment variables, so only their names are shown.
+pub fn registry_steps(config: &Config) -> Vec<String> {
+    let mut steps = vec![];
+    for registry in &config.registry_credentials {
+        steps.push(format!(
+            "docker login {} --username ${} --password-stdin  # password from ${}",
+            registry.registry, registry.username, registry.password
+        ));
+    }
+    for registry in &config.registry_credentials {
+        steps.push(format!(
+            "docker logout {}  # once the services started",
+            registry.registry
+        ));
+    }
+    steps
+}
+
+/// Services in the same wave start at the same time, at most `max_concurrent_starts` at once
+pub fn wave_steps(config: &Config) -> Vec<String> {
+    let (waves, leftover_services) = dependencies::startup_waves(config);
+
+    let mut steps: Vec<String> = waves
+        .iter()
+        .enumerate()
+        .map(|(index, wave)| {
+            let names: Vec<&str> = wave.iter().map(|service| service.name.as_str()).collect();
+            format!("{}. {}", index + 1, names.join(", "))
+        })
+        .collect();
+
+    if !leftover_services.is_empty() {
+        let names: Vec<&str> = leftover_services
+            .iter()
+            .map(|service| service.name.as_str())
+            .collect();
+        steps.push(format!(
+            "# never started, their dependencies can't start: {}",
+            names.join(", ")
+        ));
+    }
+    steps
+}
+
+/// The keys of the environment with where they come from, sorted by key. Values of command
+/// exposed values are only known once the command runs, so the command is shown instead.
+/// The keyring isn't read, so the secrets are shown as one entry.
+pub fn environment_keys(config: &Config, options: &PlanOptions) -> Vec<(String, String)> {
+    let mut keys: Vec<(String, String)> =
+        vec![("$(war machine secrets)".to_string(), "keyring".to_string())];
+
+    if !options.no_features && config.features.contains(&Feature::Bitwarden) {
+        keys.push(("$(bitwarden secrets)".to_string(), "bitwarden".to_string()));
+    }
+
+    if !options.no_services {
+        for service in &config.services {
+            for key in service_exposed_values(service) {
+                keys.push((key, service.name.clone()));
+            }
+        }
+    }
+
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// The plan of `prepare`, along with the checks the commands that prepare the machine run first
+pub async fn prepare_plan(
+    machine_state: &MachineState,
+    config: &Config,
+    options: &PlanOptions,
+) -> Vec<PlanSection> {
+    let mut plan: Vec<PlanSection> = vec![];
+
+    if !options.no_features {
+        plan.push(("Features".to_string(), feature_steps(config)));
+    }
+
+    if !options.no_requirements {
+        plan.push(("Requirements".to_string(), requirement_steps(config)));
+        plan.push(("Networks".to_string(), network_steps(config)));
+    }
+
+    if !options.no_services {
+        plan.push(("Registry logins".to_string(), registry_steps(config)));
+
+        let mut definitions = HashMap::new();
+        for service in &config.services {
+            let definition = services::definition(machine_state, config, service).await;
+            definitions.insert(service.name.clone(), definition);
+        }
+
+        // Cleaning recreates every service anyway. Services without a saved definition are
+        // compared with their containers, which would take running docker.
+        let mut recreated_services = vec![];
+        let mut changes = vec![];
+        if !options.clean_mode {
+            let started = HashMap::new();
+            for (name, service_changes) in
+                services::plan(machine_state, config, &definitions, &started)
+            {
+                let service = config.services.iter().find(|service| service.name == name);
+                if service.is_some_and(services::can_recreate) {
+                    changes.push(format!(
+                        "# {}: {}, will recreate",
+                        name,
+                        service_changes.join(", ")
+                    ));
+                    recreated_services.push(name);
+                } else {
+                    changes.push(format!(
+                        "# {}: {}, restart it yourself, it has no stop_command",
+                        name,
+                        service_changes.join(", ")
+                    ));
+                }
+            }
+        }
+        plan.push(("Changed services".to_string(), changes));
+
+        plan.push(("Startup waves".to_string(), wave_steps(config)));
+
+        for service in &config.services {
+            let recreate = recreated_services.contains(&service.name);
+            let steps = services::start_commands(
+                machine_state,
+                config,
+                service,
+                options.clean_mode,
+                recreate,
+            )
+            .await;
+            plan.push((format!("Start {}", service.name), steps));
+        }
+    }
+
+    let keys = environment_keys(config, options);
+    let longest_key_len = keys.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    let steps = keys
+        .iter()
+        .map(|(key, source)| format!("{:<width$}  # {}", key, source, width = longest_key_len))
+        .collect();
+    plan.push(("Environment keys".to_string(), steps));
+
+    plan
+}
+
+/// The plan of `run`, the command runs once the machine is prepared
+pub async fn run_plan(
+    machine_state: &MachineState,
+    config: &Config,
+    options: &PlanOptions,
+    command_name: Option<&str>,
+    command_args: &str,
+) -> Vec<PlanSection> {
+    let mut plan = prepare_plan(machine_state, config, options).await;
+
+    if let Some(command_name) = command_name {
+        if let Some(pre_command) = config.pre_commands.get(command_name) {
+            plan.push(("Pre command".to_string(), vec![pre_command.to_string()]));
+        }
+        if let Some(command) = config.commands.get(command_name) {
+            let command = format!("{} {}", command, command_args);
+            plan.push(("Command".to_string(), vec![command.trim().to_string()]));
+        }
+    }
+
+    plan
+}
+
+/// The plan of `shell`, the shell opens once the machine is prepared
+pub async fn shell_plan(
+    machine_state: &MachineState,
+    config: &Config,
+    options: &PlanOptions,
+    stop_on_exit: bool,
+) -> Vec<PlanSection> {
+    let mut plan = prepare_plan(machine_state, config, options).await;
+
+    let shell = env::var("SHELL").unwrap_or("sh".to_string());
+    plan.push((
+        "Shell".to_string(),
+        vec![format!("WM_MACHINE={} {}", config.machine_name, shell)],
+    ));
+
+    if stop_on_exit && !options.no_services {
+        let services: Vec<&Service> = config.services.iter().collect();
+        plan.extend(stop_plan(machine_state, config, &services).await);
+    }
+
+    plan
+}
+
+/// The plan of `clean` for the given services
+pub async fn clean_plan(
+    machine_state: &MachineState,
+    config: &Config,
+    services: &[&Service],
+) -> Vec<PlanSection> {
+    let mut plan = vec![];
+    for service in services {
+        let steps = services::clean_commands(machine_state, config, service).await;
+        plan.push((format!("Clean {}", service.name), steps));
+    }
+    plan
+}
+
+/// The plan of `stop` for the given services. Services are stopped in the reverse order of
+/// the startup waves, so nothing is stopped before the services that depend on it.
+pub async fn stop_plan(
+    machine_state: &MachineState,
+    config: &Config,
+    services: &[&Service],
+) -> Vec<PlanSection> {
+    let (waves, leftover_services) = dependencies::startup_waves(config);
+
+    let mut plan = vec![];
+    let ordered_services = waves.into_iter().rev().flatten().chain(leftover_services);
+    for service in ordered_services {
+        if !services.iter().any(|s| s.name == service.name) {
+            continue;
+        }
+        let steps = services::stop_commands(machine_state, config, service).await;
+        plan.push((format!("Stop {}", service.name), steps));
+    }
+    plan
+}
+
+/// What `state::preview` found wrong with the ports, it goes first in the plan
+pub fn port_conflicts_section(port_conflicts: Vec<String>) -> PlanSection {
+    ("Port conflicts".to_string(), port_conflicts)
+}
+
+/// Prints the plan, empty sections are left out
+pub async fn print(command: &str, plan: &[PlanSection]) {
+    logging::nl().await;
+    logging::print_color(logging::BG_YELLOW, " Dry run ").await;
+    logging::info(&format!(
+        "Nothing was executed, this is what `wm {}` would do",
+        command
+    ))
+    .await;
+
+    for (title, steps) in plan {
+        if steps.is_empty() {
+            continue;
+        }
+
+        logging::nl().await;
+        logging::print_color(logging::BOLD_WHITE, title).await;
+        for step in steps {
+            logging::print_color(logging::NC, &format!("  {}", step)).await;
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{collections::HashSet, env, path::Path};
+
+use crate::{
+    library::utils::{env_vars, logging},
+    models::{config::Config, machine_state::MachineState},
+};
+
+use super::prepare;
+
+/// Prints the environment that `wm run` would give to the command in the requested format.
+/// Logs are sent to stderr so the output can be evaluated directly.
+pub async fn env(
+    machine_state: MachineState,
+    config: Config,
+    config_path: &Path,
+    secrets: serde_json::Value,
+    format: &str,
+    no_services: bool,
+    no_features: bool,
+) {
+    let gathered_env_vars = prepare(
+        &machine_state,
+        &config,
+        &secrets,
+        no_services,
+        no_features,
+        false,
+    )
+    .await;
+
+    // Variables already set locally take precedence, just like when running a command
+    let mut seen = HashSet::new();
+    let mut resolved_env_vars = vec![];
+    for (key, value, _) in gathered_env_vars {
+        if seen.insert(key.clone()) {
+            let value = env::var(&key).unwrap_or(value);
+            resolved_env_vars.push((key, value));
+        }
+    }
+    resolved_env_vars.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    logging::nl().await;
+
+    // Make direnv reload the environment whenever the configuration changes
+    if format == "direnv" {
+        println!("watch_file {}", config_path.display());
+    }
+
+    println!("{}", env_vars::format_variables(&resolved_env_vars, format));
+}
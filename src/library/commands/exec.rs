@@ -0,0 +1,37 @@
+use crate::{
+    library::{config::services, utils::logging},
+    models::{config::Config, machine_state::MachineState},
+};
+
+/// Runs a command against a service and exits with the exit code of the command
+pub async fn exec(
+    machine_state: &MachineState,
+    config: &Config,
+    service_name: &str,
+    args: &[String],
+) {
+    let service = match config.services.iter().find(|s| s.name == service_name) {
+        Some(service) => service,
+        None => {
+            logging::error(&format!("Service {} not found", service_name)).await;
+            std::process::exit(1);
+        }
+    };
+
+    if args.is_empty() {
+        logging::error("No command provided. Pass the command after --").await;
+        std::process::exit(1);
+    }
+
+    match services::exec(machine_state, config, service, args).await {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            logging::error(&format!(
+                "🛑 Failed to run command in {}: {}",
+                service_name, e
+            ))
+            .await;
+            std::process::exit(1);
+        }
+    }
+}
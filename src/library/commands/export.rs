@@ -0,0 +1,557 @@
+use std::path::PathBuf;
+
+use serde_json::json;
+use serde_yaml_ng::{Mapping, Value};
+
+use crate::{
+    library::{machine, system::docker, utils::logging},
+    models::{
+        config::{Config, ContainerBuild, ExposedValueType, Source},
+        machine_state::MachineState,
+    },
+};
+
+const HEADER: &str =
+    "# Generated by `wm export` from war_machine.toml, regenerate it instead of editing it\n";
+
+/// Flags of `docker run` that take a value
+const VALUE_FLAGS: &[&str] = &[
+    "-p",
+    "--publish",
+    "-e",
+    "--env",
+    "-v",
+    "--volume",
+    "--name",
+    "--network",
+    "--network-alias",
+    "--env-file",
+    "-u",
+    "--user",
+    "-w",
+    "--workdir",
+    "--entrypoint",
+    "--restart",
+    "-l",
+    "--label",
+    "-h",
+    "--hostname",
+    "--add-host",
+    "--platform",
+    "-m",
+    "--memory",
+    "--cpus",
+    "--shm-size",
+    "--cap-add",
+    "--cap-drop",
+    "--dns",
+    "--expose",
+    "--tmpfs",
+    "--mount",
+    "--health-cmd",
+    "--health-interval",
+    "--health-timeout",
+    "--health-retries",
+    "--health-start-period",
+    "--health-start-interval",
+];
+
+/// What a container service looks like once its start command is resolved
+#[derive(Debug, Default)]
+struct ContainerDefinition {
+    name: String,
+    image: String,
+    build: Option<ContainerBuild>,
+    ports: Vec<String>,
+    environment: Vec<(String, String)>,
+    volumes: Vec<String>,
+    health: Vec<(String, String)>,
+    command: Vec<String>,
+    depends_on: Vec<String>,
+}
+
+/// Splits a command into words the way a POSIX shell would, without expanding anything
+fn split_words(command: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '"' => {
+                in_word = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => {
+                            if let Some(next) = chars.next() {
+                                word.push(next);
+                            }
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                // Line continuations separate words
+                Some('\n') | None => {}
+                Some(next) => {
+                    in_word = true;
+                    word.push(next);
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+/// Reads the ports, environment, volumes and healthcheck from a `docker run` command
+fn parse_run_command(command: &str, image: &str, definition: &mut ContainerDefinition) {
+    let words = split_words(command);
+    let start = match words.iter().position(|word| word == "run") {
+        Some(index) => index + 1,
+        None => return,
+    };
+
+    parse_run_args(&words[start..], image, definition);
+}
+
+/// Reads the arguments that follow `docker run`
+fn parse_run_args(args: &[String], image: &str, definition: &mut ContainerDefinition) {
+    let current_dir = std::env::current_dir().unwrap().display().to_string();
+    let mut words = args.iter();
+    while let Some(word) = words.next() {
+        if !word.starts_with('-') {
+            // The first argument that isn't a flag is the image, the rest is the command
+            if word.contains(image) {
+                definition.command = words.cloned().collect();
+                return;
+            }
+            continue;
+        }
+
+        let (flag, value) = match word.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+            _ => (word.as_str(), None),
+        };
+        if !VALUE_FLAGS.contains(&flag) {
+            continue;
+        }
+        let value = match value.or_else(|| words.next().cloned()) {
+            Some(value) => value,
+            None => return,
+        };
+
+        match flag {
+            "-p" | "--publish" => definition.ports.push(value),
+            "-e" | "--env" => {
+                // Variables without a value are passed through from the host, which
+                // doesn't carry over to the generated files
+                if let Some((key, value)) = value.split_once('=') {
+                    definition
+                        .environment
+                        .push((key.to_string(), value.to_string()));
+                }
+            }
+            "-v" | "--volume" => {
+                let volume = match value
+                    .strip_prefix("$(pwd)/")
+                    .or_else(|| value.strip_prefix(&format!("{}/", current_dir)))
+                {
+                    Some(relative) => format!("./{}", relative),
+                    None => value,
+                };
+                definition.volumes.push(volume);
+            }
+            flag if flag.starts_with("--health-") => definition
+                .health
+                .push((flag.trim_start_matches("--health-").to_string(), value)),
+            _ => {}
+        }
+    }
+}
+
+/// Resolves the container services and the values they expose with the current port map
+async fn collect(
+    machine_state: &MachineState,
+    config: &Config,
+) -> (Vec<ContainerDefinition>, Vec<(String, String)>, Vec<String>) {
+    let mut definitions = vec![];
+    let mut environment = vec![];
+    let mut notes = vec![];
+
+    let container_names: Vec<&String> = config
+        .services
+        .iter()
+        .filter(|service| matches!(service.source, Source::Container(_)))
+        .map(|service| &service.name)
+        .collect();
+
+    for service in &config.services {
+        let source = match &service.source {
+            Source::Container(source) => source,
+            Source::App(_) => {
+                notes.push(format!(
+                    "{} is an app service, only container services are exported",
+                    service.name
+                ));
+                continue;
+            }
+        };
+
+        let mut definition = ContainerDefinition {
+            name: service.name.clone(),
+            image: docker::image_ref(config, &service.name, source),
+            build: source.build.clone(),
+            depends_on: service
+                .depends_on
+                .iter()
+                .filter(|name| container_names.contains(name))
+                .cloned()
+                .collect(),
+            ..Default::default()
+        };
+
+        let image = if source.build.is_some() {
+            docker::built_image(config, &service.name)
+        } else {
+            source.image.clone()
+        };
+
+        if let Some(start_command) = &source.start_command {
+            let start_command = docker::replace_placeholders(
+                machine_state,
+                config,
+                start_command,
+                &service.name,
+                source,
+            )
+            .await;
+            parse_run_command(&start_command, &image, &mut definition);
+        } else {
+            let args =
+                docker::run_args(machine_state, config, &service.name, source, &service.ports)
+                    .await;
+            parse_run_args(&args, &image, &mut definition);
+        }
+
+        for port in &service.ports {
+            let key = machine::ports::service_port_key(&service.name, &port.name);
+            if let (Some(host_port), Some(container_port)) =
+                (machine_state.ports.get(&key), port.container)
+            {
+                let mapping = format!("{}:{}", host_port, container_port);
+                if !definition.ports.contains(&mapping) {
+                    definition.ports.push(mapping);
+                }
+            }
+        }
+        definitions.push(definition);
+
+        for exposed_value in &service.exposed_values {
+            match exposed_value {
+                ExposedValueType::Literal(exposed_value) => {
+                    let value = machine::ports::replace_service_ports_in_text(
+                        machine_state,
+                        &service.name,
+                        &exposed_value.value,
+                    )
+                    .await;
+                    let value = machine::ports::replace_ports_in_text(machine_state, &value).await;
+                    environment.push((exposed_value.name.trim().to_uppercase(), value));
+                }
+                ExposedValueType::Command(exposed_value) => notes.push(format!(
+                    "{}: the values of `{}` are only known at runtime and were not exported",
+                    service.name, exposed_value.command
+                )),
+            }
+        }
+    }
+
+    (definitions, environment, notes)
+}
+
+fn yaml_list(values: &[String]) -> Value {
+    Value::Sequence(values.iter().map(|v| Value::String(v.clone())).collect())
+}
+
+fn yaml_map(values: &[(String, String)]) -> Value {
+    let mut mapping = Mapping::new();
+    for (key, value) in values {
+        mapping.insert(Value::String(key.clone()), Value::String(value.clone()));
+    }
+    Value::Mapping(mapping)
+}
+
+fn render_compose(
+    config: &Config,
+    definitions: &[ContainerDefinition],
+    environment: &[(String, String)],
+) -> String {
+    let mut services = Mapping::new();
+    for definition in definitions {
+        let mut service = Mapping::new();
+        service.insert("image".into(), definition.image.clone().into());
+        if let Some(build) = &definition.build {
+            let mut build_mapping = Mapping::new();
+            build_mapping.insert("context".into(), build.context.clone().into());
+            if let Some(dockerfile) = &build.dockerfile {
+                build_mapping.insert("dockerfile".into(), dockerfile.clone().into());
+            }
+            if let Some(target) = &build.target {
+                build_mapping.insert("target".into(), target.clone().into());
+            }
+            if !build.args.is_empty() {
+                let mut args: Vec<(String, String)> = build.args.clone().into_iter().collect();
+                args.sort();
+                build_mapping.insert("args".into(), yaml_map(&args));
+            }
+            service.insert("build".into(), Value::Mapping(build_mapping));
+        }
+        if !definition.command.is_empty() {
+            service.insert("command".into(), yaml_list(&definition.command));
+        }
+        if !definition.ports.is_empty() {
+            service.insert("ports".into(), yaml_list(&definition.ports));
+        }
+        if !definition.environment.is_empty() {
+            service.insert("environment".into(), yaml_map(&definition.environment));
+        }
+        if !definition.volumes.is_empty() {
+            service.insert("volumes".into(), yaml_list(&definition.volumes));
+        }
+        if !definition.health.is_empty() {
+            let mut healthcheck = Mapping::new();
+            for (option, value) in &definition.health {
+                match option.as_str() {
+                    "cmd" => {
+                        healthcheck.insert(
+                            "test".into(),
+                            yaml_list(&["CMD-SHELL".to_string(), value.clone()]),
+                        );
+                    }
+                    "retries" => {
+                        let retries = value
+                            .parse::<u64>()
+                            .map(Value::from)
+                            .unwrap_or_else(|_| value.clone().into());
+                        healthcheck.insert("retries".into(), retries);
+                    }
+                    option => {
+                        healthcheck.insert(option.replace('-', "_").into(), value.clone().into());
+                    }
+                }
+            }
+            service.insert("healthcheck".into(), Value::Mapping(healthcheck));
+        }
+        if !definition.depends_on.is_empty() {
+            service.insert("depends_on".into(), yaml_list(&definition.depends_on));
+        }
+        services.insert(definition.name.clone().into(), Value::Mapping(service));
+    }
+
+    let mut compose = Mapping::new();
+    compose.insert("name".into(), config.machine_name.clone().into());
+    compose.insert("services".into(), Value::Mapping(services));
+    if !environment.is_empty() {
+        // Extension fields are ignored by compose, app services can merge them in
+        compose.insert("x-environment".into(), yaml_map(environment));
+    }
+
+    format!(
+        "{}{}",
+        HEADER,
+        serde_yaml_ng::to_string(&Value::Mapping(compose)).unwrap()
+    )
+}
+
+fn render_devcontainer(
+    config: &Config,
+    definitions: &[ContainerDefinition],
+    environment: &[(String, String)],
+) -> String {
+    let mut forward_ports = vec![];
+    let mut ports_attributes = serde_json::Map::new();
+    for definition in definitions {
+        for port in &definition.ports {
+            // host_ip:host:container or host:container
+            let parts: Vec<&str> = port.split(':').collect();
+            if parts.len() < 2 {
+                continue;
+            }
+            if let Ok(host_port) = parts[parts.len() - 2].parse::<u16>() {
+                forward_ports.push(json!(host_port));
+                ports_attributes.insert(
+                    host_port.to_string(),
+                    json!({ "label": definition.name, "onAutoForward": "silent" }),
+                );
+            }
+        }
+    }
+
+    let container_env: serde_json::Map<String, serde_json::Value> = environment
+        .iter()
+        .map(|(key, value)| (key.clone(), json!(value)))
+        .collect();
+
+    // The services run with docker in docker, started by wm inside the container
+    let devcontainer = json!({
+        "name": config.machine_name,
+        "image": "mcr.microsoft.com/devcontainers/base:ubuntu",
+        "features": {
+            "ghcr.io/devcontainers/features/docker-in-docker:2": {}
+        },
+        "postCreateCommand": [
+            "bash",
+            "-c",
+            "bash <(curl -sS \"https://raw.githubusercontent.com/generative-ai-inc/war-machine/main/install.sh\")"
+        ],
+        "forwardPorts": forward_ports,
+        "portsAttributes": ports_attributes,
+        "containerEnv": container_env,
+    });
+
+    format!(
+        "// {}{}\n",
+        HEADER.trim_start_matches("# "),
+        serde_json::to_string_pretty(&devcontainer).unwrap()
+    )
+}
+
+fn render_github_actions(
+    definitions: &[ContainerDefinition],
+    environment: &[(String, String)],
+) -> String {
+    let mut services = Mapping::new();
+    for definition in definitions {
+        let mut service = Mapping::new();
+        service.insert("image".into(), definition.image.clone().into());
+        if !definition.ports.is_empty() {
+            service.insert("ports".into(), yaml_list(&definition.ports));
+        }
+        if !definition.environment.is_empty() {
+            service.insert("env".into(), yaml_map(&definition.environment));
+        }
+        if !definition.volumes.is_empty() {
+            // Bind mounts need absolute paths, relative ones point into the checkout
+            let volumes: Vec<String> = definition
+                .volumes
+                .iter()
+                .map(|volume| match volume.strip_prefix("./") {
+                    Some(relative) => format!("${{{{ github.workspace }}}}/{}", relative),
+                    None => volume.clone(),
+                })
+                .collect();
+            service.insert("volumes".into(), yaml_list(&volumes));
+        }
+        // Service containers can't override their command, but they take docker create options
+        if !definition.health.is_empty() {
+            let options: Vec<String> = definition
+                .health
+                .iter()
+                .map(|(option, value)| {
+                    format!("--health-{} \"{}\"", option, value.replace('"', "\\\""))
+                })
+                .collect();
+            service.insert("options".into(), options.join(" ").into());
+        }
+        services.insert(definition.name.clone().into(), Value::Mapping(service));
+    }
+
+    let mut job = Mapping::new();
+    job.insert("services".into(), Value::Mapping(services));
+    if !environment.is_empty() {
+        job.insert("env".into(), yaml_map(environment));
+    }
+
+    format!(
+        "{}# Paste these into the job that needs the services\n{}",
+        HEADER,
+        serde_yaml_ng::to_string(&Value::Mapping(job)).unwrap()
+    )
+}
+
+/// Renders the container services of the machine in the format of another tool
+pub async fn render(
+    machine_state: &MachineState,
+    config: &Config,
+    target: &str,
+) -> (String, Vec<String>) {
+    let (definitions, environment, mut notes) = collect(machine_state, config).await;
+
+    let rendered = match target {
+        "devcontainer" => render_devcontainer(config, &definitions, &environment),
+        "github-actions" => render_github_actions(&definitions, &environment),
+        _ => render_compose(config, &definitions, &environment),
+    };
+
+    if target == "github-actions" {
+        for definition in definitions.iter().filter(|d| d.build.is_some()) {
+            notes.push(format!(
+                "{}: service containers can't be built, push {} to a registry the job can pull from",
+                definition.name, definition.image
+            ));
+        }
+        for definition in definitions.iter().filter(|d| !d.command.is_empty()) {
+            notes.push(format!(
+                "{}: service containers can't override the command, `{}` was dropped",
+                definition.name,
+                definition.command.join(" ")
+            ));
+        }
+    }
+
+    (rendered, notes)
+}
+
+/// Writes the exported definitions to the output file, or to stdout
+pub async fn export(
+    machine_state: &MachineState,
+    config: &Config,
+    target: &str,
+    output: Option<PathBuf>,
+) {
+    let (rendered, notes) = render(machine_state, config, target).await;
+
+    for note in &notes {
+        logging::warn(note).await;
+    }
+
+    match output {
+        Some(output) => {
+            if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+                tokio::fs::create_dir_all(parent).await.unwrap();
+            }
+            if let Err(e) = tokio::fs::write(&output, rendered).await {
+                logging::error(&format!("Failed to write {}: {}", output.display(), e)).await;
+                std::process::exit(1);
+            }
+            logging::info(&format!("✅ Exported {} to {}", target, output.display())).await;
+        }
+        None => print!("{}", rendered),
+    }
+}
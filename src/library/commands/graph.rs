@@ -0,0 +1,266 @@
+use regex::Regex;
+
+use crate::{
+    library::config::dependencies,
+    models::config::{Config, ExposedValueType, Service, Source},
+};
+
+/// Networks a service joins, from its `networks` or the `--network` flags of its start command
+fn service_networks(config: &Config, service: &Service) -> Vec<String> {
+    let source = match &service.source {
+        Source::Container(source) => source,
+        Source::App(_) => return vec![],
+    };
+
+    let mut networks: Vec<String> = vec![];
+    let mut add_network = |network: &str| {
+        let network = network
+            .trim_matches(|c| c == '\'' || c == '"')
+            .replace("${machine_name}", &config.machine_name);
+        if !networks.contains(&network) {
+            networks.push(network);
+        }
+    };
+
+    match &source.start_command {
+        Some(start_command) => {
+            let regex = Regex::new(r"--network(?:=|\s+)([^\s\\]+)").unwrap();
+            for captures in regex.captures_iter(start_command) {
+                add_network(&captures[1]);
+            }
+        }
+        None => source
+            .networks
+            .iter()
+            .for_each(|network| add_network(network)),
+    }
+    networks
+}
+
+/// Names of the values a service exposes. Values from commands are only known at runtime,
+/// so the command is shown instead.
+pub fn service_exposed_values(service: &Service) -> Vec<String> {
+    service
+        .exposed_values
+        .iter()
+        .map(|exposed_value| match exposed_value {
+            ExposedValueType::Literal(exposed_value) => exposed_value.name.trim().to_uppercase(),
+            ExposedValueType::Command(exposed_value) => {
+                let command = exposed_value.command.trim();
+                match command.char_indices().nth(40) {
+                    Some((index, _)) => format!("$({}…)", &command[..index]),
+                    None => format!("$({})", command),
+                }
+            }
+        })
+        .collect()
+}
+
+fn service_kind(service: &Service) -> String {
+    match &service.source {
+        Source::Container(source) => match &source.build {
+            Some(build) => format!("container built from {}", build.context),
+            None => format!("container {}:{}", source.image, source.tag),
+        },
+        Source::App(_) => "app".to_string(),
+    }
+}
+
+fn render_text(config: &Config) -> String {
+    let (waves, unstartable_services) = dependencies::startup_waves(config);
+    let mut groups: Vec<(String, Vec<&Service>)> = waves
+        .into_iter()
+        .enumerate()
+        .map(|(index, wave)| (format!("Wave {}", index + 1), wave))
+        .collect();
+    if !unstartable_services.is_empty() {
+        groups.push((
+            "Can't start (circular or missing dependencies)".to_string(),
+            unstartable_services,
+        ));
+    }
+
+    let mut lines = vec![config.machine_name.clone()];
+    for (label, services) in groups {
+        lines.push(String::new());
+        lines.push(label);
+        for service in services {
+            lines.push(format!("  {} ({})", service.name, service_kind(service)));
+            if !service.depends_on.is_empty() {
+                lines.push(format!("    depends on: {}", service.depends_on.join(", ")));
+            }
+            let networks = service_networks(config, service);
+            if !networks.is_empty() {
+                lines.push(format!("    networks:   {}", networks.join(", ")));
+            }
+            let exposed_values = service_exposed_values(service);
+            if !exposed_values.is_empty() {
+                lines.push(format!("    exposes:    {}", exposed_values.join(", ")));
+            }
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn render_dot(config: &Config) -> String {
+    let (waves, unstartable_services) = dependencies::startup_waves(config);
+
+    let node = |service: &Service| {
+        let mut label = vec![service.name.clone(), service_kind(service)];
+        label.extend(service_exposed_values(service));
+        format!(
+            "    \"{}\" [label=\"{}\"];",
+            dot_escape(&service.name),
+            label
+                .iter()
+                .map(|line| dot_escape(line))
+                .collect::<Vec<String>>()
+                .join("\\n")
+        )
+    };
+
+    let mut lines = vec![
+        format!("digraph \"{}\" {{", dot_escape(&config.machine_name)),
+        "  rankdir=LR;".to_string(),
+        "  node [shape=box, style=rounded];".to_string(),
+    ];
+
+    // Each wave is a cluster so the startup order stands out
+    for (index, wave) in waves.iter().enumerate() {
+        lines.push(format!("  subgraph cluster_wave_{} {{", index + 1));
+        lines.push(format!("    label=\"Wave {}\";", index + 1));
+        lines.push("    style=filled;".to_string());
+        lines.push("    color=\"#e8f0fe\";".to_string());
+        lines.extend(wave.iter().map(|service| node(service)));
+        lines.push("  }".to_string());
+    }
+    if !unstartable_services.is_empty() {
+        lines.push("  subgraph cluster_unstartable {".to_string());
+        lines.push("    label=\"Can't start\";".to_string());
+        lines.push("    style=filled;".to_string());
+        lines.push("    color=\"#fde8e8\";".to_string());
+        lines.extend(unstartable_services.iter().map(|service| node(service)));
+        lines.push("  }".to_string());
+    }
+
+    let mut networks: Vec<String> = vec![];
+    for service in &config.services {
+        for dependency in &service.depends_on {
+            lines.push(format!(
+                "  \"{}\" -> \"{}\";",
+                dot_escape(dependency),
+                dot_escape(&service.name)
+            ));
+        }
+        for network in service_networks(config, service) {
+            if !networks.contains(&network) {
+                lines.push(format!(
+                    "  \"network:{}\" [label=\"{}\", shape=ellipse, style=dashed];",
+                    dot_escape(&network),
+                    dot_escape(&network)
+                ));
+                networks.push(network.clone());
+            }
+            lines.push(format!(
+                "  \"{}\" -> \"network:{}\" [style=dashed, arrowhead=none];",
+                dot_escape(&service.name),
+                dot_escape(&network)
+            ));
+        }
+    }
+
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// Mermaid ids can only contain word characters
+fn mermaid_id(prefix: &str, value: &str) -> String {
+    let id: String = value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{}_{}", prefix, id)
+}
+
+fn mermaid_escape(value: &str) -> String {
+    value.replace('"', "#quot;")
+}
+
+fn render_mermaid(config: &Config) -> String {
+    let (waves, unstartable_services) = dependencies::startup_waves(config);
+
+    let node = |service: &Service| {
+        let mut label = vec![format!("<b>{}</b>", service.name), service_kind(service)];
+        label.extend(service_exposed_values(service));
+        format!(
+            "    {}[\"{}\"]",
+            mermaid_id("service", &service.name),
+            mermaid_escape(&label.join("<br/>"))
+        )
+    };
+
+    let mut lines = vec!["flowchart LR".to_string()];
+
+    // Each wave is a subgraph so the startup order stands out
+    for (index, wave) in waves.iter().enumerate() {
+        lines.push(format!(
+            "  subgraph wave_{}[\"Wave {}\"]",
+            index + 1,
+            index + 1
+        ));
+        lines.extend(wave.iter().map(|service| node(service)));
+        lines.push("  end".to_string());
+    }
+    if !unstartable_services.is_empty() {
+        lines.push("  subgraph unstartable[\"Can't start\"]".to_string());
+        lines.extend(unstartable_services.iter().map(|service| node(service)));
+        lines.push("  end".to_string());
+        lines.push("  style unstartable fill:#fde8e8".to_string());
+    }
+
+    let mut networks: Vec<String> = vec![];
+    for service in &config.services {
+        for dependency in &service.depends_on {
+            lines.push(format!(
+                "  {} --> {}",
+                mermaid_id("service", dependency),
+                mermaid_id("service", &service.name)
+            ));
+        }
+        for network in service_networks(config, service) {
+            if !networks.contains(&network) {
+                lines.push(format!(
+                    "  {}([\"{}\"])",
+                    mermaid_id("network", &network),
+                    mermaid_escape(&network)
+                ));
+                networks.push(network.clone());
+            }
+            lines.push(format!(
+                "  {} -.- {}",
+                mermaid_id("service", &service.name),
+                mermaid_id("network", &network)
+            ));
+        }
+    }
+
+    lines.join("\n") + "\n"
+}
+
+/// Renders the services, their dependencies, networks and exposed values
+pub fn render(config: &Config, format: &str) -> String {
+    match format {
+        "dot" => render_dot(config),
+        "mermaid" => render_mermaid(config),
+        _ => render_text(config),
+    }
+}
+
+pub async fn graph(config: &Config, format: &str) {
+    print!("{}", render(config, format));
+}
@@ -0,0 +1,919 @@
+use std::{collections::HashSet, error::Error, path::PathBuf};
+
+use serde_yaml_ng::{Mapping, Value};
+use tokio::fs;
+use toml_edit::{DocumentMut, Item};
+
+use crate::{
+    library::{
+        commands::init,
+        config::validation,
+        utils::{env_vars, logging},
+    },
+    models::diagnostic::Severity,
+};
+
+/// Services translated from a compose file, with everything that could not be translated
+#[derive(Debug, Default)]
+pub struct ComposeImport {
+    pub services: Vec<String>,
+    pub service_names: Vec<String>,
+    pub networks: Vec<String>,
+    pub notes: Vec<String>,
+}
+
+/// Keys of a compose service that are translated, everything else is reported
+const SUPPORTED_SERVICE_KEYS: &[&str] = &[
+    "image",
+    "ports",
+    "environment",
+    "env_file",
+    "volumes",
+    "healthcheck",
+    "networks",
+    "network_mode",
+    "depends_on",
+    "command",
+    "entrypoint",
+    "user",
+    "working_dir",
+    "hostname",
+    "restart",
+    "privileged",
+    "init",
+    "tty",
+    "stdin_open",
+    "shm_size",
+    "platform",
+    "cap_add",
+    "cap_drop",
+    "extra_hosts",
+    "labels",
+    "dns",
+    "expose",
+    "mem_limit",
+    "cpus",
+];
+
+/// Renders scalars (strings, numbers, booleans) as strings
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Compose accepts either a list or a single string for most list options
+fn string_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Sequence(items) => items.iter().filter_map(scalar).collect(),
+        other => scalar(other).into_iter().collect(),
+    }
+}
+
+/// Compose accepts either `KEY=value` lists or `KEY: value` mappings
+fn key_value_list(value: &Value, separator: &str) -> Vec<String> {
+    match value {
+        Value::Mapping(mapping) => mapping
+            .iter()
+            .filter_map(|(key, value)| {
+                let key = scalar(key)?;
+                Some(match scalar(value) {
+                    Some(value) => format!("{}{}{}", key, separator, value),
+                    None => key,
+                })
+            })
+            .collect(),
+        other => string_list(other),
+    }
+}
+
+/// Quotes a value for the shell that runs the start command. Values that reference
+/// variables are double quoted so the shell still expands them, like compose would.
+fn quote_arg(value: &str) -> String {
+    let is_plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@,+%".contains(c));
+
+    if is_plain {
+        value.to_string()
+    } else if value.contains('$') {
+        let escaped = value
+            .replace('\\', r"\\")
+            .replace('"', "\\\"")
+            .replace('`', "\\`")
+            .replace("$$", "\\$");
+        format!("\"{}\"", escaped)
+    } else {
+        env_vars::quote_sh(value)
+    }
+}
+
+/// Splits `registry/image:tag` into the parts of a container source
+fn split_image(reference: &str) -> (String, String, String) {
+    let (reference, _) = reference.split_once('@').unwrap_or((reference, ""));
+
+    let (registry, rest) = match reference.split_once('/') {
+        Some((first, rest))
+            if first.contains('.') || first.contains(':') || first == "localhost" =>
+        {
+            (first.to_string(), rest.to_string())
+        }
+        _ => ("docker.io".to_string(), reference.to_string()),
+    };
+
+    let last_slash = rest.rfind('/').map_or(0, |index| index + 1);
+    match rest[last_slash..].rfind(':') {
+        Some(index) => {
+            let index = last_slash + index;
+            (
+                registry,
+                rest[..index].to_string(),
+                rest[index + 1..].to_string(),
+            )
+        }
+        None => (registry, rest, "latest".to_string()),
+    }
+}
+
+/// Port placeholders only accept word characters
+fn port_name(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Escapes text for a TOML multi-line basic string
+fn escape_multiline(value: &str) -> String {
+    value.replace('\\', r"\\").replace("\"\"\"", "\"\"\\\"")
+}
+
+fn toml_string(value: &str) -> String {
+    toml::Value::String(value.to_string()).to_string()
+}
+
+struct ServiceTranslation<'a> {
+    machine_name: &'a str,
+    compose: &'a Mapping,
+    importable: &'a HashSet<String>,
+    name: String,
+    args: Vec<String>,
+    exposed_values: Vec<(String, String)>,
+    depends_on: Vec<String>,
+    networks: Vec<String>,
+    notes: Vec<String>,
+}
+
+impl ServiceTranslation<'_> {
+    fn note(&mut self, message: String) {
+        self.notes.push(format!("{}: {}", self.name, message));
+    }
+
+    fn add_port(&mut self, host_ip: Option<String>, container: &str, protocol: Option<String>) {
+        let base = port_name(&self.name);
+        let container_port = container.to_string();
+        let placeholder = if self.exposed_values.is_empty() {
+            base.clone()
+        } else {
+            format!("{}_{}", base, port_name(&container_port))
+        };
+        let value_name = if self.exposed_values.is_empty() {
+            format!("{}_PORT", base.to_uppercase())
+        } else {
+            format!(
+                "{}_PORT_{}",
+                base.to_uppercase(),
+                port_name(&container_port).to_uppercase()
+            )
+        };
+
+        let mut mapping = format!("${{port.{}}}:{}", placeholder, container_port);
+        if let Some(host_ip) = host_ip {
+            mapping = format!("{}:{}", host_ip, mapping);
+        }
+        if let Some(protocol) = protocol {
+            mapping = format!("{}/{}", mapping, protocol);
+        }
+
+        self.args.push(format!("-p {}", mapping));
+        self.exposed_values
+            .push((value_name, format!("${{port.{}}}", placeholder)));
+    }
+
+    fn ports(&mut self, value: &Value) {
+        let entries = match value.as_sequence() {
+            Some(entries) => entries.clone(),
+            None => return self.note("`ports` must be a list".to_string()),
+        };
+
+        for entry in entries {
+            if let Value::Mapping(long) = &entry {
+                let target = long.get("target").and_then(scalar);
+                match target {
+                    Some(target) => self.add_port(
+                        long.get("host_ip").and_then(scalar),
+                        &target,
+                        long.get("protocol").and_then(scalar),
+                    ),
+                    None => self.note("a port without `target` was skipped".to_string()),
+                }
+                continue;
+            }
+
+            let short = match scalar(&entry) {
+                Some(short) => short,
+                None => continue,
+            };
+            let (mapping, protocol) = match short.split_once('/') {
+                Some((mapping, protocol)) => (mapping.to_string(), Some(protocol.to_string())),
+                None => (short.clone(), None),
+            };
+            if mapping.contains('-') {
+                self.note(format!("the port range `{}` was not translated", short));
+                continue;
+            }
+
+            // The host port is replaced by a placeholder so wm can pick a free one
+            let parts: Vec<&str> = mapping.split(':').collect();
+            match parts.as_slice() {
+                [container] | [_, container] => self.add_port(None, container, protocol),
+                [host_ip, _, container] => {
+                    self.add_port(Some(host_ip.to_string()), container, protocol)
+                }
+                _ => self.note(format!("the port `{}` was not translated", short)),
+            }
+        }
+    }
+
+    fn volume_source(&self, source: &str) -> String {
+        if let Some(relative) = source.strip_prefix("./") {
+            format!("$(pwd)/{}", relative)
+        } else if source == "." {
+            "$(pwd)".to_string()
+        } else if let Some(home) = source.strip_prefix("~/") {
+            format!("$HOME/{}", home)
+        } else if source.starts_with('/') || source.starts_with("../") {
+            source.to_string()
+        } else {
+            // Named volumes are scoped to the machine, like compose scopes them to the project
+            let declaration = self
+                .compose
+                .get("volumes")
+                .and_then(|volumes| volumes.get(source));
+            let external = declaration
+                .and_then(|declaration| declaration.get("external"))
+                .and_then(|external| external.as_bool())
+                .unwrap_or(false);
+            match declaration
+                .and_then(|declaration| declaration.get("name"))
+                .and_then(scalar)
+            {
+                Some(name) => name,
+                None if external => source.to_string(),
+                None => format!("{}-{}", self.machine_name, source),
+            }
+        }
+    }
+
+    fn volumes(&mut self, value: &Value) {
+        let entries = match value.as_sequence() {
+            Some(entries) => entries.clone(),
+            None => return self.note("`volumes` must be a list".to_string()),
+        };
+
+        for entry in entries {
+            if let Value::Mapping(long) = &entry {
+                let volume_type = long.get("type").and_then(scalar).unwrap_or_default();
+                let target = match long.get("target").and_then(scalar) {
+                    Some(target) => target,
+                    None => {
+                        self.note("a volume without `target` was skipped".to_string());
+                        continue;
+                    }
+                };
+                if volume_type == "tmpfs" {
+                    self.args.push(format!("--tmpfs {}", quote_arg(&target)));
+                    continue;
+                }
+                let mut volume = match long.get("source").and_then(scalar) {
+                    Some(source) => format!("{}:{}", self.volume_source(&source), target),
+                    None => target,
+                };
+                if long
+                    .get("read_only")
+                    .and_then(|read_only| read_only.as_bool())
+                    .unwrap_or(false)
+                {
+                    volume.push_str(":ro");
+                }
+                self.args.push(format!("-v {}", quote_arg(&volume)));
+                continue;
+            }
+
+            let short = match scalar(&entry) {
+                Some(short) => short,
+                None => continue,
+            };
+            let volume = match short.split_once(':') {
+                Some((source, rest)) => format!("{}:{}", self.volume_source(source), rest),
+                None => short,
+            };
+            self.args.push(format!("-v {}", quote_arg(&volume)));
+        }
+    }
+
+    fn healthcheck(&mut self, value: &Value) {
+        let healthcheck = match value.as_mapping() {
+            Some(healthcheck) => healthcheck.clone(),
+            None => return self.note("`healthcheck` must be a mapping".to_string()),
+        };
+
+        for (key, value) in healthcheck.iter() {
+            let key = scalar(key).unwrap_or_default();
+            match key.as_str() {
+                "test" => {
+                    let test = match value {
+                        Value::Sequence(_) => {
+                            let parts = string_list(value);
+                            match parts.first().map(|part| part.as_str()) {
+                                Some("NONE") => {
+                                    self.args.push("--no-healthcheck".to_string());
+                                    continue;
+                                }
+                                Some("CMD-SHELL") => parts[1..].join(" "),
+                                Some("CMD") => parts[1..]
+                                    .iter()
+                                    .map(|part| quote_arg(part))
+                                    .collect::<Vec<String>>()
+                                    .join(" "),
+                                _ => parts.join(" "),
+                            }
+                        }
+                        other => scalar(other).unwrap_or_default(),
+                    };
+                    self.args.push(format!("--health-cmd {}", quote_arg(&test)));
+                }
+                "interval" | "timeout" | "retries" | "start_period" | "start_interval" => {
+                    if let Some(value) = scalar(value) {
+                        self.args
+                            .push(format!("--health-{}={}", key.replace('_', "-"), value));
+                    }
+                }
+                "disable" => {
+                    if value.as_bool().unwrap_or(false) {
+                        self.args.push("--no-healthcheck".to_string());
+                    }
+                }
+                _ => self.note(format!(
+                    "the healthcheck option `{}` was not translated",
+                    key
+                )),
+            }
+        }
+    }
+
+    /// Resolves the docker name of a network declared in the compose file
+    fn network_name(&self, network: &str) -> String {
+        let declaration = self
+            .compose
+            .get("networks")
+            .and_then(|networks| networks.get(network));
+        let external = declaration
+            .and_then(|declaration| declaration.get("external"))
+            .and_then(|external| external.as_bool())
+            .unwrap_or(false);
+
+        match declaration
+            .and_then(|declaration| declaration.get("name"))
+            .and_then(scalar)
+        {
+            Some(name) => name,
+            None if external => network.to_string(),
+            None => format!("{}_{}", self.machine_name, network),
+        }
+    }
+
+    fn networks(&mut self, service: &Mapping) {
+        if let Some(mode) = service.get("network_mode").and_then(scalar) {
+            match mode.strip_prefix("service:") {
+                Some(other) => self
+                    .args
+                    .push(format!("--network container:${{machine_name}}-{}", other)),
+                None => self.args.push(format!("--network {}", quote_arg(&mode))),
+            }
+            return;
+        }
+
+        let mut aliases = vec![self.name.clone()];
+        let networks: Vec<String> = match service.get("networks") {
+            Some(Value::Mapping(networks)) => networks
+                .iter()
+                .filter_map(|(name, options)| {
+                    aliases.extend(options.get("aliases").map(string_list).unwrap_or_default());
+                    scalar(name)
+                })
+                .collect(),
+            Some(other) => string_list(other),
+            // Compose puts services without networks on a default one
+            None => vec!["default".to_string()],
+        };
+
+        for network in networks {
+            let name = self.network_name(&network);
+            self.args.push(format!("--network {}", quote_arg(&name)));
+            self.networks.push(name);
+        }
+
+        // Keep the compose service names resolvable from the other containers
+        for alias in aliases {
+            self.args
+                .push(format!("--network-alias {}", quote_arg(&alias)));
+        }
+    }
+
+    fn depends_on(&mut self, value: &Value) {
+        let dependencies = match value {
+            Value::Mapping(dependencies) => {
+                let mut names = vec![];
+                for (name, options) in dependencies {
+                    let name = match scalar(name) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+                    let condition = options.get("condition").and_then(scalar);
+                    if let Some(condition) = condition.filter(|c| c != "service_started") {
+                        self.note(format!(
+                            "the `{}` condition on `{}` was not translated",
+                            condition, name
+                        ));
+                    }
+                    names.push(name);
+                }
+                names
+            }
+            other => string_list(other),
+        };
+
+        for dependency in dependencies {
+            if self.importable.contains(&dependency) {
+                self.depends_on.push(dependency);
+            } else {
+                self.note(format!(
+                    "the dependency on `{}` was dropped because it was skipped",
+                    dependency
+                ));
+            }
+        }
+    }
+
+    fn translate(&mut self, service: &Mapping) -> Option<String> {
+        let image = match service.get("image").and_then(scalar) {
+            Some(image) => image,
+            None => {
+                self.note("services without `image` are not supported, it was skipped".to_string());
+                return None;
+            }
+        };
+        let (registry, image_name, tag) = split_image(&image);
+        if image.contains('@') {
+            self.note(format!(
+                "the digest of `{}` was dropped, the `{}` tag is used instead",
+                image, tag
+            ));
+        }
+
+        for (key, _) in service.iter() {
+            let key = scalar(key).unwrap_or_default();
+            if key == "container_name" {
+                self.note(
+                    "`container_name` was replaced by ${machine_name}-${service.name}".to_string(),
+                );
+            } else if !SUPPORTED_SERVICE_KEYS.contains(&key.as_str()) && !key.starts_with("x-") {
+                self.note(format!("`{}` was not translated", key));
+            }
+        }
+
+        self.args
+            .push("--name ${machine_name}-${service.name}".to_string());
+
+        if let Some(ports) = service.get("ports") {
+            self.ports(ports);
+        }
+        if let Some(environment) = service.get("environment") {
+            for variable in key_value_list(environment, "=") {
+                self.args.push(format!("-e {}", quote_arg(&variable)));
+            }
+        }
+        if let Some(env_file) = service.get("env_file") {
+            let files: Vec<String> = match env_file {
+                Value::Sequence(files) => files
+                    .iter()
+                    .filter_map(|file| scalar(file).or_else(|| file.get("path").and_then(scalar)))
+                    .collect(),
+                other => string_list(other),
+            };
+            for file in files {
+                self.args.push(format!("--env-file {}", quote_arg(&file)));
+            }
+        }
+        if let Some(volumes) = service.get("volumes") {
+            self.volumes(volumes);
+        }
+        if let Some(healthcheck) = service.get("healthcheck") {
+            self.healthcheck(healthcheck);
+        }
+        self.networks(service);
+        if let Some(depends_on) = service.get("depends_on") {
+            self.depends_on(depends_on);
+        }
+
+        let flags = [
+            ("user", "--user"),
+            ("working_dir", "--workdir"),
+            ("hostname", "--hostname"),
+            ("restart", "--restart"),
+            ("shm_size", "--shm-size"),
+            ("platform", "--platform"),
+            ("mem_limit", "--memory"),
+            ("cpus", "--cpus"),
+        ];
+        for (key, flag) in flags {
+            if let Some(value) = service.get(key).and_then(scalar) {
+                self.args.push(format!("{} {}", flag, quote_arg(&value)));
+            }
+        }
+
+        let switches = [
+            ("privileged", "--privileged"),
+            ("init", "--init"),
+            ("tty", "--tty"),
+            ("stdin_open", "--interactive"),
+        ];
+        for (key, switch) in switches {
+            if service.get(key).and_then(|value| value.as_bool()) == Some(true) {
+                self.args.push(switch.to_string());
+            }
+        }
+
+        let lists = [
+            ("cap_add", "--cap-add"),
+            ("cap_drop", "--cap-drop"),
+            ("dns", "--dns"),
+            ("expose", "--expose"),
+        ];
+        for (key, flag) in lists {
+            for value in service.get(key).map(string_list).unwrap_or_default() {
+                self.args.push(format!("{} {}", flag, quote_arg(&value)));
+            }
+        }
+        for host in service
+            .get("extra_hosts")
+            .map(|hosts| key_value_list(hosts, ":"))
+            .unwrap_or_default()
+        {
+            self.args.push(format!("--add-host {}", quote_arg(&host)));
+        }
+        for label in service
+            .get("labels")
+            .map(|labels| key_value_list(labels, "="))
+            .unwrap_or_default()
+        {
+            self.args.push(format!("--label {}", quote_arg(&label)));
+        }
+
+        // The entrypoint flag only takes the executable, its arguments go before the command
+        let mut command_args: Vec<String> = vec![];
+        match service.get("entrypoint") {
+            Some(Value::Sequence(_)) => {
+                let entrypoint = string_list(&service["entrypoint"]);
+                if let Some((executable, args)) = entrypoint.split_first() {
+                    self.args
+                        .push(format!("--entrypoint {}", quote_arg(executable)));
+                    command_args.extend(args.iter().map(|arg| quote_arg(arg)));
+                }
+            }
+            Some(entrypoint) => {
+                if let Some(entrypoint) = scalar(entrypoint) {
+                    self.args
+                        .push(format!("--entrypoint {}", quote_arg(&entrypoint)));
+                }
+            }
+            None => {}
+        }
+        match service.get("command") {
+            Some(Value::Sequence(command)) => {
+                command_args.extend(command.iter().filter_map(scalar).map(|arg| quote_arg(&arg)))
+            }
+            Some(command) => command_args.extend(scalar(command)),
+            None => {}
+        }
+
+        self.args.push("${service.source.ref}".to_string());
+        if !command_args.is_empty() {
+            self.args.push(command_args.join(" "));
+        }
+
+        Some(self.render(&registry, &image_name, &tag))
+    }
+
+    fn render(&self, registry: &str, image: &str, tag: &str) -> String {
+        let mut rendered = format!("[[services]]\n  name = {}\n", toml_string(&self.name));
+
+        if !self.depends_on.is_empty() {
+            let depends_on: Vec<String> = self
+                .depends_on
+                .iter()
+                .map(|name| toml_string(name))
+                .collect();
+            rendered.push_str(&format!("  depends_on = [{}]\n", depends_on.join(", ")));
+        }
+
+        if !self.exposed_values.is_empty() {
+            rendered.push_str("  exposed_values = [\n");
+            for (name, value) in &self.exposed_values {
+                rendered.push_str(&format!(
+                    "    {{ name = {}, value = {} }},\n",
+                    toml_string(name),
+                    toml_string(value)
+                ));
+            }
+            rendered.push_str("  ]\n");
+        }
+
+        let lines: Vec<String> = std::iter::once("docker run -d".to_string())
+            .chain(self.args.iter().cloned())
+            .map(|line| format!("    {}", escape_multiline(&line)))
+            .collect();
+
+        rendered.push_str(&format!(
+            "\n  [services.source]\n    image    = {}\n    tag      = {}\n    registry = {}\n\n    start_command = \"\"\"\n{}\n    \"\"\"\n",
+            toml_string(image),
+            toml_string(tag),
+            toml_string(registry),
+            lines.join(" \\\n")
+        ));
+
+        rendered
+    }
+}
+
+/// Translates the services of a compose file into `[[services]]` entries
+pub fn translate_compose(
+    content: &str,
+    machine_name: &str,
+) -> Result<ComposeImport, Box<dyn Error>> {
+    let mut compose: Value = serde_yaml_ng::from_str(content)?;
+    compose.apply_merge()?;
+
+    let compose = match compose {
+        Value::Mapping(compose) => compose,
+        _ => return Err(Box::from("The compose file must be a mapping")),
+    };
+
+    let mut import = ComposeImport::default();
+
+    for (key, _) in compose.iter() {
+        let key = scalar(key).unwrap_or_default();
+        if !["services", "networks", "volumes", "version", "name"].contains(&key.as_str())
+            && !key.starts_with("x-")
+        {
+            import
+                .notes
+                .push(format!("The top level `{}` was not translated", key));
+        }
+    }
+
+    if let Some(Value::Mapping(networks)) = compose.get("networks") {
+        for (name, options) in networks {
+            let options = match options.as_mapping() {
+                Some(options) => options,
+                None => continue,
+            };
+            for (option, value) in options {
+                let option = scalar(option).unwrap_or_default();
+                let is_default_driver = option == "driver" && value.as_str() == Some("bridge");
+                if !is_default_driver && !["name", "external"].contains(&option.as_str()) {
+                    import.notes.push(format!(
+                        "The `{}` option of the `{}` network was not translated",
+                        option,
+                        scalar(name).unwrap_or_default()
+                    ));
+                }
+            }
+        }
+    }
+
+    let services = match compose.get("services") {
+        Some(Value::Mapping(services)) => services.clone(),
+        _ => return Err(Box::from("The compose file has no services")),
+    };
+
+    // Services without an image are skipped, so nothing can depend on them
+    let importable: HashSet<String> = services
+        .iter()
+        .filter(|(_, service)| service.get("image").is_some())
+        .filter_map(|(name, _)| scalar(name))
+        .collect();
+
+    for (name, service) in services.iter() {
+        let name = match scalar(name) {
+            Some(name) => name,
+            None => continue,
+        };
+        let service = match service.as_mapping() {
+            Some(service) => service,
+            None => {
+                import.notes.push(format!(
+                    "{}: the service must be a mapping, it was skipped",
+                    name
+                ));
+                continue;
+            }
+        };
+
+        let mut translation = ServiceTranslation {
+            machine_name,
+            compose: &compose,
+            importable: &importable,
+            name: name.clone(),
+            args: vec![],
+            exposed_values: vec![],
+            depends_on: vec![],
+            networks: vec![],
+            notes: vec![],
+        };
+
+        if let Some(rendered) = translation.translate(service) {
+            import.services.push(rendered);
+            import.service_names.push(name);
+            for network in translation.networks {
+                if !import.networks.contains(&network) {
+                    import.networks.push(network);
+                }
+            }
+        }
+        import.notes.extend(translation.notes);
+    }
+
+    Ok(import)
+}
+
+fn add_to_array(doc: &mut DocumentMut, key: &str, values: &[String]) {
+    if values.is_empty() {
+        return;
+    }
+    if doc.get(key).and_then(|item| item.as_array()).is_none() {
+        doc[key] = toml_edit::value(toml_edit::Array::new());
+    }
+    if let Some(array) = doc[key].as_array_mut() {
+        for value in values {
+            if !array
+                .iter()
+                .any(|existing| existing.as_str() == Some(value))
+            {
+                array.push(value.as_str());
+            }
+        }
+    }
+}
+
+/// Adds the services of a compose file to the configuration file, creating it if needed. The
+/// file is only written when the result is valid, unless `force` is set.
+pub async fn import_compose(config_path: PathBuf, compose_path: PathBuf, force: bool) {
+    let compose_content = match fs::read_to_string(&compose_path).await {
+        Ok(content) => content,
+        Err(e) => {
+            logging::error(&format!("Failed to read {}: {}", compose_path.display(), e)).await;
+            std::process::exit(1);
+        }
+    };
+
+    let existing_config = fs::read_to_string(&config_path).await.ok();
+    let mut doc = match &existing_config {
+        Some(content) => match content.parse::<DocumentMut>() {
+            Ok(doc) => Some(doc),
+            Err(e) => {
+                logging::error(&format!("Failed to parse {}: {}", config_path.display(), e)).await;
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let machine_name = match &doc {
+        Some(doc) => doc
+            .get("machine_name")
+            .and_then(|name| name.as_str())
+            .unwrap_or_default()
+            .to_string(),
+        None => init::detect(&std::env::current_dir().unwrap(), &[]).machine_name,
+    };
+
+    let mut import = match translate_compose(&compose_content, &machine_name) {
+        Ok(import) => import,
+        Err(e) => {
+            logging::error(&format!(
+                "Failed to translate {}: {}",
+                compose_path.display(),
+                e
+            ))
+            .await;
+            std::process::exit(1);
+        }
+    };
+
+    let mut imported = import.service_names.len();
+    let content = match doc.as_mut() {
+        Some(doc) => {
+            let existing_names: HashSet<String> = doc
+                .get("services")
+                .and_then(|services| services.as_array_of_tables())
+                .map(|services| {
+                    services
+                        .iter()
+                        .filter_map(|service| service.get("name").and_then(Item::as_str))
+                        .map(|name| name.to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let mut services = vec![];
+            for (name, service) in import.service_names.iter().zip(&import.services) {
+                if existing_names.contains(name) {
+                    import.notes.push(format!(
+                        "{}: a service with this name already exists, it was skipped",
+                        name
+                    ));
+                    imported -= 1;
+                } else {
+                    services.push(service.clone());
+                }
+            }
+
+            add_to_array(doc, "requirements", &["docker".to_string()]);
+            add_to_array(doc, "networks", &import.networks);
+
+            let mut content = doc.to_string().trim_end().to_string();
+            for service in services {
+                content.push_str("\n\n");
+                content.push_str(&service);
+            }
+            content
+        }
+        None => {
+            let project = init::Project {
+                machine_name: machine_name.clone(),
+                requirements: vec!["docker".to_string()],
+                networks: import.networks.clone(),
+                services: import.services.clone(),
+                ..Default::default()
+            };
+            init::render(&project)
+        }
+    };
+
+    for note in &import.notes {
+        logging::warn(note).await;
+    }
+
+    let diagnostics = validation::validate(&content);
+    if !diagnostics.is_empty() {
+        crate::library::system::config::print_diagnostics(&config_path, &content, &diagnostics)
+            .await;
+    }
+
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    if has_errors && !force {
+        logging::error(&format!(
+            "🛑 Not writing {}, the imported configuration has errors. Fix them in the compose file or pass --force to write it anyway",
+            config_path.display()
+        ))
+        .await;
+        std::process::exit(1);
+    }
+
+    if let Err(e) = fs::write(&config_path, content).await {
+        logging::error(&format!("Failed to write {}: {}", config_path.display(), e)).await;
+        std::process::exit(1);
+    }
+
+    logging::info(&format!(
+        "✅ Imported {} services from {} into {}",
+        imported,
+        compose_path.display(),
+        config_path.display()
+    ))
+    .await;
+}
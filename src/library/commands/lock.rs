@@ -0,0 +1,156 @@
+use std::path::Path;
+
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::{
+    library::{
+        system::{config as config_file, docker},
+        utils::logging,
+    },
+    models::{
+        config::{Config, Source},
+        lockfile::LockedImage,
+    },
+};
+
+use super::dry_run;
+
+/// Locks the images of the container services to the digests their tags point to. Services
+/// that are already locked keep their digest unless `update` is set. Built images are local
+/// and aren't locked. With `dry_run`, the images that would be resolved are printed instead.
+pub async fn lock(
+    config: &Config,
+    config_path: &Path,
+    service_name: Option<&str>,
+    update: bool,
+    dry_run: bool,
+) {
+    if let Some(service_name) = service_name {
+        if !config.services.iter().any(|s| s.name == service_name) {
+            logging::error(&format!("Service {} not found", service_name)).await;
+            std::process::exit(1);
+        }
+    }
+
+    let mut lockfile = config.lock.clone();
+
+    // Services that were removed, or that no longer pull an image, leave the lockfile
+    lockfile.images.retain(|name, _| {
+        config.services.iter().any(|service| {
+            service.name == *name
+                && matches!(&service.source, Source::Container(source) if source.build.is_none())
+        })
+    });
+
+    let mut tasks = FuturesUnordered::new();
+    let mut steps = vec![];
+    for service in &config.services {
+        if service_name.is_some_and(|name| name != service.name) {
+            continue;
+        }
+
+        let source = match &service.source {
+            Source::Container(source) if source.build.is_none() => source,
+            _ => {
+                if service_name.is_some() {
+                    logging::error(&format!(
+                        "{} doesn't pull an image, there is nothing to lock",
+                        service.name
+                    ))
+                    .await;
+                    std::process::exit(1);
+                }
+                continue;
+            }
+        };
+
+        if !update && docker::locked_digest(config, &service.name, source).is_some() {
+            continue;
+        }
+
+        if dry_run {
+            steps.push(format!(
+                "docker pull {}  # to lock {}",
+                docker::tagged_image(source),
+                service.name
+            ));
+            continue;
+        }
+
+        tasks.push(async move {
+            let result = docker::resolve_digest(source).await;
+            (service, source, result)
+        });
+    }
+
+    if dry_run {
+        if !steps.is_empty() || lockfile != config.lock {
+            steps.push(format!(
+                "# write {}",
+                config_file::lock_path(config_path).display()
+            ));
+        }
+        dry_run::print("lock", &[("Images".to_string(), steps)]).await;
+        return;
+    }
+
+    if tasks.is_empty() {
+        logging::info("✅ Every image is locked, use --update to refresh them").await;
+    }
+
+    let mut failed_services = vec![];
+    while let Some((service, source, result)) = tasks.next().await {
+        match result {
+            Ok(digest) => {
+                logging::info(&format!(
+                    "🔒 Locked {} to {}",
+                    docker::tagged_image(source),
+                    digest
+                ))
+                .await;
+                lockfile.images.insert(
+                    service.name.clone(),
+                    LockedImage {
+                        image: docker::tagged_image(source),
+                        digest,
+                    },
+                );
+            }
+            Err(e) => {
+                logging::error(&format!(
+                    "🛑 Failed to lock {}: {}",
+                    service.name,
+                    e.to_string().trim()
+                ))
+                .await;
+                failed_services.push(service.name.as_str());
+            }
+        }
+    }
+
+    if lockfile != config.lock {
+        if let Err(e) = config_file::write_lock(config_path, &lockfile).await {
+            logging::error(&format!(
+                "🛑 Failed to write {}: {}",
+                config_file::lock_path(config_path).display(),
+                e
+            ))
+            .await;
+            std::process::exit(1);
+        }
+        logging::info(&format!(
+            "✅ Wrote {}",
+            config_file::lock_path(config_path).display()
+        ))
+        .await;
+    }
+
+    if !failed_services.is_empty() {
+        logging::error(&format!(
+            "🛑 Failed to lock: {}",
+            failed_services.join(", ")
+        ))
+        .await;
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,125 @@
+use std::process::Stdio;
+
+use futures::future::join_all;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+use crate::{
+    library::{config::services, utils::logging},
+    models::config::{Config, Service},
+};
+
+const PREFIX_COLORS: [&str; 6] = [
+    logging::CYAN,
+    logging::MAGENTA,
+    logging::YELLOW,
+    logging::BLUE,
+    logging::GREEN,
+    logging::RED,
+];
+
+async fn print_prefixed_lines(reader: impl AsyncRead + Unpin, prefix: String) {
+    let mut lines = BufReader::new(reader).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        logging::print_color(logging::NC, &format!("{}{}", prefix, line)).await;
+    }
+}
+
+/// Runs the logs command with its output prefixed by the colored service name
+async fn stream_prefixed(mut command: Command, prefix: String) {
+    let child = command
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            logging::error(&format!("Failed to read logs: {}", e)).await;
+            return;
+        }
+    };
+
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+
+    tokio::join!(
+        print_prefixed_lines(stdout, prefix.clone()),
+        print_prefixed_lines(stderr, prefix),
+    );
+
+    child.wait().await.ok();
+}
+
+async fn stream_service(
+    config: &Config,
+    service: &Service,
+    follow: bool,
+    since: Option<&str>,
+    tail: Option<&str>,
+    prefix: String,
+) {
+    match services::logs_command(config, service, follow, since, tail).await {
+        Ok(command) => stream_prefixed(command, prefix).await,
+        Err(e) => logging::warn(&format!("{}{}", prefix, e)).await,
+    }
+}
+
+/// Shows the logs of a single service, or of all of them interleaved when no service is given
+pub async fn logs(
+    config: &Config,
+    service_name: Option<String>,
+    follow: bool,
+    since: Option<&str>,
+    tail: Option<&str>,
+) {
+    if let Some(service_name) = service_name {
+        let service = match config.services.iter().find(|s| s.name == service_name) {
+            Some(service) => service,
+            None => {
+                logging::error(&format!("Service {} not found", service_name)).await;
+                std::process::exit(1);
+            }
+        };
+
+        let mut command = match services::logs_command(config, service, follow, since, tail).await {
+            Ok(command) => command,
+            Err(e) => {
+                logging::error(&e.to_string()).await;
+                std::process::exit(1);
+            }
+        };
+
+        if let Err(e) = command.status().await {
+            logging::error(&format!(
+                "Failed to read the logs of {}: {}",
+                service_name, e
+            ))
+            .await;
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let longest_name_len = config
+        .services
+        .iter()
+        .map(|service| service.name.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut tasks = vec![];
+    for (i, service) in config.services.iter().enumerate() {
+        let prefix = format!(
+            "{}{:<width$} |{} ",
+            PREFIX_COLORS[i % PREFIX_COLORS.len()],
+            service.name,
+            logging::NC,
+            width = longest_name_len
+        );
+        tasks.push(stream_service(config, service, follow, since, tail, prefix));
+    }
+    join_all(tasks).await;
+}
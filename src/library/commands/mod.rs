@@ -1,5 +1,32 @@
+pub mod build;
+pub mod dry_run;
+pub mod env;
+pub mod exec;
+pub mod export;
+pub mod graph;
+pub mod import;
+pub mod init;
+pub mod lock;
+pub mod logs;
+pub mod ports;
 pub mod prepare;
+pub mod pull;
 pub mod run;
+pub mod shell;
+pub mod status;
 
+pub use build::build;
+pub use env::env;
+pub use exec::exec;
+pub use export::export;
+pub use graph::graph;
+pub use import::import_compose;
+pub use init::init;
+pub use lock::lock;
+pub use logs::logs;
+pub use ports::{all_ports, ports};
 pub use prepare::prepare;
+pub use pull::pull;
 pub use run::run;
+pub use shell::shell;
+pub use status::status;
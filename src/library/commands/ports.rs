@@ -0,0 +1,60 @@
+use crate::{
+    library::{
+        machine::{self, registry},
+        utils::logging,
+    },
+    models::{config::Config, port_registry::PortRegistry},
+};
+
+async fn print_registry_box(port_registry: &PortRegistry, project_path: &str) {
+    let header: Vec<String> = ["Port", "Name", "Machine", "Project"]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+    let rows: Vec<Vec<String>> = port_registry
+        .ports
+        .iter()
+        .map(|port| {
+            // Mark the ports of the current project
+            let project = if port.project_path == project_path {
+                format!("{} (current)", port.project_path)
+            } else {
+                port.project_path.clone()
+            };
+            vec![
+                port.port.to_string(),
+                port.name.clone(),
+                port.machine_name.clone(),
+                project,
+            ]
+        })
+        .collect();
+
+    logging::print_table(logging::BG_MAGENTA, " Port registry ", &header, &rows).await;
+}
+
+/// Prints the ports allocated for the current project
+pub async fn ports(config: &Config) {
+    let machine_state = machine::state::get_machine_state().await;
+    if machine_state.ports.is_empty() {
+        logging::info("No ports have been allocated for this project yet").await;
+    } else {
+        machine::ports::print_port_map_box(&machine_state, config).await;
+    }
+}
+
+/// Prints the ports of every project on this machine
+pub async fn all_ports() {
+    match registry::load() {
+        Ok(port_registry) if port_registry.ports.is_empty() => {
+            logging::info("No ports have been allocated by any project yet").await;
+        }
+        Ok(port_registry) => {
+            print_registry_box(&port_registry, &registry::project_path()).await;
+        }
+        Err(e) => {
+            logging::error(&format!("🛑 Failed to read the port registry: {}", e)).await;
+            std::process::exit(1);
+        }
+    }
+}
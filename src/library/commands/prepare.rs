@@ -38,6 +38,7 @@ async fn prepare_features(
 
 pub async fn get_exposed_variables(
     machine_state: &MachineState,
+    service_name: &str,
     exposed_values: &Vec<ExposedValueType>,
     available_before_start: bool,
 ) -> Vec<(String, String)> {
@@ -47,16 +48,30 @@ pub async fn get_exposed_variables(
         match exposed_value {
             ExposedValueType::Literal(exposed_value) => {
                 if available_before_start == exposed_value.available_before_start {
+                    let value = machine::ports::replace_service_ports_in_text(
+                        machine_state,
+                        service_name,
+                        &exposed_value.value,
+                    )
+                    .await;
                     env_vars_to_return.push((
                         exposed_value.name.trim().to_uppercase(),
-                        machine::ports::replace_ports_in_text(machine_state, &exposed_value.value)
-                            .await,
+                        machine::ports::replace_ports_in_text(machine_state, &value).await,
                     ));
                 }
             }
             ExposedValueType::Command(exposed_value) => {
                 if available_before_start == exposed_value.available_before_start {
-                    let command_result = command::run(&exposed_value.command).await.unwrap();
+                    let exposed_command = machine::ports::replace_service_ports_in_text(
+                        machine_state,
+                        service_name,
+                        &exposed_value.command,
+                    )
+                    .await;
+                    let exposed_command =
+                        machine::ports::replace_ports_in_text(machine_state, &exposed_command)
+                            .await;
+                    let command_result = command::run(&exposed_command).await.unwrap();
 
                     // Each line should be in the format KEY=VALUE and represent one exposed value.
                     for line in command_result.split('\n') {
@@ -107,6 +122,8 @@ pub async fn get_exposed_variables(
 /// - Set the environment variables
 /// - Create docker networks
 /// - Start local instances
+///
+/// Returns the environment variables that were gathered as `(key, value, source)`
 pub async fn prepare(
     machine_state: &MachineState,
     config: &Config,
@@ -114,7 +131,7 @@ pub async fn prepare(
     no_services: bool,
     no_features: bool,
     clean_mode: bool,
-) {
+) -> Vec<(String, String, String)> {
     let vars_iter = env::vars();
 
     let mut original_env_vars: HashMap<String, String> = HashMap::new();
@@ -142,7 +159,8 @@ pub async fn prepare(
         // Set the available_before_start variables
         for service in &config.services {
             let exposed_values =
-                get_exposed_variables(&machine_state, &service.exposed_values, true).await;
+                get_exposed_variables(machine_state, &service.name, &service.exposed_values, true)
+                    .await;
 
             for (key, value) in exposed_values {
                 env_vars.push((key, value, "war machine".to_string()));
@@ -167,7 +185,8 @@ pub async fn prepare(
         logging::nl().await;
         logging::info("Starting local services").await;
 
-        services::start_all(machine_state, config, clean_mode, true).await;
+        let started_env_vars = services::start_all(machine_state, config, clean_mode, true).await;
+        env_vars.extend(started_env_vars);
 
         // Logout from all registries
         for registry in &config.registry_credentials {
@@ -185,4 +204,6 @@ pub async fn prepare(
     env_vars::set(&env_vars).await;
 
     env_vars::print_variables_box(original_env_vars, &env_vars).await;
+
+    env_vars
 }
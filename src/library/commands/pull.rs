@@ -0,0 +1,126 @@
+use futures::stream::{FuturesUnordered, StreamExt};
+
+use crate::{
+    library::{system::docker, utils::logging},
+    models::{
+        config::{Config, PullPolicy, Source},
+        pull_error::{PullError, PullErrorKind},
+    },
+};
+
+use super::dry_run;
+
+/// Pulls the images of the container services in parallel, whatever their pull policy is,
+/// or only the image of the given service. Built images and `never` services are skipped.
+/// With `dry_run`, the `docker pull` commands are printed instead.
+pub async fn pull(config: &Config, service_name: Option<&str>, dry_run: bool) {
+    if let Some(service_name) = service_name {
+        if !config.services.iter().any(|s| s.name == service_name) {
+            logging::error(&format!("Service {} not found", service_name)).await;
+            std::process::exit(1);
+        }
+    }
+
+    // Services that share an image only pull it once
+    let mut images: Vec<(String, Vec<&str>)> = vec![];
+    for service in &config.services {
+        if service_name.is_some_and(|name| name != service.name) {
+            continue;
+        }
+
+        let source = match &service.source {
+            Source::Container(source) => source,
+            Source::App(_) => continue,
+        };
+        if source.build.is_some() {
+            logging::info(&format!(
+                "Skipping {}, its image is built with `wm build`",
+                service.name
+            ))
+            .await;
+            continue;
+        }
+        if docker::pull_policy(config, source) == PullPolicy::Never {
+            logging::info(&format!(
+                "Skipping {}, its pull policy is `never`",
+                service.name
+            ))
+            .await;
+            continue;
+        }
+
+        let image = docker::image_ref(config, &service.name, source);
+        match images.iter_mut().find(|(existing, _)| *existing == image) {
+            Some((_, services)) => services.push(&service.name),
+            None => images.push((image, vec![&service.name])),
+        }
+    }
+
+    if images.is_empty() {
+        logging::warn("There are no images to pull").await;
+        return;
+    }
+
+    if dry_run {
+        let steps = images
+            .iter()
+            .map(|(image, services)| {
+                format!("docker pull {}  # for {}", image, services.join(", "))
+            })
+            .collect();
+        dry_run::print("pull", &[("Images".to_string(), steps)]).await;
+        return;
+    }
+
+    let total = images.len();
+    logging::info(&format!("⬇️  Pulling {} images", total)).await;
+
+    let mut tasks: FuturesUnordered<_> = images
+        .into_iter()
+        .map(|(image, services)| async move {
+            let result = docker::pull_image(&image, true).await;
+            (image, services, result)
+        })
+        .collect();
+
+    let mut done = 0;
+    let mut errors: Vec<PullError> = vec![];
+    while let Some((image, services, result)) = tasks.next().await {
+        done += 1;
+        match result {
+            Ok(_) => {
+                logging::info(&format!(
+                    "✅ [{}/{}] Pulled {} for {}",
+                    done,
+                    total,
+                    image,
+                    services.join(", ")
+                ))
+                .await;
+            }
+            Err(e) => {
+                let error = PullError {
+                    service: services.join(", "),
+                    image,
+                    kind: PullErrorKind::Failed(e.to_string()),
+                };
+                logging::error(&format!("🛑 [{}/{}] {}", done, total, error)).await;
+                errors.push(error);
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        logging::info(&format!("✅ Pulled {} images", total)).await;
+    } else {
+        let images: Vec<&str> = errors.iter().map(|error| error.image.as_str()).collect();
+        logging::error(&format!(
+            "🛑 Failed to pull {} of {} images: {}",
+            errors.len(),
+            total,
+            images.join(", ")
+        ))
+        .await;
+        std::process::exit(1);
+    }
+}
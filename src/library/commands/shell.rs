@@ -0,0 +1,148 @@
+use std::{env, path::PathBuf};
+
+use tokio::{fs, process::Command};
+
+use crate::{
+    library::{config::services, utils::logging},
+    models::{config::Config, machine_state::MachineState},
+};
+
+use super::prepare;
+
+/// Writes a shell rc file under `.war_machine`, warning instead of failing
+async fn write_rc_file(path: &PathBuf, content: String) -> bool {
+    if let Some(parent) = path.parent() {
+        if let Err(e) = fs::create_dir_all(parent).await {
+            logging::warn(&format!("Failed to write the shell rc file: {}", e)).await;
+            return false;
+        }
+    }
+    match fs::write(path, content).await {
+        Ok(_) => true,
+        Err(e) => {
+            logging::warn(&format!("Failed to write the shell rc file: {}", e)).await;
+            false
+        }
+    }
+}
+
+/// Builds the command for the user's shell with a prompt marker showing the machine name
+async fn build_shell_command(config: &Config) -> Command {
+    let shell = env::var("SHELL").unwrap_or("sh".to_string());
+    let marker = format!("(wm:{})", config.machine_name);
+
+    let mut command = Command::new(&shell);
+    command.env("WM_MACHINE", &config.machine_name);
+
+    if shell.ends_with("bash") {
+        // bash resets PS1 from the user's bashrc, so we load it ourselves and prefix it after
+        let rc_path = PathBuf::from(".war_machine/bashrc");
+        let rc_content = format!("[ -f ~/.bashrc ] && . ~/.bashrc\nPS1=\"{} $PS1\"\n", marker);
+        if write_rc_file(&rc_path, rc_content).await {
+            command.arg("--rcfile").arg(rc_path);
+        }
+    } else if shell.ends_with("zsh") {
+        // zsh has no --rcfile, it reads its rc files from ZDOTDIR. We point it to a directory
+        // whose files load the user's own ones and then prefix the prompt
+        let zdotdir = PathBuf::from(".war_machine/zsh");
+        let user_zdotdir = env::var("ZDOTDIR")
+            .or_else(|_| env::var("HOME"))
+            .unwrap_or_default();
+        let zshenv = "_wm_zdotdir=\"$ZDOTDIR\"\n\
+                      ZDOTDIR=\"$WM_ZDOTDIR\"\n\
+                      [ -f \"$ZDOTDIR/.zshenv\" ] && . \"$ZDOTDIR/.zshenv\"\n\
+                      WM_ZDOTDIR=\"$ZDOTDIR\"\n\
+                      ZDOTDIR=\"$_wm_zdotdir\"\n\
+                      unset _wm_zdotdir\n"
+            .to_string();
+        let zshrc = format!(
+            "ZDOTDIR=\"$WM_ZDOTDIR\"\n\
+             unset WM_ZDOTDIR\n\
+             [ -f \"$ZDOTDIR/.zshrc\" ] && . \"$ZDOTDIR/.zshrc\"\n\
+             PROMPT=\"{} $PROMPT\"\n",
+            marker
+        );
+        if write_rc_file(&zdotdir.join(".zshenv"), zshenv).await
+            && write_rc_file(&zdotdir.join(".zshrc"), zshrc).await
+        {
+            match zdotdir.canonicalize() {
+                Ok(zdotdir) => {
+                    command.env("WM_ZDOTDIR", user_zdotdir);
+                    command.env("ZDOTDIR", zdotdir);
+                }
+                Err(e) => {
+                    logging::warn(&format!("Failed to write the shell rc file: {}", e)).await;
+                }
+            }
+        }
+    } else if shell.ends_with("fish") {
+        // fish builds its prompt from the fish_prompt function, so we wrap the user's one once
+        // their config has been read. It runs first so it still sees the last command's status
+        command.arg("--init-command").arg(format!(
+            "functions -q fish_prompt; and functions -c fish_prompt _wm_fish_prompt\n\
+             function fish_prompt\n\
+             set -l prompt (_wm_fish_prompt 2>/dev/null | string collect -N)\n\
+             printf '%s %s' '{}' \"$prompt\"\n\
+             end",
+            marker
+        ));
+    } else {
+        let prompt = env::var("PS1").unwrap_or("$ ".to_string());
+        command.env("PS1", format!("{} {}", marker, prompt));
+    }
+
+    command
+}
+
+/// Prepares the machine like `run` does and then drops the user into their shell with the
+/// resulting environment
+pub async fn shell(
+    machine_state: MachineState,
+    config: Config,
+    secrets: serde_json::Value,
+    no_services: bool,
+    no_features: bool,
+    clean_mode: bool,
+    stop_on_exit: bool,
+) {
+    prepare(
+        &machine_state,
+        &config,
+        &secrets,
+        no_services,
+        no_features,
+        clean_mode,
+    )
+    .await;
+
+    let mut command = build_shell_command(&config).await;
+
+    // Ctrl+C is meant for the commands run in the shell, war machine should keep waiting
+    tokio::spawn(async move {
+        loop {
+            tokio::signal::ctrl_c().await.unwrap();
+        }
+    });
+
+    logging::nl().await;
+    logging::print_color(logging::BG_GREEN, " Starting shell ").await;
+    logging::info("Exit the shell to return").await;
+
+    let status = command.status().await;
+
+    let code = match status {
+        Ok(status) => status.code().unwrap_or(0),
+        Err(e) => {
+            logging::error(&format!("🛑 Failed to start shell: {}", e)).await;
+            1
+        }
+    };
+
+    if stop_on_exit && !no_services {
+        logging::nl().await;
+        logging::info("🛑 Stopping services").await;
+        services::stop_all(&machine_state, &config, false).await;
+    }
+
+    std::process::exit(code);
+}
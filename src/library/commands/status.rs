@@ -0,0 +1,78 @@
+use futures::future::join_all;
+
+use crate::{
+    library::{config::services, utils::logging},
+    models::{config::Config, machine_state::MachineState, service_status::ServiceStatus},
+};
+
+fn status_row(service_status: &ServiceStatus) -> Vec<String> {
+    let id = if let Some(container_id) = &service_status.container_id {
+        container_id.clone()
+    } else if let Some(pid) = service_status.pid {
+        pid.to_string()
+    } else {
+        "-".to_string()
+    };
+
+    let mut ports: Vec<_> = service_status.ports.iter().collect();
+    ports.sort_by_key(|(name, _)| *name);
+    let ports = ports
+        .iter()
+        .map(|(name, port)| format!("{}={}", name, port))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    vec![
+        service_status.name.clone(),
+        if service_status.running {
+            "running".to_string()
+        } else {
+            "stopped".to_string()
+        },
+        service_status.health.clone().unwrap_or("-".to_string()),
+        id,
+        service_status.image.clone().unwrap_or("-".to_string()),
+        if ports.is_empty() {
+            "-".to_string()
+        } else {
+            ports
+        },
+        service_status.uptime.clone().unwrap_or("-".to_string()),
+    ]
+}
+
+async fn print_status_box(statuses: &[ServiceStatus]) {
+    let header: Vec<String> = [
+        "Service", "Status", "Health", "ID", "Image", "Ports", "Uptime",
+    ]
+    .iter()
+    .map(|column| column.to_string())
+    .collect();
+    let rows: Vec<Vec<String>> = statuses.iter().map(status_row).collect();
+
+    logging::print_table(logging::BG_BLUE, " Services ", &header, &rows).await;
+}
+
+/// Prints the status of every service in the configuration
+pub async fn status(machine_state: &MachineState, config: &Config, json_output: bool) {
+    let mut tasks = vec![];
+    for service in &config.services {
+        tasks.push(services::status(machine_state, config, service));
+    }
+    let statuses = join_all(tasks).await;
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&statuses).unwrap());
+    } else {
+        for service_status in &statuses {
+            if let Some(error) = &service_status.error {
+                logging::error(&format!(
+                    "Failed to get the status of {}: {}",
+                    service_status.name, error
+                ))
+                .await;
+            }
+        }
+        print_status_box(&statuses).await;
+    }
+}
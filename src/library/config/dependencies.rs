@@ -1,30 +1,111 @@
-use crate::{library::utils::logging, models::config::Config};
+use std::collections::HashSet;
 
+use crate::{
+    library::utils::logging,
+    models::config::{Config, Service},
+};
+
+/// Groups the services by how deep they are in the dependency graph. A service joins the
+/// first wave after all of its dependencies, which is the earliest `services::start_all`
+/// can start it. Services that can never start because of circular or missing
+/// dependencies are returned separately.
+pub fn startup_waves(config: &Config) -> (Vec<Vec<&Service>>, Vec<&Service>) {
+    let mut waves: Vec<Vec<&Service>> = vec![];
+    let mut leftover_services: Vec<&Service> = config.services.iter().collect();
+
+    while !leftover_services.is_empty() {
+        let (wave, rest): (Vec<&Service>, Vec<&Service>) =
+            leftover_services.iter().partition(|service| {
+                service.depends_on.iter().all(|dependency| {
+                    waves
+                        .iter()
+                        .flatten()
+                        .any(|started| &started.name == dependency)
+                })
+            });
+
+        if wave.is_empty() {
+            break;
+        }
+
+        waves.push(wave);
+        leftover_services = rest;
+    }
+
+    (waves, leftover_services)
+}
+
+/// Walks the dependencies of a service depth first and returns the first cycle it finds,
+/// as the path of service names that leads back to where it started
+fn find_cycle_from<'a>(
+    config: &'a Config,
+    service: &'a Service,
+    path: &mut Vec<&'a str>,
+    visited: &mut HashSet<&'a str>,
+) -> Option<Vec<String>> {
+    if let Some(start) = path.iter().position(|name| *name == service.name) {
+        let mut cycle: Vec<String> = path[start..].iter().map(|name| name.to_string()).collect();
+        cycle.push(service.name.clone());
+        return Some(cycle);
+    }
+
+    // Everything reachable from a visited service has already been checked
+    if !visited.insert(&service.name) {
+        return None;
+    }
+
+    path.push(&service.name);
+    for dependency in &service.depends_on {
+        if let Some(dependency_service) = config.services.iter().find(|s| &s.name == dependency) {
+            if let Some(cycle) = find_cycle_from(config, dependency_service, path, visited) {
+                return Some(cycle);
+            }
+        }
+    }
+    path.pop();
+
+    None
+}
+
+/// Returns the circular dependencies, each as a path like `a -> b -> c -> a`
+pub fn find_cycles(config: &Config) -> Vec<Vec<String>> {
+    let mut visited = HashSet::new();
+    let mut cycles = vec![];
+    for service in &config.services {
+        if let Some(cycle) = find_cycle_from(config, service, &mut vec![], &mut visited) {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// Makes sure every dependency exists and that there are no circular dependencies
 pub async fn check(config: &Config) {
+    let mut has_errors = false;
+
     for service in &config.services {
         for dependency in &service.depends_on {
-            // Find the services this depends on
-            let dependency_service = config.services.iter().find(|s| &s.name == dependency);
-
-            match dependency_service {
-                Some(dependency_service) => {
-                    // Check that this service is not in the dependency_service's depends_on
-                    if dependency_service.depends_on.contains(&service.name) {
-                        logging::error(&format!(
-                            "Circular dependency detected: {} -> {}",
-                            service.name, dependency
-                        ))
-                        .await;
-                    }
-                }
-                None => {
-                    logging::error(&format!(
-                        "Dependency {} not found for service {}",
-                        dependency, service.name
-                    ))
-                    .await;
-                }
+            if !config.services.iter().any(|s| &s.name == dependency) {
+                logging::error(&format!(
+                    "Dependency {} not found for service {}",
+                    dependency, service.name
+                ))
+                .await;
+                has_errors = true;
             }
         }
     }
+
+    for cycle in find_cycles(config) {
+        logging::error(&format!(
+            "Circular dependency detected: {}",
+            cycle.join(" -> ")
+        ))
+        .await;
+        has_errors = true;
+    }
+
+    if has_errors {
+        std::process::exit(1);
+    }
 }
@@ -3,3 +3,4 @@ pub mod dependencies;
 pub mod features;
 pub mod requirements;
 pub mod services;
+pub mod validation;
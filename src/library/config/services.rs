@@ -1,12 +1,20 @@
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::error::Error;
+use std::process::ExitStatus;
+use std::time::{Duration, Instant};
 
 use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
 
 use crate::library::commands::prepare::get_exposed_variables;
-use crate::library::system::{custom_app, docker};
-use crate::library::utils::env_vars;
-use crate::models::config::{Config, Service, Source};
-use crate::models::machine_state::MachineState;
+use crate::library::machine;
+use crate::library::system::{command, custom_app, docker, probe};
+use crate::library::utils::{env_vars, logging};
+use crate::models::config::{Config, ReadyProbe, Service, Source};
+use crate::models::machine_state::{MachineState, ServiceDefinition};
+use crate::models::service_status::ServiceStatus;
 
 pub async fn clean(
     machine_state: &MachineState,
@@ -26,7 +34,7 @@ pub async fn clean(
 
     // Set the available_before_start=false variables
     let exposed_values =
-        get_exposed_variables(&machine_state, &service.exposed_values, false).await;
+        get_exposed_variables(machine_state, &service.name, &service.exposed_values, false).await;
 
     let mut env_vars = vec![];
     for (key, value) in exposed_values {
@@ -36,41 +44,326 @@ pub async fn clean(
     env_vars::set(&env_vars).await;
 }
 
-pub async fn start(
+/// How often to check whether a service became healthy
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Checks the health of the service once. Returns `None` when it is healthy, the current
+/// status when it may still become healthy, and an error when it never will.
+async fn check_health(
     machine_state: &MachineState,
     config: &Config,
     service: &Service,
-    clean_mode: bool,
-    fail_fast: bool,
-) {
+) -> Result<Option<String>, Box<dyn Error>> {
+    let health_check_command = match &service.source {
+        Source::Container(container_source) => match &container_source.health_check_command {
+            Some(health_check_command) => Some(
+                docker::replace_placeholders(
+                    machine_state,
+                    config,
+                    health_check_command,
+                    &service.name,
+                    container_source,
+                )
+                .await,
+            ),
+            None => None,
+        },
+        Source::App(app_source) => Some(
+            custom_app::replace_placeholders(
+                machine_state,
+                config,
+                &app_source.health_check_command,
+                &service.name,
+            )
+            .await,
+        ),
+    };
+
+    if let Some(health_check_command) = health_check_command {
+        return match command::run(&health_check_command).await {
+            Ok(_) => Ok(None),
+            Err(e) => {
+                let output = e.to_string().trim().to_string();
+                if output.is_empty() {
+                    Ok(Some(format!("`{}` failed", health_check_command)))
+                } else {
+                    Ok(Some(output))
+                }
+            }
+        };
+    }
+
+    // Without a health check command, rely on the healthcheck docker runs in the container
+    let service_status = docker::get_service_status(config, &service.name).await?;
+    if !service_status.running {
+        return Err(Box::from(format!("{} is not running", service.name)));
+    }
+
+    match service_status.health {
+        Some(health) if health == "healthy" => Ok(None),
+        Some(health) => Ok(Some(health)),
+        None => {
+            logging::warn(&format!(
+                "{} has no healthcheck, add --health-cmd to its start_command or set a health_check_command",
+                service.name
+            ))
+            .await;
+            Ok(None)
+        }
+    }
+}
+
+/// Waits until the service is healthy, for at most `health_timeout` seconds
+pub async fn wait_until_healthy(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+) -> Result<(), Box<dyn Error>> {
+    let timeout = Duration::from_secs(service.health_timeout);
+    let deadline = Instant::now() + timeout;
+
+    logging::info(&format!("⏳ Waiting for {} to be healthy", service.name)).await;
+
+    loop {
+        let status = match check_health(machine_state, config, service).await? {
+            Some(status) => status,
+            None => {
+                logging::info(&format!("✅ {} is healthy", service.name)).await;
+                return Ok(());
+            }
+        };
+
+        if Instant::now() >= deadline {
+            return Err(Box::from(format!(
+                "{} did not become healthy within {}s, last status: {}",
+                service.name,
+                timeout.as_secs(),
+                status
+            )));
+        }
+
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    }
+}
+
+async fn replace_placeholders(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    text: &String,
+) -> String {
     match &service.source {
         Source::Container(container_source) => {
-            docker::start_service(
+            docker::replace_placeholders(
                 machine_state,
                 config,
+                text,
                 &service.name,
                 container_source,
-                clean_mode,
-                fail_fast,
             )
-            .await;
+            .await
         }
-        Source::App(app_source) => {
-            custom_app::start_service(
+        Source::App(_) => {
+            custom_app::replace_placeholders(machine_state, config, text, &service.name).await
+        }
+    }
+}
+
+async fn replace_probe_placeholders(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    ready: &ReadyProbe,
+) -> ReadyProbe {
+    let mut ready = ready.clone();
+    for probe in [&mut ready.tcp, &mut ready.http, &mut ready.exec]
+        .into_iter()
+        .flatten()
+    {
+        *probe = replace_placeholders(machine_state, config, service, probe).await;
+    }
+    ready
+}
+
+/// The resolved definition of the service, its hash changes when the service has to be recreated
+pub async fn definition(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+) -> ServiceDefinition {
+    let fields = match &service.source {
+        Source::Container(source) => {
+            docker::definition_fields(machine_state, config, &service.name, source, &service.ports)
+                .await
+        }
+        Source::App(source) => {
+            custom_app::definition_fields(machine_state, config, &service.name, source).await
+        }
+    };
+
+    let serialized = serde_json::to_string(&fields).unwrap_or_default();
+    let hash = format!("{:x}", Sha256::digest(serialized.as_bytes()));
+    ServiceDefinition {
+        hash: hash[..12].to_string(),
+        fields,
+    }
+}
+
+/// Values longer than this are only reported as changed
+const MAX_SHOWN_VALUE_LENGTH: usize = 40;
+
+/// Describes what changed between two definitions, like `tag latest → 7.2`
+pub fn describe_changes(old: &ServiceDefinition, new: &ServiceDefinition) -> Vec<String> {
+    let fields: BTreeSet<&String> = old.fields.keys().chain(new.fields.keys()).collect();
+
+    let mut changes = vec![];
+    for field in fields {
+        let change = match (old.fields.get(field), new.fields.get(field)) {
+            (Some(old_value), Some(new_value)) if old_value == new_value => continue,
+            (Some(old_value), Some(new_value))
+                if old_value.len() <= MAX_SHOWN_VALUE_LENGTH
+                    && new_value.len() <= MAX_SHOWN_VALUE_LENGTH =>
+            {
+                format!("{} {} → {}", field, old_value, new_value)
+            }
+            (Some(_), Some(_)) => format!("{} changed", field),
+            (Some(_), None) => format!("{} removed", field),
+            (None, Some(_)) => format!("{} added", field),
+            (None, None) => continue,
+        };
+        changes.push(change);
+    }
+
+    if changes.is_empty() && old.hash != new.hash {
+        changes.push("definition changed".to_string());
+    }
+    changes
+}
+
+/// Only services that can be stopped can be recreated
+pub fn can_recreate(service: &Service) -> bool {
+    match &service.source {
+        Source::Container(_) => true,
+        Source::App(source) => source.stop_command.is_some(),
+    }
+}
+
+/// The services that were started before, even when the state has no definition for them:
+/// the `wm.config-hash` label of their container, or `None` for apps that have a pid file
+pub async fn started_services(config: &Config) -> HashMap<String, Option<String>> {
+    let has_containers = config
+        .services
+        .iter()
+        .any(|service| matches!(service.source, Source::Container(_)));
+    let mut started: HashMap<String, Option<String>> = if has_containers {
+        docker::config_hash_labels(config)
+            .await
+            .into_iter()
+            .map(|(name, hash)| (name, Some(hash)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+
+    for service in &config.services {
+        if matches!(service.source, Source::App(_))
+            && machine::logs::get_pid_path(&service.name).exists()
+        {
+            started.insert(service.name.clone(), None);
+        }
+    }
+    started
+}
+
+/// The services whose definition changed since they were last started, with what changed
+pub fn plan(
+    machine_state: &MachineState,
+    config: &Config,
+    definitions: &HashMap<String, ServiceDefinition>,
+    started: &HashMap<String, Option<String>>,
+) -> Vec<(String, Vec<String>)> {
+    let mut changed_services: Vec<(String, Vec<String>)> = definitions
+        .iter()
+        .filter_map(|(name, definition)| {
+            let previous = match machine_state.containers.get(name) {
+                Some(previous) => previous,
+                // Without a saved definition, the label of the container tells whether the
+                // service changed. Apps have none, so they are started again.
+                None => {
+                    let config_hash = docker::service_config_hash(config, name);
+                    let change = match started.get(name)? {
+                        Some(label) if *label == config_hash => return None,
+                        Some(label) => format!("config hash {} → {}", label, config_hash),
+                        None => "no saved definition".to_string(),
+                    };
+                    return Some((name.clone(), vec![change]));
+                }
+            };
+            if previous.hash == definition.hash {
+                return None;
+            }
+            Some((name.clone(), describe_changes(previous, definition)))
+        })
+        .collect();
+    changed_services.sort();
+    changed_services
+}
+
+/// Starts the service and returns the exposed values that only become available after it
+/// starts. With `recreate`, a running service is replaced by a new one.
+pub async fn start(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    clean_mode: bool,
+    recreate: bool,
+) -> Result<Vec<(String, String, String)>, Box<dyn Error>> {
+    match &service.source {
+        Source::Container(container_source) => {
+            // Cleaning removes the container, so a new one is created from the current definition
+            docker::start_service(
                 machine_state,
                 config,
                 &service.name,
-                app_source,
-                clean_mode,
-                fail_fast,
+                container_source,
+                &service.ports,
+                clean_mode || recreate,
             )
-            .await;
+            .await?;
+        }
+        Source::App(app_source) => {
+            if recreate && !clean_mode {
+                custom_app::stop_service(machine_state, config, &service.name, app_source, false)
+                    .await;
+            }
+            custom_app::start_service(machine_state, config, &service.name, app_source, clean_mode)
+                .await?;
+        }
+    }
+
+    // Dependents and the command only start once this returns
+    if service.wait_for_healthy {
+        if let Err(e) = wait_until_healthy(machine_state, config, service).await {
+            logging::error(&format!("🛑 {}", e)).await;
+            return Err(e);
+        }
+    }
+
+    if let Some(ready) = &service.ready {
+        let ready = replace_probe_placeholders(machine_state, config, service, ready).await;
+        match probe::wait_until_ready(&ready).await {
+            Ok(_) => logging::info(&format!("✅ {} is ready", service.name)).await,
+            Err(e) => {
+                let e: Box<dyn Error> = Box::from(format!("{} is {}", service.name, e));
+                logging::error(&format!("🛑 {}", e)).await;
+                return Err(e);
+            }
         }
     }
 
     // Set the available_before_start=false variables
     let exposed_values =
-        get_exposed_variables(&machine_state, &service.exposed_values, false).await;
+        get_exposed_variables(machine_state, &service.name, &service.exposed_values, false).await;
 
     let mut env_vars = vec![];
     for (key, value) in exposed_values {
@@ -78,55 +371,431 @@ pub async fn start(
     }
 
     env_vars::set(&env_vars).await;
+
+    Ok(env_vars)
 }
 
+/// What `start` runs for the service, for dry runs
+pub async fn start_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    clean_mode: bool,
+    recreate: bool,
+) -> Vec<String> {
+    let mut commands = match &service.source {
+        Source::Container(container_source) => {
+            docker::start_commands(
+                machine_state,
+                config,
+                &service.name,
+                container_source,
+                &service.ports,
+                clean_mode || recreate,
+            )
+            .await
+        }
+        Source::App(app_source) => {
+            let mut commands = vec![];
+            if recreate && !clean_mode {
+                commands.extend(
+                    custom_app::stop_commands(machine_state, config, &service.name, app_source)
+                        .await,
+                );
+            }
+            commands.extend(
+                custom_app::start_commands(
+                    machine_state,
+                    config,
+                    &service.name,
+                    app_source,
+                    clean_mode,
+                )
+                .await,
+            );
+            commands
+        }
+    };
+
+    if service.wait_for_healthy {
+        commands.push(format!(
+            "# wait for {} to be healthy, for at most {}s",
+            service.name, service.health_timeout
+        ));
+    }
+
+    if let Some(ready) = &service.ready {
+        let ready = replace_probe_placeholders(machine_state, config, service, ready).await;
+        let probes = [
+            ("tcp", &ready.tcp),
+            ("http", &ready.http),
+            ("exec", &ready.exec),
+        ];
+        for (kind, probe) in probes {
+            if let Some(probe) = probe {
+                commands.push(format!("# wait for the {} probe `{}`", kind, probe));
+            }
+        }
+    }
+
+    commands
+}
+
+/// Starts every service as soon as the services it depends on have started, with at most
+/// `max_concurrent_starts` services starting at the same time. Services that depend on a
+/// service that failed to start are skipped. With `fail_fast`, no new services are started
+/// after a failure and the process exits once the ones already starting are done.
 pub async fn start_all(
     machine_state: &MachineState,
     config: &Config,
     clean_mode: bool,
     fail_fast: bool,
-) {
-    let mut tasks = vec![];
-
-    let mut leftover_services = HashMap::new();
+) -> Vec<(String, String, String)> {
+    let mut env_vars = vec![];
 
-    // First start the services without any dependencies
+    let mut definitions: HashMap<String, ServiceDefinition> = HashMap::new();
     for service in &config.services {
-        if service.depends_on.is_empty() {
-            let task = start(machine_state, config, &service, clean_mode, fail_fast);
-            tasks.push(task);
+        let definition = definition(machine_state, config, service).await;
+        definitions.insert(service.name.clone(), definition);
+    }
+
+    // Cleaning recreates every service anyway
+    let mut recreated_services: HashSet<String> = HashSet::new();
+    let changed_services = if clean_mode {
+        vec![]
+    } else {
+        let started = started_services(config).await;
+        plan(machine_state, config, &definitions, &started)
+    };
+    if !changed_services.is_empty() {
+        logging::info("📝 Services changed since they were started:").await;
+    }
+    for (name, changes) in changed_services {
+        let service = config.services.iter().find(|service| service.name == name);
+        if service.is_some_and(can_recreate) {
+            logging::info(&format!(
+                "  {}: {}, will recreate",
+                name,
+                changes.join(", ")
+            ))
+            .await;
+            recreated_services.insert(name);
         } else {
-            leftover_services.insert(service.name.clone(), service);
+            logging::warn(&format!(
+                "  {}: {}, restart it yourself, it has no stop_command",
+                name,
+                changes.join(", ")
+            ))
+            .await;
+        }
+    }
+    let recreated_services = &recreated_services;
+
+    let mut pending_services: Vec<&Service> = config.services.iter().collect();
+    let mut finished_services: HashSet<&str> = HashSet::new();
+    let mut failed_services: Vec<&str> = vec![];
+    let mut skipped_services: Vec<&str> = vec![];
+    let mut running_tasks = FuturesUnordered::new();
+    let max_concurrent_starts = config.max_concurrent_starts.max(1);
+
+    loop {
+        // Skipping a service can make its own dependents skippable, so repeat until nothing changes
+        loop {
+            let skipped: Vec<(&Service, &str)> = pending_services
+                .iter()
+                .filter_map(|service| {
+                    service
+                        .depends_on
+                        .iter()
+                        .find(|dependency| {
+                            failed_services.contains(&dependency.as_str())
+                                || skipped_services.contains(&dependency.as_str())
+                        })
+                        .map(|dependency| (*service, dependency.as_str()))
+                })
+                .collect();
+
+            if skipped.is_empty() {
+                break;
+            }
+
+            for (service, dependency) in skipped {
+                logging::error(&format!(
+                    "🛑 Skipping {} because {} did not start",
+                    service.name, dependency
+                ))
+                .await;
+                skipped_services.push(&service.name);
+                pending_services.retain(|pending| pending.name != service.name);
+            }
+        }
+
+        let stop_starting = fail_fast && !failed_services.is_empty();
+        while !stop_starting && running_tasks.len() < max_concurrent_starts {
+            let ready_index = pending_services.iter().position(|service| {
+                service
+                    .depends_on
+                    .iter()
+                    .all(|dependency| finished_services.contains(dependency.as_str()))
+            });
+
+            let service = match ready_index {
+                Some(index) => pending_services.remove(index),
+                None => break,
+            };
+
+            // Failures are handled here, so a failing service doesn't exit the process
+            // while other services are still starting
+            running_tasks.push(async move {
+                let recreate = recreated_services.contains(&service.name);
+                let result = start(machine_state, config, service, clean_mode, recreate).await;
+                (service, result)
+            });
+        }
+
+        // Nothing is starting and nothing else can start
+        let (service, result) = match running_tasks.next().await {
+            Some(finished) => finished,
+            None => break,
+        };
+
+        match result {
+            Ok(service_env_vars) => {
+                finished_services.insert(&service.name);
+                env_vars.extend(service_env_vars);
+            }
+            Err(_) => failed_services.push(&service.name),
         }
     }
 
-    // We can always start the services without dependencies asynchronously
-    join_all(tasks).await;
-    // Now we loop over the services that have dependencies and try to start them asynchronously if we can
-    // Dependencies configuration should have already checked for:
-    // - Circular dependencies
-    // - Missing dependencies
-    // - Missing services
-    // So this should never loop infinitely
+    // Only the services that are running now have the current definition
+    definitions.retain(|name, _| finished_services.contains(name.as_str()));
+    machine::state::save_definitions(definitions).await;
+
+    if !failed_services.is_empty() {
+        skipped_services.extend(pending_services.iter().map(|service| service.name.as_str()));
+        logging::error(&format!(
+            "🛑 Failed to start: {}",
+            failed_services.join(", ")
+        ))
+        .await;
+        if !skipped_services.is_empty() {
+            logging::error(&format!(
+                "🛑 Not started because of the failures: {}",
+                skipped_services.join(", ")
+            ))
+            .await;
+        }
+
+        if fail_fast {
+            std::process::exit(1);
+        }
+    } else {
+        // Dependencies configuration should have already reported these
+        for service in pending_services {
+            logging::error(&format!(
+                "🛑 Not starting {}, its dependencies can't be started",
+                service.name
+            ))
+            .await;
+        }
+    }
+
+    env_vars
+}
+
+pub async fn stop(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    fail_fast: bool,
+) {
+    match &service.source {
+        Source::Container(container_source) => {
+            docker::stop_service(
+                machine_state,
+                config,
+                &service.name,
+                container_source,
+                fail_fast,
+            )
+            .await;
+        }
+        Source::App(app_source) => {
+            custom_app::stop_service(machine_state, config, &service.name, app_source, fail_fast)
+                .await;
+        }
+    }
+}
+
+/// What `clean` runs for the service, for dry runs
+pub async fn clean_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+) -> Vec<String> {
+    match &service.source {
+        Source::Container(_) => docker::clean_commands(config, &service.name),
+        Source::App(app_source) => {
+            custom_app::clean_commands(machine_state, config, &service.name, app_source).await
+        }
+    }
+}
+
+/// What `stop` runs for the service, for dry runs
+pub async fn stop_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+) -> Vec<String> {
+    match &service.source {
+        Source::Container(container_source) => {
+            docker::stop_commands(machine_state, config, &service.name, container_source).await
+        }
+        Source::App(app_source) => {
+            custom_app::stop_commands(machine_state, config, &service.name, app_source).await
+        }
+    }
+}
+
+/// Stops all the services in reverse dependency order, a service is only stopped
+/// once every service that depends on it has been stopped.
+pub async fn stop_all(machine_state: &MachineState, config: &Config, fail_fast: bool) {
+    let mut leftover_services: HashMap<String, &Service> = config
+        .services
+        .iter()
+        .map(|service| (service.name.clone(), service))
+        .collect();
+
     while !leftover_services.is_empty() {
-        let mut new_tasks = vec![];
-        let mut started_services = vec![];
+        let mut tasks = vec![];
+        let mut stopped_services = vec![];
         for (service_name, service) in &leftover_services {
-            // We need to check if all the dependencies are started.
-            // If they are not in the leftover_services hashmap, they are already started
-            if service
-                .depends_on
-                .iter()
-                .all(|dependency| !leftover_services.contains_key(dependency))
+            // A service can be stopped when none of the services still running depend on it
+            if !leftover_services
+                .values()
+                .any(|other| other.depends_on.contains(service_name))
             {
-                let task = start(machine_state, config, &service, clean_mode, fail_fast);
-                new_tasks.push(task);
-                started_services.push(service_name.clone());
+                tasks.push(stop(machine_state, config, service, fail_fast));
+                stopped_services.push(service_name.clone());
+            }
+        }
+
+        // Circular dependencies would leave us without any service to stop, in that case
+        // we stop everything that is left at once instead of looping forever
+        if tasks.is_empty() {
+            for service in leftover_services.values() {
+                tasks.push(stop(machine_state, config, service, fail_fast));
             }
+            join_all(tasks).await;
+            break;
         }
-        for service_name in started_services {
+
+        for service_name in stopped_services {
             leftover_services.remove(&service_name);
         }
-        join_all(new_tasks).await;
+        join_all(tasks).await;
+    }
+}
+
+pub async fn status(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+) -> ServiceStatus {
+    let mut service_status = match &service.source {
+        Source::Container(_) => match docker::get_service_status(config, &service.name).await {
+            Ok(service_status) => service_status,
+            Err(e) => ServiceStatus {
+                name: service.name.clone(),
+                running: false,
+                health: None,
+                container_id: None,
+                pid: None,
+                image: None,
+                ports: HashMap::new(),
+                uptime: None,
+                error: Some(e.to_string().trim().to_string()),
+            },
+        },
+        Source::App(app_source) => {
+            custom_app::get_service_status(machine_state, config, &service.name, app_source).await
+        }
+    };
+
+    for port_name in machine::ports::get_service_ports_needed(service) {
+        if let Some(port) = machine_state.ports.get(&port_name) {
+            // Declared ports are shown by the name the service gave them
+            let prefix = format!("{}.", service.name);
+            let port_name = match port_name.strip_prefix(&prefix) {
+                Some(declared_name) => declared_name.to_string(),
+                None => port_name,
+            };
+            service_status.ports.insert(port_name, *port);
+        }
+    }
+
+    service_status
+}
+
+/// Builds the command that prints the logs of a service
+pub async fn logs_command(
+    config: &Config,
+    service: &Service,
+    follow: bool,
+    since: Option<&str>,
+    tail: Option<&str>,
+) -> Result<Command, Box<dyn Error>> {
+    match &service.source {
+        Source::Container(_) => match docker::get_container_id(config, &service.name).await? {
+            Some(container_id) => Ok(docker::logs_command(&container_id, follow, since, tail)),
+            None => Err(Box::from(format!(
+                "No container found for {}",
+                service.name
+            ))),
+        },
+        Source::App(_) => {
+            let log_path = machine::logs::get_log_path(&service.name);
+            if !log_path.exists() {
+                return Err(Box::from(format!(
+                    "No logs captured for {} yet, they are written to {} when war machine starts it",
+                    service.name,
+                    log_path.display()
+                )));
+            }
+
+            if since.is_some() {
+                logging::warn(&format!(
+                    "--since is not supported for app services, showing all the logs of {}",
+                    service.name
+                ))
+                .await;
+            }
+
+            Ok(machine::logs::logs_command(&service.name, follow, tail))
+        }
+    }
+}
+
+/// Runs a command against the service, inside its container or with its exposed values for apps
+pub async fn exec(
+    machine_state: &MachineState,
+    config: &Config,
+    service: &Service,
+    args: &[String],
+) -> Result<ExitStatus, Box<dyn Error>> {
+    match &service.source {
+        Source::Container(_) => docker::exec(config, &service.name, args).await,
+        Source::App(_) => {
+            let mut exposed_values =
+                get_exposed_variables(machine_state, &service.name, &service.exposed_values, true)
+                    .await;
+            exposed_values.extend(
+                get_exposed_variables(machine_state, &service.name, &service.exposed_values, false)
+                    .await,
+            );
+
+            custom_app::exec(args, &exposed_values).await
+        }
     }
 }
@@ -0,0 +1,1060 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+
+use regex::Regex;
+use serde::de::{self, DeserializeOwned, Deserializer, Visitor};
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+use crate::{
+    library::{config::dependencies, machine, system::docker},
+    models::{
+        config::{
+            AppSource, Config, ContainerBuild, ContainerHealthcheck, ContainerSource,
+            ExposedValueCommand, ExposedValueLiteral, PortRange, PythonpathFeature, ReadyProbe,
+            RegistryCredentials, Service, ServicePort, Source,
+        },
+        diagnostic::{Diagnostic, Severity},
+    },
+};
+
+/// Deserializer that only records the field names serde asks a struct for
+#[derive(Default)]
+struct FieldNames(&'static [&'static str]);
+
+impl<'de> Deserializer<'de> for &mut FieldNames {
+    type Error = serde::de::value::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0 = fields;
+        Err(de::Error::custom("field names recorded"))
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// The keys of a struct in `models::config`, as serde reads them
+fn fields<T: DeserializeOwned>() -> Vec<&'static str> {
+    let mut names = FieldNames::default();
+    let _ = T::deserialize(&mut names);
+    names.0.to_vec()
+}
+
+/// The keys a table accepts, taken from its struct in `models::config`, and the ones it can't
+/// do without. Keep `required` in sync with the fields that have no default.
+struct Schema {
+    name: &'static str,
+    keys: fn() -> Vec<&'static str>,
+    required: &'static [&'static str],
+}
+
+const CONFIG_SCHEMA: Schema = Schema {
+    name: "configuration",
+    keys: fields::<Config>,
+    required: &["machine_name"],
+};
+
+const PORT_RANGE_SCHEMA: Schema = Schema {
+    name: "port range",
+    keys: fields::<PortRange>,
+    required: &["start", "end"],
+};
+
+const SERVICE_SCHEMA: Schema = Schema {
+    name: "service",
+    keys: fields::<Service>,
+    required: &["name", "source"],
+};
+
+const BUILD_SCHEMA: Schema = Schema {
+    name: "build",
+    keys: fields::<ContainerBuild>,
+    required: &["context"],
+};
+
+const HEALTHCHECK_SCHEMA: Schema = Schema {
+    name: "healthcheck",
+    keys: fields::<ContainerHealthcheck>,
+    required: &["command"],
+};
+
+const SERVICE_PORT_SCHEMA: Schema = Schema {
+    name: "service port",
+    keys: fields::<ServicePort>,
+    required: &["name"],
+};
+
+const READY_SCHEMA: Schema = Schema {
+    name: "ready probe",
+    keys: fields::<ReadyProbe>,
+    required: &[],
+};
+
+const REGISTRY_CREDENTIALS_SCHEMA: Schema = Schema {
+    name: "registry credentials",
+    keys: fields::<RegistryCredentials>,
+    required: &["registry", "username", "password"],
+};
+
+/// Variants of the untagged `Source` enum, in the order serde tries them
+const SOURCE_SCHEMAS: &[Schema] = &[
+    Schema {
+        name: "app source",
+        keys: fields::<AppSource>,
+        required: &[
+            "install_command",
+            "install_check_command",
+            "start_command",
+            "health_check_command",
+        ],
+    },
+    Schema {
+        name: "container source",
+        keys: fields::<ContainerSource>,
+        // Either `image` or `build`, checked with the other semantics
+        required: &[],
+    },
+];
+
+const EXPOSED_VALUE_SCHEMAS: &[Schema] = &[
+    Schema {
+        name: "command exposed value",
+        keys: fields::<ExposedValueCommand>,
+        required: &["command"],
+    },
+    Schema {
+        name: "literal exposed value",
+        keys: fields::<ExposedValueLiteral>,
+        required: &["name", "value"],
+    },
+];
+
+/// Variants of the `Feature` enum, tagged by their `name`
+const FEATURE_SCHEMAS: &[Schema] = &[
+    Schema {
+        name: "pythonpath",
+        keys: || [vec!["name"], fields::<PythonpathFeature>()].concat(),
+        required: &["env_file_path", "pythonpath_value"],
+    },
+    Schema {
+        name: "bitwarden",
+        keys: || vec!["name"],
+        required: &[],
+    },
+];
+
+/// Levenshtein distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b_chars.len()]
+}
+
+/// Finds the candidate closest to the value, if any is close enough to be a typo
+pub fn suggest<'a>(value: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    let max_distance = std::cmp::max(2, value.len() / 3);
+
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(value, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn did_you_mean(suggestion: Option<String>) -> Option<String> {
+    suggestion.map(|suggestion| format!("did you mean `{}`?", suggestion))
+}
+
+/// Returns the tables of an array of tables, written either as `[[name]]` or as inline tables
+fn tables_in(item: &Item) -> Vec<(&dyn TableLike, Option<Range<usize>>)> {
+    if let Some(array_of_tables) = item.as_array_of_tables() {
+        array_of_tables
+            .iter()
+            .map(|table| (table as &dyn TableLike, table.span()))
+            .collect()
+    } else if let Some(array) = item.as_array() {
+        array
+            .iter()
+            .filter_map(|value| value.as_inline_table())
+            .map(|table| (table as &dyn TableLike, table.span()))
+            .collect()
+    } else {
+        vec![]
+    }
+}
+
+/// Spans of the string values in `table` and every table below it. Comments are not values,
+/// so placeholders in them are never found.
+fn string_spans(table: &dyn TableLike) -> Vec<Range<usize>> {
+    fn value_spans(value: &Value, spans: &mut Vec<Range<usize>>) {
+        match value {
+            Value::String(string) => spans.extend(string.span()),
+            Value::Array(array) => array.iter().for_each(|value| value_spans(value, spans)),
+            Value::InlineTable(table) => spans.extend(string_spans(table)),
+            _ => {}
+        }
+    }
+
+    let mut spans = vec![];
+    for (_, item) in table.iter() {
+        match item {
+            Item::Value(value) => value_spans(value, &mut spans),
+            Item::Table(table) => spans.extend(string_spans(table)),
+            Item::ArrayOfTables(tables) => tables
+                .iter()
+                .for_each(|table| spans.extend(string_spans(table))),
+            Item::None => {}
+        }
+    }
+    spans
+}
+
+fn key_span(table: &dyn TableLike, key: &str) -> Option<Range<usize>> {
+    table.key(key).and_then(|key| key.span())
+}
+
+fn value_span(table: &dyn TableLike, key: &str) -> Option<Range<usize>> {
+    table.get(key).and_then(|item| item.span())
+}
+
+/// Reports unknown keys with suggestions and missing required keys
+fn check_keys(
+    table: &dyn TableLike,
+    schema: &Schema,
+    what: &str,
+    span: Option<Range<usize>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let keys = (schema.keys)();
+    let mut typos: HashMap<String, String> = HashMap::new();
+
+    for (key, _) in table.iter() {
+        if keys.contains(&key) {
+            continue;
+        }
+
+        let suggestion = suggest(key, keys.iter().copied());
+        if let Some(suggestion) = &suggestion {
+            typos.insert(suggestion.clone(), key.to_string());
+        }
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!("unknown key `{}` in {}", key, what),
+            span: key_span(table, key),
+            help: did_you_mean(suggestion),
+        });
+    }
+
+    for required in schema.required {
+        if table.contains_key(required) {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: format!("{} is missing `{}`", what, required),
+            span: span.clone(),
+            help: typos
+                .get(*required)
+                .map(|typo| format!("rename `{}` to `{}`", typo, required)),
+        });
+    }
+}
+
+/// Checks a table that deserializes into an untagged enum. Serde only says that the data
+/// did not match any variant, so we find the variant the table was meant to be.
+fn check_variants(
+    table: &dyn TableLike,
+    schemas: &[Schema],
+    what: &str,
+    span: Option<Range<usize>>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    // Otherwise the closest variant is the one that knows most of the keys, typos included
+    let score = |schema: &Schema| {
+        let keys = (schema.keys)();
+        table
+            .iter()
+            .filter(|(key, _)| keys.contains(key) || suggest(key, keys.iter().copied()).is_some())
+            .count()
+    };
+    let (_, closest) = schemas
+        .iter()
+        .enumerate()
+        // max_by_key returns the last maximum, prefer the first variant on ties
+        .max_by_key(|(i, schema)| (score(schema), std::cmp::Reverse(*i)))
+        .unwrap();
+
+    // Serde picks the first variant whose required keys are all there. A variant without
+    // required keys matches any table, so it only counts when no other variant fits better.
+    let matched = schemas.iter().find(|schema| {
+        schema
+            .required
+            .iter()
+            .all(|required| table.contains_key(required))
+            && (!schema.required.is_empty() || score(schema) >= score(closest))
+    });
+
+    if let Some(schema) = matched {
+        check_keys(
+            table,
+            schema,
+            &format!("{} ({})", what, schema.name),
+            span,
+            diagnostics,
+        );
+        return;
+    }
+
+    let variant_names: Vec<&str> = schemas.iter().map(|schema| schema.name).collect();
+    diagnostics.push(Diagnostic {
+        severity: Severity::Error,
+        message: format!(
+            "{} does not match any of: {} (closest: {})",
+            what,
+            variant_names.join(", "),
+            closest.name
+        ),
+        span: span.clone(),
+        help: None,
+    });
+
+    check_keys(table, closest, what, span, diagnostics);
+}
+
+const PULL_POLICIES: &[&str] = &["always", "missing", "never"];
+
+/// Serde can only say that a source with a wrong pull policy matches no variant
+fn check_pull_policy(table: &dyn TableLike, what: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let value = match table.get("pull_policy").and_then(|item| item.as_str()) {
+        Some(value) => value,
+        None => return,
+    };
+    if PULL_POLICIES.contains(&value) {
+        return;
+    }
+
+    diagnostics.push(Diagnostic {
+        severity: Severity::Error,
+        message: format!(
+            "unknown pull_policy `{}` in {}, expected one of `always`, `missing`, `never`",
+            value, what
+        ),
+        span: key_span(table, "pull_policy"),
+        help: did_you_mean(suggest(value, PULL_POLICIES.iter().copied())),
+    });
+}
+
+fn check_structure(root: &dyn TableLike, diagnostics: &mut Vec<Diagnostic>) {
+    check_keys(
+        root,
+        &CONFIG_SCHEMA,
+        CONFIG_SCHEMA.name,
+        Some(0..0),
+        diagnostics,
+    );
+
+    check_pull_policy(root, CONFIG_SCHEMA.name, diagnostics);
+
+    if let Some(port_range) = root.get("port_range").and_then(|item| item.as_table_like()) {
+        check_keys(
+            port_range,
+            &PORT_RANGE_SCHEMA,
+            PORT_RANGE_SCHEMA.name,
+            key_span(root, "port_range"),
+            diagnostics,
+        );
+    }
+
+    if let Some(services) = root.get("services") {
+        for (i, (service, span)) in tables_in(services).into_iter().enumerate() {
+            let span = span.or(key_span(root, "services"));
+            let what = match service.get("name").and_then(|name| name.as_str()) {
+                Some(name) => format!("service `{}`", name),
+                None => format!("service #{}", i + 1),
+            };
+
+            check_keys(service, &SERVICE_SCHEMA, &what, span.clone(), diagnostics);
+
+            if let Some(source) = service.get("source").and_then(|item| item.as_table_like()) {
+                let source_span = key_span(service, "source").or(span.clone());
+                check_variants(
+                    source,
+                    SOURCE_SCHEMAS,
+                    &format!("{} source", what),
+                    source_span.clone(),
+                    diagnostics,
+                );
+
+                check_pull_policy(source, &format!("{} source", what), diagnostics);
+
+                if let Some(build) = source.get("build").and_then(|item| item.as_table_like()) {
+                    check_keys(
+                        build,
+                        &BUILD_SCHEMA,
+                        &format!("{} build", what),
+                        key_span(source, "build").or(source_span.clone()),
+                        diagnostics,
+                    );
+                }
+
+                if let Some(healthcheck) = source
+                    .get("healthcheck")
+                    .and_then(|item| item.as_table_like())
+                {
+                    check_keys(
+                        healthcheck,
+                        &HEALTHCHECK_SCHEMA,
+                        &format!("{} healthcheck", what),
+                        key_span(source, "healthcheck").or(source_span),
+                        diagnostics,
+                    );
+                }
+            }
+
+            if let Some(ports) = service.get("ports") {
+                for (port, port_span) in tables_in(ports) {
+                    check_keys(
+                        port,
+                        &SERVICE_PORT_SCHEMA,
+                        &format!("{} port", what),
+                        port_span.or(key_span(service, "ports")),
+                        diagnostics,
+                    );
+                }
+            }
+
+            if let Some(ready) = service.get("ready").and_then(|item| item.as_table_like()) {
+                check_keys(
+                    ready,
+                    &READY_SCHEMA,
+                    &format!("{} ready probe", what),
+                    key_span(service, "ready").or(span.clone()),
+                    diagnostics,
+                );
+            }
+
+            if let Some(exposed_values) = service.get("exposed_values") {
+                for (exposed_value, exposed_value_span) in tables_in(exposed_values) {
+                    check_variants(
+                        exposed_value,
+                        EXPOSED_VALUE_SCHEMAS,
+                        &format!("exposed value of {}", what),
+                        exposed_value_span.or(key_span(service, "exposed_values")),
+                        diagnostics,
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(registry_credentials) = root.get("registry_credentials") {
+        for (credentials, span) in tables_in(registry_credentials) {
+            check_keys(
+                credentials,
+                &REGISTRY_CREDENTIALS_SCHEMA,
+                REGISTRY_CREDENTIALS_SCHEMA.name,
+                span.or(key_span(root, "registry_credentials")),
+                diagnostics,
+            );
+        }
+    }
+
+    if let Some(features) = root.get("features") {
+        for (feature, span) in tables_in(features) {
+            let span = span.or(key_span(root, "features"));
+            let name = feature.get("name").and_then(|name| name.as_str());
+            let schema = FEATURE_SCHEMAS
+                .iter()
+                .find(|schema| Some(schema.name) == name);
+
+            match (name, schema) {
+                (Some(_), Some(schema)) => {
+                    check_keys(
+                        feature,
+                        schema,
+                        &format!("feature `{}`", schema.name),
+                        span,
+                        diagnostics,
+                    );
+                }
+                (Some(name), None) => {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        message: format!("unknown feature `{}`", name),
+                        span: value_span(feature, "name"),
+                        help: did_you_mean(suggest(
+                            name,
+                            FEATURE_SCHEMAS.iter().map(|schema| schema.name),
+                        )),
+                    });
+                }
+                (None, _) => {
+                    diagnostics.push(Diagnostic {
+                        severity: Severity::Error,
+                        message: "feature is missing `name`".to_string(),
+                        span,
+                        help: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+fn check_semantics(
+    config: &Config,
+    root: &dyn TableLike,
+    content: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let service_tables = root.get("services").map(tables_in).unwrap_or_default();
+    let service_names: Vec<&str> = config
+        .services
+        .iter()
+        .map(|service| service.name.as_str())
+        .collect();
+
+    let mut seen = HashSet::new();
+    for (i, service) in config.services.iter().enumerate() {
+        let service_table = service_tables.get(i).map(|(table, _)| *table);
+
+        if !seen.insert(service.name.as_str()) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!("service `{}` is defined more than once", service.name),
+                span: service_table.and_then(|table| value_span(table, "name")),
+                help: None,
+            });
+        }
+
+        for dependency in &service.depends_on {
+            let span = service_table
+                .and_then(|table| table.get("depends_on"))
+                .and_then(|item| item.as_array())
+                .and_then(|array| {
+                    array
+                        .iter()
+                        .find(|value| value.as_str() == Some(dependency))
+                })
+                .and_then(|value| value.span());
+
+            if dependency == &service.name {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!("service `{}` depends on itself", service.name),
+                    span,
+                    help: None,
+                });
+            } else if !service_names.contains(&dependency.as_str()) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!(
+                        "service `{}` depends on unknown service `{}`",
+                        service.name, dependency
+                    ),
+                    span,
+                    help: did_you_mean(suggest(dependency, service_names.iter().copied())),
+                });
+            }
+        }
+    }
+
+    for (i, service) in config.services.iter().enumerate() {
+        let ready = match &service.ready {
+            Some(ready) => ready,
+            None => continue,
+        };
+        let span = service_tables
+            .get(i)
+            .and_then(|(table, _)| key_span(*table, "ready"));
+
+        let kinds = [&ready.tcp, &ready.http, &ready.exec]
+            .iter()
+            .filter(|kind| kind.is_some())
+            .count();
+        if kinds != 1 {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "the ready probe of service `{}` needs exactly one of `tcp`, `http` or `exec`",
+                    service.name
+                ),
+                span: span.clone(),
+                help: None,
+            });
+        }
+        if ready.status.is_some() && ready.http.is_none() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                message: format!(
+                    "the ready probe of service `{}` only checks `status` for `http` probes",
+                    service.name
+                ),
+                span,
+                help: None,
+            });
+        }
+    }
+
+    let port_name_regex = Regex::new(r"^\w+$").unwrap();
+    for (i, service) in config.services.iter().enumerate() {
+        let port_tables = service_tables
+            .get(i)
+            .and_then(|(table, _)| table.get("ports"))
+            .map(tables_in)
+            .unwrap_or_default();
+
+        let mut seen_ports = HashSet::new();
+        for (j, port) in service.ports.iter().enumerate() {
+            let span = port_tables
+                .get(j)
+                .and_then(|(table, _)| value_span(*table, "name"));
+
+            if !port_name_regex.is_match(&port.name) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!(
+                        "invalid port name `{}` in service `{}`, port names can only contain letters, numbers and underscores",
+                        port.name, service.name
+                    ),
+                    span: span.clone(),
+                    help: None,
+                });
+            }
+            if !seen_ports.insert(port.name.as_str()) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!(
+                        "port `{}` is declared more than once in service `{}`",
+                        port.name, service.name
+                    ),
+                    span,
+                    help: None,
+                });
+            }
+        }
+    }
+
+    // Container services either pull an image or build one
+    for (i, service) in config.services.iter().enumerate() {
+        let source = match &service.source {
+            Source::Container(source) => source,
+            Source::App(_) => continue,
+        };
+        let source_table = service_tables
+            .get(i)
+            .and_then(|(table, _)| table.get("source"))
+            .and_then(|item| item.as_table_like());
+
+        if source.image.is_empty() && source.build.is_none() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "service `{}` source needs an `image` or a `build`",
+                    service.name
+                ),
+                span: service_tables
+                    .get(i)
+                    .and_then(|(table, span)| key_span(*table, "source").or(span.clone())),
+                help: None,
+            });
+        } else if source.build.is_none() && source.tag.is_empty() {
+            // Only built images fall back to `latest`, a pulled image must say which tag it runs
+            let typo = source_table.and_then(|table| {
+                table
+                    .iter()
+                    .map(|(key, _)| key)
+                    .find(|key| *key != "tag" && suggest(key, ["tag"]).is_some())
+            });
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!("service `{}` source is missing `tag`", service.name),
+                span: service_tables
+                    .get(i)
+                    .and_then(|(table, span)| key_span(*table, "source").or(span.clone())),
+                help: typo.map(|typo| format!("rename `{}` to `tag`", typo)),
+            });
+        } else if !source.image.is_empty() && source.build.is_some() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                message: format!(
+                    "service `{}` is built from a Dockerfile, so its `image` is ignored",
+                    service.name
+                ),
+                span: source_table.and_then(|table| key_span(table, "image")),
+                help: Some(format!(
+                    "built images are named `{}/{}`",
+                    config.machine_name, service.name
+                )),
+            });
+        }
+
+        if source.build.is_some() && source.pull_policy.is_some() {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                message: format!(
+                    "service `{}` is built from a Dockerfile, so its `pull_policy` is ignored",
+                    service.name
+                ),
+                span: source_table.and_then(|table| key_span(table, "pull_policy")),
+                help: None,
+            });
+        }
+    }
+
+    // Containers are found through the labels added to the `docker run` of the start command
+    for (i, service) in config.services.iter().enumerate() {
+        let start_command = match &service.source {
+            Source::Container(source) => match &source.start_command {
+                Some(start_command) => start_command,
+                None => continue,
+            },
+            Source::App(_) => continue,
+        };
+        if docker::runs_container(start_command) {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!(
+                "service `{}` has a start_command without `docker run`, so war machine can't label its container and won't find it",
+                service.name
+            ),
+            span: service_tables
+                .get(i)
+                .and_then(|(table, _)| table.get("source"))
+                .and_then(|item| item.as_table_like())
+                .and_then(|table| key_span(table, "start_command")),
+            help: Some(
+                "status, stop, clean, logs and exec only see containers started with `docker run`"
+                    .to_string(),
+            ),
+        });
+    }
+
+    // The structured container fields are only used to build the command when there is none
+    for (i, service) in config.services.iter().enumerate() {
+        let source = match &service.source {
+            Source::Container(source) if source.start_command.is_some() => source,
+            _ => continue,
+        };
+        let source_table = service_tables
+            .get(i)
+            .and_then(|(table, _)| table.get("source"))
+            .and_then(|item| item.as_table_like());
+
+        let ignored_fields: Vec<&str> = [
+            ("env", !source.env.is_empty()),
+            ("volumes", !source.volumes.is_empty()),
+            ("networks", !source.networks.is_empty()),
+            ("command", !source.command.is_empty()),
+            ("entrypoint", source.entrypoint.is_some()),
+            ("healthcheck", source.healthcheck.is_some()),
+            ("user", source.user.is_some()),
+            ("extra_args", !source.extra_args.is_empty()),
+        ]
+        .into_iter()
+        .filter(|(_, is_set)| *is_set)
+        .map(|(field, _)| field)
+        .collect();
+        if ignored_fields.is_empty() {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!(
+                "service `{}` has a start_command, so its {} {} ignored",
+                service.name,
+                ignored_fields
+                    .iter()
+                    .map(|field| format!("`{}`", field))
+                    .collect::<Vec<String>>()
+                    .join(", "),
+                if ignored_fields.len() == 1 {
+                    "is"
+                } else {
+                    "are"
+                }
+            ),
+            span: source_table.and_then(|table| key_span(table, ignored_fields[0])),
+            help: Some(
+                "remove the start_command to build the command from these fields".to_string(),
+            ),
+        });
+    }
+
+    // Services that depend on themselves are reported above
+    for cycle in dependencies::find_cycles(config)
+        .into_iter()
+        .filter(|cycle| cycle.len() > 2)
+    {
+        let span = config
+            .services
+            .iter()
+            .position(|service| service.name == cycle[0])
+            .and_then(|i| service_tables.get(i))
+            .and_then(|(table, _)| table.get("depends_on"))
+            .and_then(|item| item.span());
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: format!("circular dependency: {}", cycle.join(" -> ")),
+            span,
+            help: Some("remove one of these `depends_on` entries".to_string()),
+        });
+    }
+
+    let pre_commands_table = root
+        .get("pre_commands")
+        .and_then(|item| item.as_table_like());
+    let mut pre_command_names: Vec<&String> = config.pre_commands.keys().collect();
+    pre_command_names.sort();
+    for pre_command_name in pre_command_names {
+        if config.commands.contains_key(pre_command_name) {
+            continue;
+        }
+
+        diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            message: format!(
+                "pre command `{}` has no matching entry in [commands] and will never run",
+                pre_command_name
+            ),
+            span: pre_commands_table.and_then(|table| key_span(table, pre_command_name)),
+            help: did_you_mean(suggest(
+                pre_command_name,
+                config.commands.keys().map(|name| name.as_str()),
+            )),
+        });
+    }
+
+    // Ports are only allocated for the placeholders found in the service commands
+    let allocated_ports: HashSet<String> = config
+        .services
+        .iter()
+        .flat_map(machine::ports::get_service_ports_needed)
+        .collect();
+
+    // Placeholders are searched in the source of each string value, for their exact spans
+    let placeholder_regex = Regex::new(r"\$\{port\.([^}]*)\}").unwrap();
+    let placeholder_name_regex = Regex::new(r"^\w+(\.\w+)?$").unwrap();
+    for span in string_spans(root) {
+        for captures in placeholder_regex.captures_iter(&content[span.clone()]) {
+            let placeholder = captures.get(0).unwrap();
+            let placeholder_span = span.start + placeholder.start()..span.start + placeholder.end();
+            let port_name = &captures[1];
+
+            if !placeholder_name_regex.is_match(port_name) {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!(
+                        "invalid port placeholder `{}`, port names can only contain letters, numbers and underscores",
+                        placeholder.as_str()
+                    ),
+                    span: Some(placeholder_span),
+                    help: None,
+                });
+            } else if !allocated_ports.contains(port_name) {
+                // The placeholder is left as is, configs that worked before validation keep working
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    message: format!(
+                        "port `{}` is never allocated, ports are only allocated when a service declares them or a service command uses them",
+                        port_name
+                    ),
+                    span: Some(placeholder_span),
+                    help: did_you_mean(suggest(
+                        port_name,
+                        allocated_ports.iter().map(|port| port.as_str()),
+                    )),
+                });
+            }
+        }
+    }
+
+    // A `${service.ports.<name>}` placeholder belongs to the service whose table it is in
+    let service_placeholder_regex = Regex::new(r"\$\{service\.ports\.([^}]*)\}").unwrap();
+    for (service, (table, _)) in config.services.iter().zip(&service_tables) {
+        for span in string_spans(*table) {
+            for captures in service_placeholder_regex.captures_iter(&content[span.clone()]) {
+                let placeholder = captures.get(0).unwrap();
+                let port_name = &captures[1];
+                if service.ports.iter().any(|port| port.name == port_name) {
+                    continue;
+                }
+
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Error,
+                    message: format!(
+                        "service `{}` does not declare a port named `{}`",
+                        service.name, port_name
+                    ),
+                    span: Some(span.start + placeholder.start()..span.start + placeholder.end()),
+                    help: did_you_mean(suggest(
+                        port_name,
+                        service.ports.iter().map(|port| port.name.as_str()),
+                    ))
+                    .or(Some(format!(
+                        "add {{ name = \"{}\" }} to the `ports` of the service",
+                        port_name
+                    ))),
+                });
+            }
+        }
+    }
+
+    if config.port_range.start > config.port_range.end {
+        diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: format!(
+                "port range starts at {} but ends at {}",
+                config.port_range.start, config.port_range.end
+            ),
+            span: key_span(root, "port_range"),
+            help: None,
+        });
+    }
+
+    let pinned_ports_table = root.get("ports").and_then(|item| item.as_table_like());
+    let mut pinned_ports: Vec<(&String, &u16)> = config.ports.iter().collect();
+    pinned_ports.sort();
+    let mut pinned_by_port: HashMap<u16, &String> = HashMap::new();
+    for (name, port) in pinned_ports {
+        let span = pinned_ports_table.and_then(|table| value_span(table, name));
+
+        if !allocated_ports.contains(name) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Warning,
+                message: format!("pinned port `{}` is not used by any service", name),
+                span: pinned_ports_table.and_then(|table| key_span(table, name)),
+                help: did_you_mean(suggest(
+                    name,
+                    allocated_ports.iter().map(|port| port.as_str()),
+                )),
+            });
+        }
+
+        if config.excluded_ports.contains(port) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!("pinned port `{}` uses excluded port {}", name, port),
+                span: span.clone(),
+                help: None,
+            });
+        }
+
+        if let Some(other_name) = pinned_by_port.insert(*port, name) {
+            diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                message: format!(
+                    "ports `{}` and `{}` are both pinned to {}",
+                    other_name, name, port
+                ),
+                span,
+                help: None,
+            });
+        }
+    }
+
+    for (table_name, commands) in [
+        ("commands", &config.commands),
+        ("pre_commands", &config.pre_commands),
+    ] {
+        let table = root.get(table_name).and_then(|item| item.as_table_like());
+        for (name, command) in commands {
+            if command.contains("${port.") {
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    message: format!(
+                        "port placeholders are not replaced in [{}], `{}` will see them as is",
+                        table_name, name
+                    ),
+                    span: table.and_then(|table| value_span(table, name)),
+                    help: Some(
+                        "expose the port from the service and use the environment variable instead"
+                            .to_string(),
+                    ),
+                });
+            }
+        }
+    }
+}
+
+/// Validates the content of a configuration file, returning every problem found
+pub fn validate(content: &str) -> Vec<Diagnostic> {
+    let document = match ImDocument::parse(content) {
+        Ok(document) => document,
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                message: e.message().to_string(),
+                span: e.span(),
+                help: None,
+            }];
+        }
+    };
+
+    let mut diagnostics = vec![];
+    let root = document.as_table();
+
+    check_structure(root, &mut diagnostics);
+
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        return diagnostics;
+    }
+
+    // Wrong value types are left for serde to report
+    match toml::from_str::<Config>(content) {
+        Ok(config) => check_semantics(&config, root, content, &mut diagnostics),
+        Err(e) => diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            message: e.message().to_string(),
+            span: e.span(),
+            help: None,
+        }),
+    }
+
+    diagnostics
+}
+
+/// Converts a byte offset into a 1-based line and column
+pub fn line_and_column(content: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(content.len());
+    let before = &content[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
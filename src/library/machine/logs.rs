@@ -0,0 +1,32 @@
+use std::path::PathBuf;
+
+use tokio::process::Command;
+
+/// Path of the file where the output of an app service is captured
+pub fn get_log_path(service_name: &str) -> PathBuf {
+    PathBuf::from(".war_machine/logs").join(format!("{}.log", service_name))
+}
+
+/// Path of the file that keeps the process group of an app service, written when war machine
+/// starts it. The file was last modified when the app started.
+pub fn get_pid_path(service_name: &str) -> PathBuf {
+    PathBuf::from(".war_machine/pids").join(format!("{}.pid", service_name))
+}
+
+/// Builds the command that prints the captured logs of an app service
+pub fn logs_command(service_name: &str, follow: bool, tail: Option<&str>) -> Command {
+    let mut command = Command::new("tail");
+
+    // tail defaults to the last 10 lines, we want the whole file unless told otherwise
+    match tail {
+        Some(tail) => command.args(["-n", tail]),
+        None => command.args(["-n", "+1"]),
+    };
+
+    if follow {
+        command.arg("-F");
+    }
+
+    command.arg(get_log_path(service_name));
+    command
+}
@@ -1,2 +1,4 @@
+pub mod logs;
 pub mod ports;
+pub mod registry;
 pub mod state;
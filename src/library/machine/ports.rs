@@ -1,13 +1,17 @@
 use regex::Regex;
 
 use crate::{
-    library::{system::command, utils::logging},
+    library::{
+        machine::registry::{self, LockedRegistry},
+        system::{command, custom_app, docker},
+        utils::logging,
+    },
     models::{
-        config::{Config, Source},
+        config::{Config, PortRange, Service, Source},
         machine_state::MachineState,
     },
 };
-use std::collections::HashSet;
+use std::{collections::HashSet, error::Error, net::TcpListener};
 
 pub async fn replace_ports_in_text(machine_state: &MachineState, text: &str) -> String {
     let mut text = text.to_string();
@@ -19,6 +23,32 @@ pub async fn replace_ports_in_text(machine_state: &MachineState, text: &str) ->
     text
 }
 
+/// Name a port declared in the `ports` of a service is allocated under
+pub fn service_port_key(service_name: &str, port_name: &str) -> String {
+    format!("{}.{}", service_name, port_name)
+}
+
+/// Replaces `${service.ports.<name>}` with the ports the service declared
+pub async fn replace_service_ports_in_text(
+    machine_state: &MachineState,
+    service_name: &str,
+    text: &str,
+) -> String {
+    let mut text = text.to_string();
+    let prefix = format!("{}.", service_name);
+
+    for (key, value) in machine_state.ports.iter() {
+        if let Some(port_name) = key.strip_prefix(&prefix) {
+            text = text.replace(
+                &format!("${{service.ports.{}}}", port_name),
+                value.to_string().as_str(),
+            );
+        }
+    }
+
+    text
+}
+
 fn get_ports_needed_from_str(input: &str) -> Vec<String> {
     // Find any instances of '${port.<word>}' in the commands
     let mut ports_needed: Vec<String> = vec![];
@@ -32,45 +62,86 @@ fn get_ports_needed_from_str(input: &str) -> Vec<String> {
     ports_needed
 }
 
-async fn get_ports_needed(config: &Config) -> Vec<String> {
-    let mut ports_needed: Vec<String> = vec![];
-    for service in &config.services {
-        match &service.source {
-            Source::Container(docker_service) => {
-                if let Some(start_command) = &docker_service.start_command {
-                    let ports = get_ports_needed_from_str(&start_command);
-                    ports_needed.extend(ports);
-                }
-
-                if let Some(stop_command) = &docker_service.stop_command {
-                    let ports = get_ports_needed_from_str(&stop_command);
-                    ports_needed.extend(ports);
-                }
+/// Returns the names of the ports a service declares or references in its commands
+pub fn get_service_ports_needed(service: &Service) -> Vec<String> {
+    let mut ports_needed: Vec<String> = service
+        .ports
+        .iter()
+        .map(|port| service_port_key(&service.name, &port.name))
+        .collect();
+    match &service.source {
+        Source::Container(docker_service) => {
+            if let Some(start_command) = &docker_service.start_command {
+                let ports = get_ports_needed_from_str(start_command);
+                ports_needed.extend(ports);
+            }
+
+            if let Some(stop_command) = &docker_service.stop_command {
+                let ports = get_ports_needed_from_str(stop_command);
+                ports_needed.extend(ports);
+            }
+
+            if let Some(health_check_command) = &docker_service.health_check_command {
+                let ports = get_ports_needed_from_str(health_check_command);
+                ports_needed.extend(ports);
+            }
+
+            let mut structured_values: Vec<&String> = docker_service.env.values().collect();
+            structured_values.extend(&docker_service.volumes);
+            structured_values.extend(&docker_service.networks);
+            structured_values.extend(&docker_service.command);
+            structured_values.extend(&docker_service.extra_args);
+            structured_values.extend(&docker_service.entrypoint);
+            if let Some(healthcheck) = &docker_service.healthcheck {
+                structured_values.push(&healthcheck.command);
+            }
+            if let Some(build) = &docker_service.build {
+                structured_values.extend(build.args.values());
             }
-            Source::App(app_service) => {
-                let install_ports = get_ports_needed_from_str(&app_service.install_command);
-                ports_needed.extend(install_ports);
-
-                let health_check_ports =
-                    get_ports_needed_from_str(&app_service.health_check_command);
-                ports_needed.extend(health_check_ports);
-
-                let start_ports = get_ports_needed_from_str(&app_service.start_command);
-                ports_needed.extend(start_ports);
-
-                if let Some(stop_command) = &app_service.stop_command {
-                    let stop_ports = get_ports_needed_from_str(&stop_command);
-                    ports_needed.extend(stop_ports);
-                }
-
-                if let Some(clean_command) = &app_service.clean_command {
-                    let clean_ports = get_ports_needed_from_str(&clean_command);
-                    ports_needed.extend(clean_ports);
-                }
+            for value in structured_values {
+                ports_needed.extend(get_ports_needed_from_str(value));
+            }
+        }
+        Source::App(app_service) => {
+            let install_ports = get_ports_needed_from_str(&app_service.install_command);
+            ports_needed.extend(install_ports);
+
+            let health_check_ports = get_ports_needed_from_str(&app_service.health_check_command);
+            ports_needed.extend(health_check_ports);
+
+            let start_ports = get_ports_needed_from_str(&app_service.start_command);
+            ports_needed.extend(start_ports);
+
+            if let Some(stop_command) = &app_service.stop_command {
+                let stop_ports = get_ports_needed_from_str(stop_command);
+                ports_needed.extend(stop_ports);
+            }
+
+            if let Some(clean_command) = &app_service.clean_command {
+                let clean_ports = get_ports_needed_from_str(clean_command);
+                ports_needed.extend(clean_ports);
             }
         }
     }
 
+    if let Some(ready) = &service.ready {
+        for probe in [&ready.tcp, &ready.http, &ready.exec].into_iter().flatten() {
+            ports_needed.extend(get_ports_needed_from_str(probe));
+        }
+    }
+
+    // Drop duplicates while keeping the order in which they appear
+    let mut seen = HashSet::new();
+    ports_needed.retain(|port| seen.insert(port.clone()));
+    ports_needed
+}
+
+async fn get_ports_needed(config: &Config) -> Vec<String> {
+    let mut ports_needed: Vec<String> = vec![];
+    for service in &config.services {
+        ports_needed.extend(get_service_ports_needed(service));
+    }
+
     // Drop all duplicate port names
     let ports_needed: Vec<String> = ports_needed
         .into_iter()
@@ -80,108 +151,356 @@ async fn get_ports_needed(config: &Config) -> Vec<String> {
     ports_needed
 }
 
-pub async fn get_ports(length: usize) -> Vec<i32> {
-    // Start at 49000 and increment
-    let mut i: i32 = 49000;
-    let mut ports: Vec<i32> = vec![];
-    while ports.len() < length as usize {
-        let nc_result = command::run(format!("nc -zv 127.0.0.1 {}", i).as_str()).await;
-        match nc_result {
-            Ok(_) => {
-                // Connection successful, port is in use
-            }
-            Err(_) => {
-                // Connection failed, port is not in use
-                ports.push(i);
-            }
+/// Whether nothing is listening on the port, checked by binding it
+pub fn is_port_free(port: u16) -> bool {
+    ["0.0.0.0", "127.0.0.1"]
+        .iter()
+        .all(|host| TcpListener::bind((*host, port)).is_ok())
+}
+
+/// Picks `length` free ports from the range, skipping the excluded ones
+pub fn get_ports(
+    length: usize,
+    port_range: &PortRange,
+    excluded_ports: &HashSet<u16>,
+) -> Result<Vec<u16>, Box<dyn Error>> {
+    let mut ports: Vec<u16> = vec![];
+    if length == 0 {
+        return Ok(ports);
+    }
+
+    for port in port_range.start..=port_range.end {
+        if excluded_ports.contains(&port) || !is_port_free(port) {
+            continue;
+        }
+        ports.push(port);
+        if ports.len() == length {
+            return Ok(ports);
         }
-        i += 1;
     }
-    ports
+
+    Err(Box::from(format!(
+        "only {} of the {} ports needed are free between {} and {}",
+        ports.len(),
+        length,
+        port_range.start,
+        port_range.end
+    )))
+}
+
+/// Port inside the container that an allocated port is published to, for declared ports
+fn container_port(config: &Config, key: &str) -> Option<u16> {
+    config.services.iter().find_map(|service| {
+        service
+            .ports
+            .iter()
+            .find(|port| service_port_key(&service.name, &port.name) == key)
+            .and_then(|port| port.container)
+    })
 }
 
-pub async fn print_port_map_box(machine_state: &MachineState) {
+pub async fn print_port_map_box(machine_state: &MachineState, config: &Config) {
     // If there are no ports, don't print anything
     if machine_state.ports.is_empty() {
         return;
     }
 
-    logging::nl().await;
-    logging::print_color(logging::BG_MAGENTA, " Port map ").await;
-
-    // We need to find the longest key so we can align the table
-    let longest_name = machine_state.ports.keys().max_by_key(|key| key.len());
-    let longest_name_len = longest_name.map_or(0, |key| key.len());
-
-    let longest_port = machine_state
-        .ports
-        .values()
-        .max_by_key(|value| value.to_string().len());
-    let longest_port_len = longest_port.map_or(0, |value| value.to_string().len());
-
-    let name_margin = "─".to_string().repeat(longest_name_len);
-    let port_margin = "─".to_string().repeat(longest_port_len);
-
-    // Using longer | character for sides: │
-    logging::print_color(
-        logging::NC,
-        &format!("┌─{}─┬─{}─┐", name_margin, port_margin),
-    )
-    .await;
-    logging::print_color(
-        logging::NC,
-        &format!(
-            "│ {:<name_width$} │ {:<port_width$} │",
-            "Name",
-            "Port",
-            name_width = longest_name_len,
-            port_width = longest_port_len
-        ),
-    )
-    .await;
-    logging::print_color(
-        logging::NC,
-        &format!("├─{}─┼─{}─┤", name_margin, port_margin),
-    )
-    .await;
-
     // Sort by name
     let mut sorted_ports: Vec<_> = machine_state.ports.iter().collect();
     sorted_ports.sort_by_key(|(key, _)| *key);
 
-    for (key, value) in sorted_ports {
-        let parsed_name = format!("{:<width$}", key, width = longest_name_len);
-        let parsed_port = format!("{:<width$}", value, width = longest_port_len);
+    let mut header = vec!["Name".to_string(), "Port".to_string()];
+    let mut rows: Vec<Vec<String>> = sorted_ports
+        .iter()
+        .map(|(key, value)| vec![key.to_string(), value.to_string()])
+        .collect();
+
+    // Only show where the ports go when some service declares it
+    let container_ports: Vec<Option<u16>> = sorted_ports
+        .iter()
+        .map(|(key, _)| container_port(config, key))
+        .collect();
+    if container_ports.iter().any(|port| port.is_some()) {
+        header.push("Container".to_string());
+        for (row, port) in rows.iter_mut().zip(container_ports) {
+            row.push(port.map_or("-".to_string(), |port| port.to_string()));
+        }
+    }
+
+    logging::print_table(logging::BG_MAGENTA, " Port map ", &header, &rows).await;
+}
+
+/// Ports held by this machine: published by its running containers, or used by app services
+/// that pass their health check
+async fn get_own_ports(
+    machine_state: &MachineState,
+    config: &Config,
+    busy_names: &HashSet<&String>,
+) -> HashSet<u16> {
+    let mut own_ports = docker::get_published_ports(config)
+        .await
+        .unwrap_or_default();
+
+    for service in &config.services {
+        let app_source = match &service.source {
+            Source::App(app_source) => app_source,
+            Source::Container(_) => continue,
+        };
+        let service_ports: Vec<String> = get_service_ports_needed(service)
+            .into_iter()
+            .filter(|name| busy_names.contains(name))
+            .collect();
+        if service_ports.is_empty() {
+            continue;
+        }
 
-        logging::print_color(
-            logging::NC,
-            &format!("│ {} │ {} │", parsed_name, parsed_port),
+        let health_check_command = custom_app::replace_placeholders(
+            machine_state,
+            config,
+            &app_source.health_check_command,
+            &service.name,
         )
         .await;
+        if command::run(&health_check_command).await.is_ok() {
+            own_ports.extend(
+                service_ports
+                    .iter()
+                    .filter_map(|name| machine_state.ports.get(name))
+                    .filter_map(|port| u16::try_from(*port).ok()),
+            );
+        }
     }
 
-    logging::print_color(
-        logging::NC,
-        &format!("└─{}─┴─{}─┘", name_margin, port_margin),
-    )
-    .await;
+    own_ports
 }
 
-pub async fn produce_port_map(machine_state: &mut MachineState, config: &Config) {
+/// Keeps the ports of the state that are still needed and still fit the range, the exclusions
+/// and the pins, adds the pinned ports and returns the names of the ports left to pick
+async fn keep_valid_ports(machine_state: &mut MachineState, config: &Config) -> Vec<String> {
     let mut ports_needed = get_ports_needed(config).await;
+    ports_needed.sort();
 
     // Drop any ports in the state that are not needed
     machine_state
         .ports
         .retain(|port, _| ports_needed.contains(port));
 
-    // Drop any ports_needed that the state already has
-    ports_needed.retain(|port| !machine_state.ports.contains_key(port));
+    let pinned_ports: HashSet<u16> = config.ports.values().copied().collect();
+    let excluded_ports: HashSet<u16> = config.excluded_ports.iter().copied().collect();
+
+    // Pick again the ports that no longer fit the range, the exclusions or the pins
+    machine_state.ports.retain(|name, port| {
+        let port = match u16::try_from(*port) {
+            Ok(port) => port,
+            Err(_) => return false,
+        };
+        match config.ports.get(name) {
+            Some(pinned_port) => *pinned_port == port,
+            None => {
+                (config.port_range.start..=config.port_range.end).contains(&port)
+                    && !excluded_ports.contains(&port)
+                    && !pinned_ports.contains(&port)
+            }
+        }
+    });
+
+    for name in &ports_needed {
+        if let Some(pinned_port) = config.ports.get(name) {
+            machine_state
+                .ports
+                .insert(name.to_string(), i32::from(*pinned_port));
+        }
+    }
+
+    ports_needed
+}
+
+/// Ports of the state that are taken by another process, by name
+fn busy_ports(machine_state: &MachineState) -> Vec<(String, u16)> {
+    let mut busy_ports: Vec<(String, u16)> = machine_state
+        .ports
+        .iter()
+        .filter_map(|(name, port)| Some((name.to_string(), u16::try_from(*port).ok()?)))
+        .filter(|(_, port)| !is_port_free(*port))
+        .collect();
+    busy_ports.sort();
+    busy_ports
+}
+
+/// Removes the ports that another project registered from the state, pinned ones are kept
+fn remove_foreign_ports(
+    machine_state: &mut MachineState,
+    config: &Config,
+    foreign_ports: &HashSet<u16>,
+) -> Vec<(String, u16)> {
+    let mut claimed_ports: Vec<(String, u16)> = machine_state
+        .ports
+        .iter()
+        .filter_map(|(name, port)| Some((name.to_string(), u16::try_from(*port).ok()?)))
+        .filter(|(name, port)| !config.ports.contains_key(name) && foreign_ports.contains(port))
+        .collect();
+    claimed_ports.sort();
+    for (name, _) in &claimed_ports {
+        machine_state.ports.remove(name);
+    }
+    claimed_ports
+}
+
+/// Picks a port for each of the names the state has no port for yet
+fn pick_ports(
+    machine_state: &mut MachineState,
+    config: &Config,
+    ports_needed: &[String],
+    foreign_ports: &HashSet<u16>,
+) -> Result<(), Box<dyn Error>> {
+    let ports_needed: Vec<&String> = ports_needed
+        .iter()
+        .filter(|port| !machine_state.ports.contains_key(*port))
+        .collect();
+
+    let mut taken_ports: HashSet<u16> = config.excluded_ports.iter().copied().collect();
+    taken_ports.extend(config.ports.values());
+    taken_ports.extend(foreign_ports);
+    taken_ports.extend(
+        machine_state
+            .ports
+            .values()
+            .filter_map(|port| u16::try_from(*port).ok()),
+    );
+
+    let ports = get_ports(ports_needed.len(), &config.port_range, &taken_ports)?;
+    for (name, port) in ports_needed.iter().zip(ports.iter()) {
+        machine_state
+            .ports
+            .insert(name.to_string(), i32::from(*port));
+    }
+    Ok(())
+}
+
+pub async fn produce_port_map(machine_state: &mut MachineState, config: &Config) {
+    let ports_needed = keep_valid_ports(machine_state, config).await;
+
+    // Ports from earlier runs may have been taken by another process since
+    let busy_ports = busy_ports(machine_state);
+
+    let mut moved_ports: Vec<(String, u16, &str)> = vec![];
+    let mut conflicts: Vec<String> = vec![];
+    if !busy_ports.is_empty() {
+        let busy_names: HashSet<&String> = busy_ports.iter().map(|(name, _)| name).collect();
+        let own_ports = get_own_ports(machine_state, config, &busy_names).await;
+
+        for (name, port) in busy_ports {
+            if own_ports.contains(&port) {
+                continue;
+            }
+            if config.ports.contains_key(&name) {
+                conflicts.push(format!("{} (pinned for `{}`)", port, name));
+            } else {
+                machine_state.ports.remove(&name);
+                moved_ports.push((name, port, "is used by another process"));
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        logging::error(&format!(
+            "🛑 These pinned ports are already in use: {}. Stop what is using them or change [ports] in the configuration",
+            conflicts.join(", ")
+        ))
+        .await;
+        std::process::exit(1);
+    }
+
+    // Other projects on this machine keep their ports even while they are stopped. The
+    // registry stays locked until it is saved so projects starting at the same time don't
+    // pick the same ports.
+    let project_path = registry::project_path();
+    let mut locked_registry = match LockedRegistry::open().await {
+        Ok(locked_registry) => Some(locked_registry),
+        Err(e) => {
+            logging::warn(&format!(
+                "⚠️  Ports are not coordinated with other projects: {}",
+                e
+            ))
+            .await;
+            None
+        }
+    };
+    let foreign_ports = locked_registry
+        .as_ref()
+        .map(|locked_registry| locked_registry.foreign_ports(&project_path))
+        .unwrap_or_default();
+
+    for (name, port) in remove_foreign_ports(machine_state, config, &foreign_ports) {
+        moved_ports.push((name, port, "is registered by another project"));
+    }
+
+    if let Err(e) = pick_ports(machine_state, config, &ports_needed, &foreign_ports) {
+        logging::error(&format!("🛑 Failed to allocate ports: {}", e)).await;
+        std::process::exit(1);
+    }
+
+    for (name, old_port, reason) in moved_ports {
+        logging::warn(&format!(
+            "⚠️  Port {} of `{}` {}, moved it to {}",
+            old_port, name, reason, machine_state.ports[&name]
+        ))
+        .await;
+    }
+
+    if let Some(locked_registry) = &mut locked_registry {
+        locked_registry.register(&project_path, &config.machine_name, machine_state);
+        if let Err(e) = locked_registry.save() {
+            logging::warn(&format!("⚠️  Failed to save the port registry: {}", e)).await;
+        }
+    }
+
+    print_port_map_box(machine_state, config).await;
+}
+
+/// The port map `produce_port_map` would produce, without running anything or locking and
+/// saving the registry. Whether a busy port is held by this project is only known by asking
+/// its services, so busy ports are kept. What `produce_port_map` would stop on or move is
+/// returned instead of logged.
+pub async fn preview_port_map(machine_state: &mut MachineState, config: &Config) -> Vec<String> {
+    let ports_needed = keep_valid_ports(machine_state, config).await;
+
+    let mut conflicts = vec![];
+    for (name, port) in busy_ports(machine_state) {
+        if config.ports.contains_key(&name) {
+            conflicts.push(format!(
+                "# port {} pinned for `{}` is in use, wm stops unless a service of this project holds it",
+                port, name
+            ));
+        } else {
+            conflicts.push(format!(
+                "# port {} of `{}` is in use, it moves unless a service of this project holds it",
+                port, name
+            ));
+        }
+    }
+
+    let foreign_ports = match registry::load() {
+        Ok(port_registry) => registry::foreign_ports(&port_registry, &registry::project_path()),
+        Err(e) => {
+            conflicts.push(format!(
+                "# ports are not coordinated with other projects: {}",
+                e
+            ));
+            HashSet::new()
+        }
+    };
+    for (name, port) in remove_foreign_ports(machine_state, config, &foreign_ports) {
+        conflicts.push(format!(
+            "# port {} of `{}` is registered by another project, it moves",
+            port, name
+        ));
+    }
 
-    let ports = get_ports(ports_needed.len()).await;
-    for (i, port) in ports_needed.iter().zip(ports.iter()) {
-        machine_state.ports.insert(i.to_string(), *port);
+    if let Err(e) = pick_ports(machine_state, config, &ports_needed, &foreign_ports) {
+        conflicts.push(format!("# failed to allocate ports, wm stops: {}", e));
     }
 
-    print_port_map_box(machine_state).await;
+    print_port_map_box(machine_state, config).await;
+    conflicts
 }
@@ -0,0 +1,167 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use nix::errno::Errno;
+use nix::fcntl::{Flock, FlockArg};
+
+use crate::models::machine_state::MachineState;
+use crate::models::port_registry::{PortRegistry, RegisteredPort};
+
+/// How long to wait for another project to release the registry
+const LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// `$XDG_STATE_HOME/wm`, or `~/.local/state/wm` when it isn't set
+pub fn registry_dir() -> Result<PathBuf, Box<dyn Error>> {
+    if let Ok(state_home) = std::env::var("XDG_STATE_HOME") {
+        if !state_home.is_empty() {
+            return Ok(PathBuf::from(state_home).join("wm"));
+        }
+    }
+
+    match std::env::var("HOME") {
+        Ok(home) if !home.is_empty() => Ok(PathBuf::from(home).join(".local/state/wm")),
+        _ => Err(Box::from("neither XDG_STATE_HOME nor HOME is set")),
+    }
+}
+
+/// Path that identifies the current project in the registry
+pub fn project_path() -> String {
+    let current_dir = std::env::current_dir().unwrap();
+    current_dir
+        .canonicalize()
+        .unwrap_or(current_dir)
+        .display()
+        .to_string()
+}
+
+fn read(path: &Path) -> Result<PortRegistry, Box<dyn Error>> {
+    let mut registry = match std::fs::read_to_string(path) {
+        Ok(contents) if contents.trim().is_empty() => PortRegistry::default(),
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => PortRegistry::default(),
+        Err(e) => return Err(Box::new(e)),
+    };
+
+    // Forget the projects that were deleted
+    registry
+        .ports
+        .retain(|port| Path::new(&port.project_path).exists());
+
+    Ok(registry)
+}
+
+/// Ports owned by other projects
+pub fn foreign_ports(registry: &PortRegistry, project_path: &str) -> HashSet<u16> {
+    registry
+        .ports
+        .iter()
+        .filter(|port| port.project_path != project_path)
+        .map(|port| port.port)
+        .collect()
+}
+
+/// Takes the lock without blocking, retrying until another project releases it or the
+/// timeout passes
+fn lock(mut lock_file: File) -> Result<Flock<File>, String> {
+    let started_at = Instant::now();
+    loop {
+        match Flock::lock(lock_file, FlockArg::LockExclusiveNonblock) {
+            Ok(lock) => return Ok(lock),
+            Err((file, Errno::EWOULDBLOCK)) if started_at.elapsed() < LOCK_TIMEOUT => {
+                lock_file = file;
+                std::thread::sleep(LOCK_RETRY_INTERVAL);
+            }
+            Err((_, Errno::EWOULDBLOCK)) => {
+                return Err(format!(
+                    "timed out after {}s waiting for another project to release the port registry",
+                    LOCK_TIMEOUT.as_secs()
+                ))
+            }
+            Err((_, e)) => return Err(format!("failed to lock the port registry: {}", e)),
+        }
+    }
+}
+
+/// Reads the registry without locking it
+pub fn load() -> Result<PortRegistry, Box<dyn Error>> {
+    read(&registry_dir()?.join("ports.json"))
+}
+
+/// The registry, locked until this is dropped so other projects wait for us to save it. Keep
+/// it only for the read-modify-write of the registry.
+pub struct LockedRegistry {
+    pub registry: PortRegistry,
+    path: PathBuf,
+    _lock: Flock<File>,
+}
+
+impl LockedRegistry {
+    /// Waiting for the lock happens on a blocking thread so the runtime keeps going
+    pub async fn open() -> Result<LockedRegistry, Box<dyn Error>> {
+        let dir = registry_dir()?;
+        std::fs::create_dir_all(&dir)?;
+
+        let lock_file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join("ports.lock"))?;
+        let lock = tokio::task::spawn_blocking(move || lock(lock_file)).await??;
+
+        let path = dir.join("ports.json");
+        let registry = read(&path)?;
+
+        Ok(LockedRegistry {
+            registry,
+            path,
+            _lock: lock,
+        })
+    }
+
+    /// Ports owned by other projects
+    pub fn foreign_ports(&self, project_path: &str) -> HashSet<u16> {
+        foreign_ports(&self.registry, project_path)
+    }
+
+    /// Replaces the ports of the project with the ones in the machine state
+    pub fn register(
+        &mut self,
+        project_path: &str,
+        machine_name: &str,
+        machine_state: &MachineState,
+    ) {
+        self.registry
+            .ports
+            .retain(|port| port.project_path != project_path);
+
+        for (name, port) in &machine_state.ports {
+            if let Ok(port) = u16::try_from(*port) {
+                self.registry.ports.push(RegisteredPort {
+                    port,
+                    name: name.to_string(),
+                    machine_name: machine_name.to_string(),
+                    project_path: project_path.to_string(),
+                });
+            }
+        }
+
+        self.registry.ports.sort_by(|a, b| {
+            (a.port, &a.project_path, &a.name).cmp(&(b.port, &b.project_path, &b.name))
+        });
+    }
+
+    /// Writes the registry to a temporary file first so readers never see half of it
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let temporary_path = self.path.with_extension("json.tmp");
+        std::fs::write(
+            &temporary_path,
+            serde_json::to_string_pretty(&self.registry)?,
+        )?;
+        std::fs::rename(&temporary_path, &self.path)?;
+        Ok(())
+    }
+}
@@ -5,9 +5,9 @@ use tokio::fs::OpenOptions;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
 use crate::models::config::Config;
-use crate::models::machine_state::MachineState;
+use crate::models::machine_state::{MachineState, ServiceDefinition};
 
-use super::ports::produce_port_map;
+use super::ports::{preview_port_map, produce_port_map};
 
 pub async fn get_machine_state() -> MachineState {
     let file_read_result = OpenOptions::new()
@@ -46,6 +46,22 @@ pub async fn save_machine_state(state: &MachineState) {
     file.flush().await.unwrap();
 }
 
+/// Records what the services were started from, the rest of the state is left as it is
+pub async fn save_definitions(definitions: HashMap<String, ServiceDefinition>) {
+    let mut machine_state = get_machine_state().await;
+    machine_state.containers.extend(definitions);
+    save_machine_state(&machine_state).await;
+}
+
+/// Forgets the definitions of services whose containers were removed
+pub async fn forget_definitions(names: &[&str]) {
+    let mut machine_state = get_machine_state().await;
+    machine_state
+        .containers
+        .retain(|name, _| !names.contains(&name.as_str()));
+    save_machine_state(&machine_state).await;
+}
+
 pub async fn create_war_machine_dir(path: &PathBuf) {
     std::fs::create_dir_all(path).unwrap();
 
@@ -62,6 +78,20 @@ pub async fn create_war_machine_dir(path: &PathBuf) {
     file.flush().await.unwrap();
 }
 
+/// The saved state, in clean mode only with the pinned ports so the containers about to be
+/// cleaned don't count as a conflict
+async fn starting_state(config: &Config, clean_mode: bool) -> MachineState {
+    let mut machine_state = get_machine_state().await;
+
+    if clean_mode {
+        machine_state.ports.retain(|name, port| {
+            config.ports.get(name).map(|port| i32::from(*port)) == Some(*port)
+        });
+    }
+
+    machine_state
+}
+
 /// Checks if the .war_machine dir exists, if not it creates it
 pub async fn check(config: &Config, clean_mode: bool) -> MachineState {
     let war_machine_dir = std::env::current_dir().unwrap().join(".war_machine");
@@ -69,14 +99,18 @@ pub async fn check(config: &Config, clean_mode: bool) -> MachineState {
         create_war_machine_dir(&war_machine_dir).await;
     }
 
-    let mut machine_state = get_machine_state().await;
-
-    if clean_mode {
-        machine_state.ports.clear();
-    }
-
+    let mut machine_state = starting_state(config, clean_mode).await;
     produce_port_map(&mut machine_state, config).await;
 
     save_machine_state(&machine_state).await;
     machine_state
 }
+
+/// The state `check` would produce. Only state.json and the port registry are read: nothing
+/// runs, nothing is locked or saved. The port conflicts `check` would stop on or resolve are
+/// returned along with it.
+pub async fn preview(config: &Config, clean_mode: bool) -> (MachineState, Vec<String>) {
+    let mut machine_state = starting_state(config, clean_mode).await;
+    let port_conflicts = preview_port_map(&mut machine_state, config).await;
+    (machine_state, port_conflicts)
+}
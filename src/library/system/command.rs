@@ -1,6 +1,14 @@
-use std::error::Error;
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    path::Path,
+    process::Stdio,
+};
 
-use tokio::{process::Command, sync::watch};
+use tokio::{
+    process::{Child, Command},
+    sync::watch,
+};
 
 use crate::library::utils::logging;
 
@@ -21,15 +29,65 @@ pub async fn run(command_str: &str) -> Result<String, Box<dyn Error>> {
     }
 }
 
+/// Where the output of spawned commands should go, stderr when the logs go there too
+pub fn stdout() -> Stdio {
+    if logging::is_logging_to_stderr() {
+        Stdio::from(std::io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
+
 pub async fn spawn(command_str: &str) -> Result<(), Box<dyn Error>> {
-    let (tx, mut rx) = watch::channel(false);
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command_str)
+        .stdout(stdout())
+        .spawn()
+        .expect("Failed to run command");
+
+    wait(child).await
+}
+
+/// Same as `spawn` but appends the stdout and stderr of the command to the file at `log_path`.
+/// Processes left running in the background by the command keep writing to the same file.
+/// The command runs in its own process group, whose id is written to `pid_path`, so the
+/// processes it leaves running can be found later.
+pub async fn spawn_with_log(
+    command_str: &str,
+    log_path: &Path,
+    pid_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    for path in [log_path, pid_path] {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+
+    let log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)?;
 
     let child = Command::new("sh")
         .arg("-c")
-        .arg(&command_str)
+        .arg(command_str)
+        .stdout(Stdio::from(log_file.try_clone()?))
+        .stderr(Stdio::from(log_file))
+        .process_group(0)
         .spawn()
         .expect("Failed to run command");
 
+    if let Some(pid) = child.id() {
+        fs::write(pid_path, pid.to_string())?;
+    }
+
+    wait(child).await
+}
+
+async fn wait(child: Child) -> Result<(), Box<dyn Error>> {
+    let (tx, mut rx) = watch::channel(false);
+
     let pid: u32 = child.id().expect("Failed to get command pid");
     let handle = child.wait_with_output();
 
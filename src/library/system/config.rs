@@ -1,32 +1,115 @@
-use std::path::PathBuf;
+use std::error::Error;
+use std::path::{Path, PathBuf};
 
 use tokio::fs::{self};
 
-use crate::{library::utils::logging, models::config::Config};
+use crate::{
+    library::{config::validation, utils::logging},
+    models::{
+        config::Config,
+        diagnostic::{Diagnostic, Severity},
+        lockfile::Lockfile,
+    },
+};
+
+/// Prints the diagnostics as `file:line:column: message`
+pub async fn print_diagnostics(config_path: &Path, content: &str, diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics {
+        let location = match &diagnostic.span {
+            Some(span) => {
+                let (line, column) = validation::line_and_column(content, span.start);
+                format!("{}:{}:{}", config_path.display(), line, column)
+            }
+            None => config_path.display().to_string(),
+        };
+
+        let message = format!("{}: {}", location, diagnostic.message);
+        // Diagnostics go to stderr so they never end up in the output of `status` or `env`
+        match diagnostic.severity {
+            Severity::Error => logging::error_stderr(&message).await,
+            Severity::Warning => logging::warn_stderr(&message).await,
+        }
+
+        if let Some(help) = &diagnostic.help {
+            logging::print_color_stderr(logging::NC, &format!("        help: {}", help)).await;
+        }
+    }
+}
 
 /// Checks that the config file is set up correctly
 pub async fn parse(config_path: PathBuf) -> Config {
     // Read the TOML file
-    let toml_content = fs::read_to_string(config_path).await;
+    let toml_content = fs::read_to_string(&config_path).await;
 
     let toml_content = match toml_content {
         Ok(content) => content,
         Err(e) => {
-            logging::error(&format!("Error reading config file: {}", e)).await;
+            logging::error_stderr(&format!("Error reading config file: {}", e)).await;
             std::process::exit(1);
         }
     };
 
+    let diagnostics = validation::validate(&toml_content);
+    print_diagnostics(&config_path, &toml_content, &diagnostics).await;
+
+    if diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error)
+    {
+        logging::error_stderr("Error parsing config file").await;
+        std::process::exit(1);
+    }
+
     // Parse the TOML content
     let config_result = toml::from_str(&toml_content);
 
-    let config: Config = match config_result {
+    let mut config: Config = match config_result {
         Ok(parsed_config) => parsed_config,
         Err(e) => {
-            logging::error(&format!("Error parsing config file: {}", e)).await;
+            logging::error_stderr(&format!("Error parsing config file: {}", e)).await;
             std::process::exit(1);
         }
     };
 
+    config.lock = parse_lock(&config_path).await;
+    config.config_path = config_path;
+
     config
 }
+
+const LOCK_HEADER: &str =
+    "# Generated by `wm lock`, commit it so everyone runs the same images\n\n";
+
+/// The lockfile that belongs to the config file, `war_machine.lock` for `war_machine.toml`
+pub fn lock_path(config_path: &Path) -> PathBuf {
+    config_path.with_extension("lock")
+}
+
+/// Reads the lockfile of the config file, which is empty when there is none
+pub async fn parse_lock(config_path: &Path) -> Lockfile {
+    let lock_path = lock_path(config_path);
+    let lock_content = match fs::read_to_string(&lock_path).await {
+        Ok(content) => content,
+        Err(_) => return Lockfile::default(),
+    };
+
+    match toml::from_str(&lock_content) {
+        Ok(lockfile) => lockfile,
+        Err(e) => {
+            logging::error(&format!(
+                "Error parsing {}: {}",
+                lock_path.display(),
+                e.message()
+            ))
+            .await;
+            logging::error("Fix it or remove it and run `wm lock` again").await;
+            std::process::exit(1);
+        }
+    }
+}
+
+pub async fn write_lock(config_path: &Path, lockfile: &Lockfile) -> Result<(), Box<dyn Error>> {
+    let content = format!("{}{}", LOCK_HEADER, toml::to_string(lockfile)?);
+    fs::write(lock_path(config_path), content).await?;
+    Ok(())
+}
@@ -1,8 +1,18 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    process::ExitStatus,
+    time::SystemTime,
+};
+
+use tokio::process::Command;
+
 use crate::{
     library::{machine, utils::logging},
     models::{
         config::{AppSource, Config},
         machine_state::MachineState,
+        service_status::ServiceStatus,
     },
 };
 
@@ -18,6 +28,8 @@ pub async fn replace_placeholders(
     let mut new_command = command.to_string();
     new_command = new_command.replace("${machine_name}", &config.machine_name);
     new_command = new_command.replace("${service.name}", name);
+    new_command =
+        machine::ports::replace_service_ports_in_text(machine_state, name, &new_command).await;
     new_command = machine::ports::replace_ports_in_text(&machine_state, &new_command).await;
     new_command
 }
@@ -79,14 +91,220 @@ pub async fn clean_service(
     }
 }
 
-pub async fn start_service(
+pub async fn stop_service(
     machine_state: &MachineState,
     config: &Config,
     name: &str,
     source: &AppSource,
-    clean_mode: bool,
     fail_fast: bool,
 ) {
+    let stop_command = match &source.stop_command {
+        Some(stop_command) => replace_placeholders(machine_state, config, stop_command, name).await,
+        None => {
+            logging::warn(&format!(
+                "{} has no stop_command configured, leaving it running",
+                name
+            ))
+            .await;
+            return;
+        }
+    };
+
+    let stop_results = command::spawn(&stop_command).await;
+
+    match stop_results {
+        Ok(_) => {
+            let _ = std::fs::remove_file(machine::logs::get_pid_path(name));
+            logging::info(&format!("🛑 {} stopped", name)).await;
+        }
+        Err(e) => {
+            logging::error(&format!("Failed to stop {}", name)).await;
+            logging::error(&e.to_string()).await;
+
+            if fail_fast {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Gets the status of the app by running its health check. Ports are left for the caller to fill.
+pub async fn get_service_status(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+) -> ServiceStatus {
+    let health_check_command =
+        replace_placeholders(machine_state, config, &source.health_check_command, name).await;
+
+    let healthy = command::run(&health_check_command).await.is_ok();
+
+    // Apps started before war machine recorded them have no pid file
+    let pid_path = machine::logs::get_pid_path(name);
+    let pid = match std::fs::read_to_string(&pid_path) {
+        Ok(pid) => pid.trim().parse::<u32>().ok(),
+        Err(_) => None,
+    };
+    let pid = match pid {
+        Some(pid) if process_group_alive(pid).await => Some(pid),
+        _ => None,
+    };
+
+    let uptime = if healthy {
+        std::fs::metadata(&pid_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|started| SystemTime::now().duration_since(started).ok())
+            .map(|uptime| format_uptime(uptime.as_secs()))
+    } else {
+        None
+    };
+
+    ServiceStatus {
+        name: name.to_string(),
+        running: healthy,
+        health: Some(if healthy { "healthy" } else { "unhealthy" }.to_string()),
+        container_id: None,
+        pid,
+        image: None,
+        ports: HashMap::new(),
+        uptime,
+        error: None,
+    }
+}
+
+/// Whether any process of the group war machine started the app in is still running
+async fn process_group_alive(pgid: u32) -> bool {
+    Command::new("kill")
+        .args(["-0", "--", &format!("-{}", pgid)])
+        .output()
+        .await
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// Uptime in the words `docker ps` uses, like `5 minutes`
+pub fn format_uptime(seconds: u64) -> String {
+    let (value, unit) = match seconds {
+        0..=59 => (seconds, "second"),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    if value == 1 {
+        format!("1 {}", unit)
+    } else {
+        format!("{} {}s", value, unit)
+    }
+}
+
+/// Runs a command with the exposed values of the app in its environment
+pub async fn exec(
+    args: &[String],
+    exposed_values: &[(String, String)],
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let status = Command::new(&args[0])
+        .args(&args[1..])
+        .envs(exposed_values.iter().cloned())
+        .status()
+        .await?;
+    Ok(status)
+}
+
+/// What the app is installed and started with, with the placeholders replaced. The app has
+/// to be restarted when any of these change.
+pub async fn definition_fields(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+) -> BTreeMap<String, String> {
+    BTreeMap::from([
+        (
+            "install_command".to_string(),
+            replace_placeholders(machine_state, config, &source.install_command, name).await,
+        ),
+        (
+            "start_command".to_string(),
+            replace_placeholders(machine_state, config, &source.start_command, name).await,
+        ),
+    ])
+}
+
+/// What `clean_service` runs, for dry runs
+pub async fn clean_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+) -> Vec<String> {
+    match &source.clean_command {
+        Some(clean_command) => {
+            vec![replace_placeholders(machine_state, config, clean_command, name).await]
+        }
+        None => vec![],
+    }
+}
+
+/// What `stop_service` runs, for dry runs
+pub async fn stop_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+) -> Vec<String> {
+    match &source.stop_command {
+        Some(stop_command) => {
+            vec![replace_placeholders(machine_state, config, stop_command, name).await]
+        }
+        None => vec![format!(
+            "# {} has no stop_command, it is left running",
+            name
+        )],
+    }
+}
+
+/// What `start_service` runs, for dry runs
+pub async fn start_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+    clean_mode: bool,
+) -> Vec<String> {
+    let install_command =
+        replace_placeholders(machine_state, config, &source.install_command, name).await;
+    let installation_check_command =
+        replace_placeholders(machine_state, config, &source.install_check_command, name).await;
+    let start_command =
+        replace_placeholders(machine_state, config, &source.start_command, name).await;
+    let health_check_command =
+        replace_placeholders(machine_state, config, &source.health_check_command, name).await;
+
+    let mut commands = vec![format!(
+        "{}  # only when `{}` fails",
+        install_command, installation_check_command
+    )];
+    if clean_mode {
+        commands.extend(clean_commands(machine_state, config, name, source).await);
+        commands.push(start_command);
+    } else {
+        commands.push(format!(
+            "{}  # only when `{}` fails",
+            start_command, health_check_command
+        ));
+    }
+    commands
+}
+
+pub async fn start_service(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &AppSource,
+    clean_mode: bool,
+) -> Result<(), Box<dyn Error>> {
     let install_command =
         replace_placeholders(&machine_state, config, &source.install_command, name).await;
 
@@ -101,14 +319,14 @@ pub async fn start_service(
     check_installation(&installation_check_command, &install_command, name).await;
 
     if clean_mode {
-        clean_service(machine_state, config, name, source, fail_fast).await;
+        clean_service(machine_state, config, name, source, false).await;
     } else {
         let health_check_results = command::run(&health_check_command).await;
 
         match health_check_results {
             Ok(_) => {
                 logging::info(&format!("✅ {} is running", name)).await;
-                return;
+                return Ok(());
             }
             Err(_) => {
                 logging::warn(&format!("{} is not running. Starting...", name)).await;
@@ -116,19 +334,24 @@ pub async fn start_service(
         }
     }
 
-    let start_results = command::spawn(&start_command).await;
+    let log_path = machine::logs::get_log_path(name);
+    let pid_path = machine::logs::get_pid_path(name);
+    let start_results = command::spawn_with_log(&start_command, &log_path, &pid_path).await;
 
     match start_results {
         Ok(_) => {
             logging::info(&format!("🚀 Started {}", name)).await;
+            Ok(())
         }
         Err(e) => {
-            logging::error(&format!("🛑 Failed to start {}", name)).await;
+            logging::error(&format!(
+                "🛑 Failed to start {}, check the logs at {}",
+                name,
+                log_path.display()
+            ))
+            .await;
             logging::error(&e.to_string()).await;
-
-            if fail_fast {
-                std::process::exit(1);
-            }
+            Err(e)
         }
     }
 }
@@ -1,16 +1,33 @@
 use futures::future::join_all;
-use std::error::Error;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fs,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::ExitStatus,
+};
 use tokio::{process::Command, sync::watch};
 
 use crate::{
-    library::{machine, utils::logging},
+    library::{
+        machine,
+        utils::{
+            env_vars::{join_sh, quote_sh},
+            logging,
+        },
+    },
     models::{
-        config::{Config, ContainerSource},
+        config::{Config, ContainerBuild, ContainerSource, PullPolicy, ServicePort, Source},
         machine_state::MachineState,
+        pull_error::{PullError, PullErrorKind},
+        service_status::ServiceStatus,
     },
 };
 
-use super::command;
+use super::{command, config};
 
 pub async fn login(registry: &str, username: &str, password: &str) -> Result<(), Box<dyn Error>> {
     let login_result = command::run(
@@ -62,25 +79,612 @@ pub async fn create_network(name: &str) -> Result<(), Box<dyn Error>> {
     }
 }
 
-pub async fn pull_image(registry: &str, image: &str, tag: &str) -> Result<(), Box<dyn Error>> {
-    let logout_result =
-        command::spawn(format!("docker pull {}/{}:{}", registry, image, tag).as_str()).await;
+/// Pulls the image, showing docker's progress unless `quiet` is set
+pub async fn pull_image(image: &str, quiet: bool) -> Result<(), Box<dyn Error>> {
+    if quiet {
+        command::run(&format!("docker pull -q {}", image)).await?;
+        Ok(())
+    } else {
+        command::spawn(&format!("docker pull {}", image)).await
+    }
+}
 
-    match logout_result {
-        Ok(_) => Ok(()),
-        Err(e) => Err(Box::from(e)),
+/// Whether the image is available locally
+pub async fn image_exists(image: &str) -> bool {
+    Command::new("docker")
+        .args(["image", "inspect", image])
+        .output()
+        .await
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// The pull policy of the source, or the one of the configuration when it has none
+pub fn pull_policy(config: &Config, source: &ContainerSource) -> PullPolicy {
+    source.pull_policy.unwrap_or(config.pull_policy)
+}
+
+/// Pulls the image of the service when its pull policy asks for it. Returns whether it pulled.
+pub async fn ensure_image(
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    quiet: bool,
+) -> Result<bool, PullError> {
+    let image = image_ref(config, name, source);
+
+    let pull = match pull_policy(config, source) {
+        PullPolicy::Always => true,
+        PullPolicy::Missing => !image_exists(&image).await,
+        PullPolicy::Never => {
+            if image_exists(&image).await {
+                false
+            } else {
+                return Err(PullError {
+                    service: name.to_string(),
+                    image,
+                    kind: PullErrorKind::NotAvailable,
+                });
+            }
+        }
+    };
+
+    if !pull {
+        return Ok(false);
+    }
+
+    match pull_image(&image, quiet).await {
+        Ok(_) => Ok(true),
+        Err(e) => Err(PullError {
+            service: name.to_string(),
+            image,
+            kind: PullErrorKind::Failed(e.to_string()),
+        }),
+    }
+}
+
+/// Label that keeps the context hash a built image was built from
+const CONTEXT_HASH_LABEL: &str = "wm.context-hash";
+
+/// Name of the image built for a service, docker only accepts lowercase repository names
+pub fn built_image(config: &Config, name: &str) -> String {
+    format!("{}/{}", config.machine_name, name).to_lowercase()
+}
+
+/// The configured tag, built images are tagged `latest` when it isn't set
+pub fn image_tag(source: &ContainerSource) -> &str {
+    if source.tag.is_empty() {
+        "latest"
+    } else {
+        &source.tag
+    }
+}
+
+/// The image as configured, `registry/image:tag`
+pub fn tagged_image(source: &ContainerSource) -> String {
+    format!("{}/{}:{}", source.registry, source.image, source.tag)
+}
+
+/// The digest in the lockfile, as long as the configured image is the one that was locked
+pub fn locked_digest<'a>(
+    config: &'a Config,
+    name: &str,
+    source: &ContainerSource,
+) -> Option<&'a str> {
+    config
+        .lock
+        .images
+        .get(name)
+        .filter(|locked| locked.image == tagged_image(source))
+        .map(|locked| locked.digest.as_str())
+}
+
+/// The image the service container runs, pinned to the locked digest when there is one
+pub fn image_ref(config: &Config, name: &str, source: &ContainerSource) -> String {
+    if source.build.is_some() {
+        format!("{}:{}", built_image(config, name), image_tag(source))
+    } else if let Some(digest) = locked_digest(config, name, source) {
+        format!("{}/{}@{}", source.registry, source.image, digest)
+    } else {
+        tagged_image(source)
+    }
+}
+
+/// Picks the digest of the image out of `docker image inspect --format '{{json .RepoDigests}}'`.
+/// The same image can be known under other repos, their digests are never used.
+pub fn parse_repo_digest(output: &str, registry: &str, image: &str) -> Option<String> {
+    let repo_digests: Vec<String> = serde_json::from_str(output.trim()).ok()?;
+
+    let mut repos = vec![format!("{}/{}", registry, image)];
+    // Images from Docker Hub are listed without the registry, like "redis@sha256:…"
+    if registry == "docker.io" {
+        repos.push(image.to_string());
+        repos.push(format!("library/{}", image));
+        repos.push(format!("docker.io/library/{}", image));
+    }
+
+    repo_digests
+        .iter()
+        .filter_map(|repo_digest| repo_digest.split_once('@'))
+        .find(|(repo, _)| repos.iter().any(|candidate| candidate == repo))
+        .map(|(_, digest)| digest.to_string())
+}
+
+async fn get_repo_digests(image: &str) -> Result<String, Box<dyn Error>> {
+    command::run(&format!(
+        "docker image inspect --format '{{{{json .RepoDigests}}}}' {}",
+        image
+    ))
+    .await
+}
+
+/// Pulls the configured tag and returns the digest it points to in the registry
+pub async fn resolve_digest(source: &ContainerSource) -> Result<String, Box<dyn Error>> {
+    let image = tagged_image(source);
+    pull_image(&image, true).await?;
+    let output = get_repo_digests(&image).await?;
+    match parse_repo_digest(&output, &source.registry, &source.image) {
+        Some(digest) => Ok(digest),
+        None => Err(Box::from(format!("{} has no registry digest", image))),
+    }
+}
+
+/// Warns when the lockfile is for another image than the configured one, or when the running
+/// container of the service was started from another digest than the locked one
+async fn check_lock(config: &Config, name: &str, source: &ContainerSource, running: bool) {
+    let locked = match config.lock.images.get(name) {
+        Some(locked) => locked,
+        None => return,
+    };
+
+    if locked.image != tagged_image(source) {
+        logging::warn(&format!(
+            "⚠️  {} has {} for {}, but it's configured to run {}. Run `wm lock --update {}`",
+            config::lock_path(&config.config_path).display(),
+            locked.image,
+            name,
+            tagged_image(source),
+            name
+        ))
+        .await;
+        return;
+    }
+
+    if !running {
+        return;
+    }
+
+    let container_image = match get_container_id(config, name).await {
+        Ok(Some(container_id)) => command::run(&format!(
+            "docker inspect --format '{{{{.Image}}}}' {}",
+            container_id
+        ))
+        .await
+        .ok(),
+        _ => None,
+    };
+    let repo_digests = match container_image {
+        Some(container_image) => get_repo_digests(container_image.trim()).await.ok(),
+        None => None,
+    };
+
+    if let Some(repo_digests) = repo_digests {
+        if !repo_digests.contains(&locked.digest) {
+            logging::warn(&format!(
+                "⚠️  {} runs another image than the {} in {}. Run `wm clean {}` to recreate it",
+                name,
+                locked.digest,
+                config::lock_path(&config.config_path).display(),
+                name
+            ))
+            .await;
+        }
+    }
+}
+
+/// Plain names and paths in the `.dockerignore` of the context. Patterns with wildcards are
+/// left out, they only make the hash change more often than needed.
+fn ignored_paths(context: &Path) -> HashSet<PathBuf> {
+    let mut ignored = HashSet::from([PathBuf::from(".git")]);
+    let content = fs::read_to_string(context.join(".dockerignore")).unwrap_or_default();
+    for line in content.lines() {
+        let line = line.trim().trim_start_matches('/').trim_end_matches('/');
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with('!')
+            || line.contains(['*', '?', '['])
+        {
+            continue;
+        }
+        ignored.insert(PathBuf::from(line));
+    }
+    ignored
+}
+
+fn collect_files(
+    context: &Path,
+    dir: &Path,
+    ignored: &HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(context)?.to_path_buf();
+        if ignored.contains(&relative) {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(context, &path, ignored, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Hash of the files in the build context and the build settings, the image is only rebuilt
+/// when it changes
+pub fn context_hash(build: &ContainerBuild) -> Result<String, Box<dyn Error>> {
+    let context = Path::new(&build.context);
+    if !context.is_dir() {
+        return Err(Box::from(format!(
+            "build context {} is not a directory",
+            build.context
+        )));
+    }
+
+    let mut files = Vec::new();
+    collect_files(context, context, &ignored_paths(context), &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(context.join(&file))?);
+        hasher.update([0]);
+    }
+
+    let mut args: Vec<(&String, &String)> = build.args.iter().collect();
+    args.sort();
+    for (key, value) in args {
+        hasher.update(format!("arg {}={}\n", key, value).as_bytes());
+    }
+    if let Some(dockerfile) = &build.dockerfile {
+        hasher.update(format!("dockerfile {}\n", dockerfile).as_bytes());
+    }
+    if let Some(target) = &build.target {
+        hasher.update(format!("target {}\n", target).as_bytes());
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Arguments for `docker build`
+pub fn build_args(image: &str, build: &ContainerBuild, hash: &str) -> Vec<String> {
+    let mut args = vec!["-t".to_string(), image.to_string()];
+
+    if let Some(dockerfile) = &build.dockerfile {
+        args.push("-f".to_string());
+        args.push(
+            Path::new(&build.context)
+                .join(dockerfile)
+                .display()
+                .to_string(),
+        );
+    }
+
+    if let Some(target) = &build.target {
+        args.push("--target".to_string());
+        args.push(target.to_string());
+    }
+
+    let mut build_arguments: Vec<(&String, &String)> = build.args.iter().collect();
+    build_arguments.sort();
+    for (key, value) in build_arguments {
+        args.push("--build-arg".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+
+    args.push("--label".to_string());
+    args.push(format!("{}={}", CONTEXT_HASH_LABEL, hash));
+    args.push(build.context.to_string());
+    args
+}
+
+/// Context hash the local image was built from, `None` when there is no such image
+async fn get_built_hash(image: &str) -> Option<String> {
+    let output = command::run(&format!(
+        "docker image inspect --format '{{{{ index .Config.Labels \"{}\" }}}}' {}",
+        CONTEXT_HASH_LABEL, image
+    ))
+    .await
+    .ok()?;
+
+    let hash = output.trim();
+    if hash.is_empty() || hash == "<no value>" {
+        None
+    } else {
+        Some(hash.to_string())
+    }
+}
+
+/// The build section of the source with its placeholders replaced
+async fn resolve_build(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+) -> Option<ContainerBuild> {
+    let mut build = source.build.clone()?;
+    build.context = replace_placeholders(machine_state, config, &build.context, name, source).await;
+    for value in build.args.values_mut() {
+        *value = replace_placeholders(machine_state, config, value, name, source).await;
+    }
+    Some(build)
+}
+
+/// Builds the service image when its context changed since the last build, or always with `force`
+pub async fn build_image(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    force: bool,
+) -> Result<(), Box<dyn Error>> {
+    let build = match resolve_build(machine_state, config, name, source).await {
+        Some(build) => build,
+        None => return Err(Box::from(format!("{} has no build section", name))),
+    };
+
+    let image = image_ref(config, name, source);
+    let hash = context_hash(&build)?;
+
+    if !force && get_built_hash(&image).await.as_deref() == Some(hash.as_str()) {
+        logging::info(&format!("✅ {} image is up to date", name)).await;
+        return Ok(());
+    }
+
+    logging::info(&format!("🔨 Building {}", image)).await;
+    let status = Command::new("docker")
+        .arg("build")
+        .args(build_args(&image, &build, &hash))
+        .stdout(command::stdout())
+        .status()
+        .await?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Box::from(format!("docker build of {} failed", image)))
+    }
+}
+
+/// `-p` values for the declared ports that map to a container port
+pub fn publish_args(
+    machine_state: &MachineState,
+    name: &str,
+    ports: &[ServicePort],
+) -> Vec<String> {
+    ports
+        .iter()
+        .filter_map(|port| {
+            let container_port = port.container?;
+            let host_port = machine_state
+                .ports
+                .get(&machine::ports::service_port_key(name, &port.name))?;
+            Some(format!("{}:{}", host_port, container_port))
+        })
+        .collect()
+}
+
+/// Bind mounts relative to the project become absolute, since docker doesn't run in a shell
+fn absolute_volume(volume: &str) -> String {
+    match volume.strip_prefix("./") {
+        Some(relative) => format!(
+            "{}/{}",
+            std::env::current_dir().unwrap().display(),
+            relative
+        ),
+        None => volume.to_string(),
+    }
+}
+
+/// Arguments for `docker run`, built from the structured fields of the source
+pub async fn run_args(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    ports: &[ServicePort],
+) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "-d".to_string(),
+        "--name".to_string(),
+        format!("{}-{}", config.machine_name, name),
+    ];
+
+    for (key, value) in labels(config, name) {
+        args.push("--label".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+
+    for network in &source.networks {
+        args.push("--network".to_string());
+        args.push(replace_placeholders(machine_state, config, network, name, source).await);
+    }
+
+    for published_port in publish_args(machine_state, name, ports) {
+        args.push("-p".to_string());
+        args.push(published_port);
+    }
+
+    // Sorted so the same configuration always gives the same command
+    let mut env: Vec<(&String, &String)> = source.env.iter().collect();
+    env.sort();
+    for (key, value) in env {
+        let value = replace_placeholders(machine_state, config, value, name, source).await;
+        args.push("-e".to_string());
+        args.push(format!("{}={}", key, value));
+    }
+
+    for volume in &source.volumes {
+        let volume = replace_placeholders(machine_state, config, volume, name, source).await;
+        args.push("-v".to_string());
+        args.push(absolute_volume(&volume));
+    }
+
+    if let Some(user) = &source.user {
+        args.push("--user".to_string());
+        args.push(user.to_string());
+    }
+
+    if let Some(entrypoint) = &source.entrypoint {
+        args.push("--entrypoint".to_string());
+        args.push(replace_placeholders(machine_state, config, entrypoint, name, source).await);
     }
+
+    if let Some(healthcheck) = &source.healthcheck {
+        args.push("--health-cmd".to_string());
+        args.push(
+            replace_placeholders(machine_state, config, &healthcheck.command, name, source).await,
+        );
+        let options = [
+            ("--health-interval", healthcheck.interval.clone()),
+            ("--health-timeout", healthcheck.timeout.clone()),
+            (
+                "--health-retries",
+                healthcheck.retries.map(|retries| retries.to_string()),
+            ),
+            ("--health-start-period", healthcheck.start_period.clone()),
+        ];
+        for (flag, value) in options {
+            if let Some(value) = value {
+                args.push(flag.to_string());
+                args.push(value);
+            }
+        }
+    }
+
+    for extra_arg in &source.extra_args {
+        args.push(replace_placeholders(machine_state, config, extra_arg, name, source).await);
+    }
+
+    args.push(image_ref(config, name, source));
+
+    for arg in &source.command {
+        args.push(replace_placeholders(machine_state, config, arg, name, source).await);
+    }
+
+    args
+}
+
+/// What the service container is created from, with the placeholders replaced. The container
+/// has to be recreated when any of these change.
+pub async fn definition_fields(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    ports: &[ServicePort],
+) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+
+    if let Some(build) = resolve_build(machine_state, config, name, source).await {
+        let build_hash = match context_hash(&build) {
+            Ok(hash) => hash[..12].to_string(),
+            Err(e) => e.to_string(),
+        };
+        fields.insert("build".to_string(), build_hash);
+    } else {
+        fields.insert(
+            "image".to_string(),
+            format!("{}/{}", source.registry, source.image),
+        );
+        if let Some(digest) = locked_digest(config, name, source) {
+            fields.insert("digest".to_string(), digest.to_string());
+        }
+    }
+    fields.insert("tag".to_string(), image_tag(source).to_string());
+
+    if let Some(start_command) = &source.start_command {
+        fields.insert(
+            "start_command".to_string(),
+            replace_placeholders(machine_state, config, start_command, name, source).await,
+        );
+        return fields;
+    }
+
+    let mut env = vec![];
+    for (key, value) in &source.env {
+        let value = replace_placeholders(machine_state, config, value, name, source).await;
+        env.push(format!("{}={}", key, value));
+    }
+    env.sort();
+
+    let mut volumes = vec![];
+    for volume in &source.volumes {
+        let volume = replace_placeholders(machine_state, config, volume, name, source).await;
+        volumes.push(absolute_volume(&volume));
+    }
+
+    let mut networks = vec![];
+    for network in &source.networks {
+        networks.push(replace_placeholders(machine_state, config, network, name, source).await);
+    }
+
+    let mut command = vec![];
+    for arg in &source.command {
+        command.push(replace_placeholders(machine_state, config, arg, name, source).await);
+    }
+
+    let mut extra_args = vec![];
+    for arg in &source.extra_args {
+        extra_args.push(replace_placeholders(machine_state, config, arg, name, source).await);
+    }
+
+    let mut entrypoint = vec![];
+    if let Some(value) = &source.entrypoint {
+        entrypoint.push(replace_placeholders(machine_state, config, value, name, source).await);
+    }
+
+    let mut healthcheck = vec![];
+    if let Some(value) = &source.healthcheck {
+        healthcheck
+            .push(replace_placeholders(machine_state, config, &value.command, name, source).await);
+        healthcheck.extend(value.interval.clone());
+        healthcheck.extend(value.timeout.clone());
+        healthcheck.extend(value.retries.map(|retries| retries.to_string()));
+        healthcheck.extend(value.start_period.clone());
+    }
+
+    let lists = [
+        ("ports", publish_args(machine_state, name, ports), ", "),
+        ("env", env, ", "),
+        ("volumes", volumes, ", "),
+        ("networks", networks, ", "),
+        ("command", command, " "),
+        ("extra_args", extra_args, " "),
+        ("entrypoint", entrypoint, " "),
+        ("user", source.user.iter().cloned().collect(), " "),
+        ("healthcheck", healthcheck, " "),
+    ];
+    for (field, values, separator) in lists {
+        if !values.is_empty() {
+            fields.insert(field.to_string(), values.join(separator));
+        }
+    }
+
+    fields
 }
 
-pub async fn start_container(name: &str, source: &ContainerSource) -> Result<(), Box<dyn Error>> {
+pub async fn start_container(args: &[String]) -> Result<(), Box<dyn Error>> {
     let (tx, mut rx) = watch::channel(false);
 
     let child = Command::new("docker")
         .arg("run")
-        .arg("-d")
-        .arg("--name")
-        .arg(name)
-        .arg(&source.image)
+        .args(args)
+        .stdout(command::stdout())
         .spawn()
         .expect("Failed to start docker container");
 
@@ -131,15 +735,8 @@ pub async fn start_container(name: &str, source: &ContainerSource) -> Result<(),
     }
 }
 
-pub async fn remove_containers(filter: &str) -> Result<(), Box<dyn Error>> {
-    let containers_to_remove_vec = Command::new("docker")
-        .args(&["ps", "-a", "--filter", filter, "-q"])
-        .output()
-        .await
-        .expect("Failed to get docker container ids")
-        .stdout;
-
-    let containers_to_remove = String::from_utf8_lossy(&containers_to_remove_vec);
+pub async fn remove_containers(filters: &[String]) -> Result<(), Box<dyn Error>> {
+    let containers_to_remove = docker_ps(filters, true, None).await?;
 
     let mut tasks = Vec::new();
     for container in containers_to_remove.lines() {
@@ -163,7 +760,365 @@ pub async fn remove_containers(filter: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+pub async fn stop_containers(filters: &[String]) -> Result<(), Box<dyn Error>> {
+    let containers_to_stop = docker_ps(filters, false, None).await?;
+
+    let mut tasks = Vec::new();
+    for container in containers_to_stop.lines() {
+        let task = Command::new("docker").args(["stop", container]).status();
+        tasks.push(task);
+    }
+
+    let results = join_all(tasks).await;
+    for result in results {
+        match result {
+            Ok(_) => {}
+            Err(e) => {
+                logging::error(&format!("Failed to stop container: {}", e)).await;
+                return Err(Box::new(e));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+const MACHINE_LABEL: &str = "wm.machine";
+const SERVICE_LABEL: &str = "wm.service";
+const PROJECT_LABEL: &str = "wm.project";
+const CONFIG_HASH_LABEL: &str = "wm.config-hash";
+
+/// Short hash of the service as configured, before its placeholders are replaced
+pub fn service_config_hash(config: &Config, name: &str) -> String {
+    let service = config.services.iter().find(|service| service.name == name);
+    // Going through a Value sorts the keys of the maps in the service
+    let definition = serde_json::to_value(service)
+        .map(|value| value.to_string())
+        .unwrap_or_default();
+    let hash = format!("{:x}", Sha256::digest(definition.as_bytes()));
+    hash[..12].to_string()
+}
+
+/// The `wm.config-hash` label of the container of each service, stopped ones included
+pub async fn config_hash_labels(config: &Config) -> HashMap<String, String> {
+    let format = format!(
+        "{{{{.Label \"{}\"}}}}\t{{{{.Label \"{}\"}}}}",
+        SERVICE_LABEL, CONFIG_HASH_LABEL
+    );
+    let output = docker_ps(&machine_filters(config), true, Some(&format))
+        .await
+        .unwrap_or_default();
+
+    output
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(_, hash)| !hash.trim().is_empty())
+        .map(|(name, hash)| (name.trim().to_string(), hash.trim().to_string()))
+        .collect()
+}
+
+/// Labels of the containers war machine creates for a service
+pub fn labels(config: &Config, name: &str) -> Vec<(String, String)> {
+    vec![
+        (MACHINE_LABEL.to_string(), config.machine_name.clone()),
+        (SERVICE_LABEL.to_string(), name.to_string()),
+        (PROJECT_LABEL.to_string(), machine::registry::project_path()),
+        (
+            CONFIG_HASH_LABEL.to_string(),
+            service_config_hash(config, name),
+        ),
+    ]
+}
+
+/// Filters that match the containers of every service of this project
+fn machine_filters(config: &Config) -> Vec<String> {
+    vec![
+        format!("label={}={}", MACHINE_LABEL, config.machine_name),
+        format!(
+            "label={}={}",
+            PROJECT_LABEL,
+            machine::registry::project_path()
+        ),
+    ]
+}
+
+/// Filters that match the container war machine creates for a service
+pub fn service_filters(config: &Config, name: &str) -> Vec<String> {
+    let mut filters = machine_filters(config);
+    filters.push(format!("label={}={}", SERVICE_LABEL, name));
+    filters
+}
+
+fn docker_run_regex() -> Regex {
+    Regex::new(r"\bdocker\s+(container\s+)?run\b").unwrap()
+}
+
+/// Whether the start command runs the container with `docker run`, which is where the labels go
+pub fn runs_container(command: &str) -> bool {
+    docker_run_regex().is_match(command)
+}
+
+/// Adds the labels to the `docker run` of a start command. Commands that don't run a
+/// container themselves are left as they are.
+pub fn add_labels(command: &str, labels: &[(String, String)]) -> String {
+    let regex = docker_run_regex();
+    let label_args: Vec<String> = labels
+        .iter()
+        .map(|(key, value)| format!("--label {}", quote_sh(&format!("{}={}", key, value))))
+        .collect();
+    regex
+        .replace(command, |captures: &regex::Captures| {
+            format!("{} {}", &captures[0], label_args.join(" "))
+        })
+        .to_string()
+}
+
+/// Runs `docker ps` with the filters, printing the ids unless a format is given
+async fn docker_ps(
+    filters: &[String],
+    all: bool,
+    format: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let mut command = Command::new("docker");
+    command.arg("ps");
+    if all {
+        command.arg("-a");
+    }
+    for filter in filters {
+        command.args(["--filter", filter]);
+    }
+    match format {
+        Some(format) => command.args(["--format", format]),
+        None => command.arg("-q"),
+    };
+
+    let output = command.output().await?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    } else {
+        Err(Box::from(
+            String::from_utf8_lossy(&output.stderr).to_string(),
+        ))
+    }
+}
+
+/// Whether the container was created from the image as it is now
+async fn uses_image(container_id: &str, image: &str) -> bool {
+    let container_image = command::run(&format!(
+        "docker inspect --format '{{{{.Image}}}}' {}",
+        container_id
+    ))
+    .await;
+    let image_id = command::run(&format!(
+        "docker image inspect --format '{{{{.Id}}}}' {}",
+        image
+    ))
+    .await;
+
+    match (container_image, image_id) {
+        (Ok(container_image), Ok(image_id)) => container_image.trim() == image_id.trim(),
+        _ => false,
+    }
+}
+
+/// Removes the container named like the service that was created without labels, by an
+/// earlier version of war machine, so the labeled one can take its name
+async fn remove_unlabeled_container(config: &Config, name: &str) -> Result<(), Box<dyn Error>> {
+    let output = docker_ps(
+        &[format!("name=^{}-{}$", config.machine_name, name)],
+        true,
+        Some(&format!(
+            "{{{{.ID}}}}\t{{{{.Label \"{}\"}}}}",
+            SERVICE_LABEL
+        )),
+    )
+    .await?;
+
+    for line in output.lines() {
+        let (id, service_label) = line.split_once('\t').unwrap_or((line, ""));
+        if id.trim().is_empty() || !service_label.trim().is_empty() {
+            continue;
+        }
+        logging::warn(&format!(
+            "⚠️  Removing the {} container, it was created without war machine labels",
+            name
+        ))
+        .await;
+        Command::new("docker")
+            .args(["rm", "-f", id.trim()])
+            .output()
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Host ports in `docker ps --format '{{.Label "wm.service"}}\t{{.Ports}}'` output, only for
+/// the given services
+pub fn parse_published_ports(output: &str, service_names: &HashSet<String>) -> HashSet<u16> {
+    // Ports look like "0.0.0.0:49000->6379/tcp, :::49000->6379/tcp"
+    let regex = Regex::new(r":(\d+)->").unwrap();
+    let mut ports = HashSet::new();
+    for line in output.lines() {
+        let (name, container_ports) = match line.split_once('\t') {
+            Some(parts) => parts,
+            None => continue,
+        };
+        if !service_names.contains(name.trim()) {
+            continue;
+        }
+        for captures in regex.captures_iter(container_ports) {
+            if let Ok(port) = captures[1].parse::<u16>() {
+                ports.insert(port);
+            }
+        }
+    }
+    ports
+}
+
+/// Host ports published by the running containers of the machine's services
+pub async fn get_published_ports(config: &Config) -> Result<HashSet<u16>, Box<dyn Error>> {
+    let output = docker_ps(
+        &machine_filters(config),
+        false,
+        Some(&format!(
+            "{{{{.Label \"{}\"}}}}\t{{{{.Ports}}}}",
+            SERVICE_LABEL
+        )),
+    )
+    .await?;
+
+    let service_names: HashSet<String> = config
+        .services
+        .iter()
+        .filter(|service| matches!(service.source, Source::Container(_)))
+        .map(|service| service.name.clone())
+        .collect();
+
+    Ok(parse_published_ports(&output, &service_names))
+}
+
+/// Gets the id of the service container, running or not
+pub async fn get_container_id(
+    config: &Config,
+    name: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let output = docker_ps(&service_filters(config, name), true, None).await?;
+
+    Ok(output.lines().next().map(|id| id.trim().to_string()))
+}
+
+/// Builds the `docker logs` command for a container
+pub fn logs_command(
+    container_id: &str,
+    follow: bool,
+    since: Option<&str>,
+    tail: Option<&str>,
+) -> Command {
+    let mut command = Command::new("docker");
+    command.arg("logs");
+
+    if follow {
+        command.arg("--follow");
+    }
+    if let Some(since) = since {
+        command.args(["--since", since]);
+    }
+    if let Some(tail) = tail {
+        command.args(["--tail", tail]);
+    }
+
+    command.arg(container_id);
+    command
+}
+
+/// Runs a command inside the service container, attaching a TTY when we are in one
+pub async fn exec(
+    config: &Config,
+    name: &str,
+    args: &[String],
+) -> Result<ExitStatus, Box<dyn Error>> {
+    let container_id = match get_container_id(config, name).await? {
+        Some(container_id) => container_id,
+        None => return Err(Box::from(format!("No container found for {}", name))),
+    };
+
+    let mut command = Command::new("docker");
+    command.arg("exec").arg("-i");
+
+    if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+        command.arg("-t");
+    }
+
+    let status = command.arg(container_id).args(args).status().await?;
+    Ok(status)
+}
+
+/// Gets the status of the service container from `docker ps`. Ports are left for the caller to fill.
+pub async fn get_service_status(
+    config: &Config,
+    name: &str,
+) -> Result<ServiceStatus, Box<dyn Error>> {
+    let output = docker_ps(&service_filters(config, name), true, Some("{{json .}}")).await?;
+
+    let mut status = ServiceStatus {
+        name: name.to_string(),
+        running: false,
+        health: None,
+        container_id: None,
+        pid: None,
+        image: None,
+        ports: HashMap::new(),
+        uptime: None,
+        error: None,
+    };
+
+    // There should only be one container per service, if there are none the service is stopped
+    let container_line = match output.lines().find(|line| !line.trim().is_empty()) {
+        Some(line) => line,
+        None => return Ok(status),
+    };
+
+    let container: serde_json::Value = serde_json::from_str(container_line)?;
+    let container_status = container["Status"].as_str().unwrap_or_default();
+
+    status.running = container["State"].as_str() == Some("running");
+    status.container_id = container["ID"].as_str().map(|id| id.to_string());
+    status.image = container["Image"].as_str().map(|image| image.to_string());
+
+    if container_status.contains("(healthy)") {
+        status.health = Some("healthy".to_string());
+    } else if container_status.contains("(unhealthy)") {
+        status.health = Some("unhealthy".to_string());
+    } else if container_status.contains("(health: starting)") {
+        status.health = Some("starting".to_string());
+    }
+
+    // The status looks like "Up 5 minutes (healthy)"
+    if status.running {
+        if let Some(uptime) = container_status.strip_prefix("Up ") {
+            let uptime = uptime.split(" (").next().unwrap_or(uptime);
+            status.uptime = Some(uptime.to_string());
+        }
+    }
+
+    Ok(status)
+}
+
 /// Replaces the placeholders in the command with the actual values
+/// Replaces `${env.NAME}` with the variable from the prepared environment. The structured
+/// fields don't go through a shell, so this is how they refer to secrets and exposed values.
+/// Only done right before `docker run`, so plans and exports never contain the values.
+/// Variables that are not set are left as is.
+pub fn replace_env_placeholders(text: &str) -> String {
+    let regex = Regex::new(r"\$\{env\.(\w+)\}").unwrap();
+    regex
+        .replace_all(text, |captures: &regex::Captures| {
+            std::env::var(&captures[1]).unwrap_or_else(|_| captures[0].to_string())
+        })
+        .to_string()
+}
+
 pub async fn replace_placeholders(
     machine_state: &MachineState,
     config: &Config,
@@ -174,16 +1129,26 @@ pub async fn replace_placeholders(
     let mut new_command = command.to_string();
     new_command = new_command.replace("${machine_name}", &config.machine_name);
     new_command = new_command.replace("${service.name}", name);
-    new_command = new_command.replace("${service.source.image}", &source.image);
-    new_command = new_command.replace("${service.source.tag}", &source.tag);
+    let image = if source.build.is_some() {
+        built_image(config, name)
+    } else {
+        source.image.clone()
+    };
+    new_command = new_command.replace("${service.source.image}", &image);
+    new_command = new_command.replace("${service.source.tag}", image_tag(source));
     new_command = new_command.replace("${service.source.registry}", &source.registry);
+    new_command = new_command.replace("${service.source.ref}", &image_ref(config, name, source));
+    new_command =
+        machine::ports::replace_service_ports_in_text(machine_state, name, &new_command).await;
     new_command = machine::ports::replace_ports_in_text(&machine_state, &new_command).await;
     new_command
 }
 
 pub async fn clean_service(config: &Config, name: &str, fail_fast: bool) {
-    let remove_containers_result =
-        remove_containers(&format!("name=^{}-{}$", config.machine_name, name)).await;
+    let remove_containers_result = match remove_containers(&service_filters(config, name)).await {
+        Ok(_) => remove_unlabeled_container(config, name).await,
+        Err(e) => Err(e),
+    };
 
     match remove_containers_result {
         Ok(_) => {
@@ -203,14 +1168,44 @@ pub async fn clean_service(config: &Config, name: &str, fail_fast: bool) {
     }
 }
 
-pub async fn start_service(
+/// Stops the service container without removing it, so it can be resumed later
+pub async fn stop_service(
     machine_state: &MachineState,
     config: &Config,
     name: &str,
     source: &ContainerSource,
-    clean_mode: bool,
     fail_fast: bool,
 ) {
+    let stop_results = if let Some(stop_command) = &source.stop_command {
+        let stop_command =
+            replace_placeholders(machine_state, config, stop_command, name, source).await;
+        command::spawn(&stop_command).await
+    } else {
+        stop_containers(&service_filters(config, name)).await
+    };
+
+    match stop_results {
+        Ok(_) => {
+            logging::info(&format!("🛑 {} stopped", name)).await;
+        }
+        Err(e) => {
+            logging::error(&format!("Failed to stop {}: {}", name, e)).await;
+
+            if fail_fast {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+pub async fn start_service(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    ports: &[ServicePort],
+    clean_mode: bool,
+) -> Result<(), Box<dyn Error>> {
     let start_command = if let Some(start_command) = &source.start_command {
         Some(replace_placeholders(machine_state, config, start_command, name, source).await)
     } else {
@@ -218,70 +1213,235 @@ pub async fn start_service(
     };
 
     if clean_mode {
-        clean_service(config, name, fail_fast).await;
+        clean_service(config, name, false).await;
     } else {
-        // Check if redis is already running
-        let check_results = Command::new("docker")
-            .arg("ps")
-            .arg("--filter")
-            .arg(&format!("name=^{}-{}$", config.machine_name, name))
-            .output()
-            .await;
-
-        match check_results {
+        // Check if the service is already running
+        match docker_ps(&service_filters(config, name), false, None).await {
             Ok(output) => {
-                let output_str = String::from_utf8_lossy(&output.stdout);
-                if output_str.contains(&format!("{}", name)) {
+                if !output.trim().is_empty() {
                     logging::info(&format!("✅ {} is running", name)).await;
-                    return;
+                    if source.build.is_none() {
+                        check_lock(config, name, source, true).await;
+                    }
+                    return Ok(());
                 }
             }
             Err(e) => {
                 logging::error(&format!("🛑 Failed to check if {} is running: {}", name, e)).await;
-
-                if fail_fast {
-                    std::process::exit(1);
-                }
+                return Err(e);
             }
         }
+
+        if let Err(e) = remove_unlabeled_container(config, name).await {
+            logging::error(&format!(
+                "🛑 Failed to remove the old {} container: {}",
+                name, e
+            ))
+            .await;
+            return Err(e);
+        }
     }
 
-    let pull_image_result = pull_image(&source.registry, &source.image, &source.tag).await;
-    if pull_image_result.is_err() {
-        logging::error(&format!(
-            "🛑 Failed to pull {}/{}:{}",
-            source.registry, source.image, source.tag
-        ))
-        .await;
+    // Failures are returned rather than exiting, the caller decides what a failed start means
+    if source.build.is_some() {
+        // Built images only exist locally, there is nothing to pull
+        if let Err(e) = build_image(machine_state, config, name, source, false).await {
+            logging::error(&format!("🛑 Failed to build {}: {}", name, e)).await;
+            return Err(e);
+        }
+    } else {
+        check_lock(config, name, source, false).await;
+        if let Err(e) = ensure_image(config, name, source, false).await {
+            logging::error(&format!("🛑 {}", e)).await;
+            return Err(Box::new(e));
+        }
+    }
 
-        logging::error(&pull_image_result.err().unwrap().to_string()).await;
+    // Containers stopped with `wm stop` are resumed, unless the image was pulled or built again
+    // since they were created
+    if !clean_mode {
+        if let Ok(Some(container_id)) = get_container_id(config, name).await {
+            if uses_image(&container_id, &image_ref(config, name, source)).await {
+                let resume_results = command::run(&format!("docker start {}", container_id)).await;
+                match resume_results {
+                    Ok(_) => {
+                        logging::info(&format!("🚀 resumed {}", name)).await;
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        logging::error(&format!("🛑 Failed to resume {}: {}", name, e)).await;
+                        return Err(e);
+                    }
+                }
+            }
 
-        std::process::exit(1);
+            logging::info(&format!(
+                "🔄 {} was stopped on another image, recreating it",
+                name
+            ))
+            .await;
+            if let Err(e) = command::run(&format!("docker rm -f {}", container_id)).await {
+                logging::error(&format!(
+                    "🛑 Failed to remove the old {} container: {}",
+                    name, e
+                ))
+                .await;
+                return Err(e);
+            }
+        }
     }
 
     let start_results;
 
     if let Some(start_command) = start_command {
+        let start_command = add_labels(&start_command, &labels(config, name));
         start_results = command::spawn(&start_command).await;
     } else {
-        start_results = start_container(name, source).await;
+        let args: Vec<String> = run_args(machine_state, config, name, source, ports)
+            .await
+            .iter()
+            .map(|arg| replace_env_placeholders(arg))
+            .collect();
+        start_results = start_container(&args).await;
     }
 
     match start_results {
         Ok(_) => {
             logging::info(&format!("🚀 started {}", name)).await;
+            Ok(())
         }
         Err(e) => {
             logging::error(&format!("🛑 Failed to start {}", name)).await;
             logging::error(&e.to_string()).await;
+            Err(e)
+        }
+    }
+}
 
-            if fail_fast {
-                std::process::exit(1);
-            }
+/// The `docker build` that `build_image` runs, for dry runs
+pub async fn build_command(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+) -> Result<String, Box<dyn Error>> {
+    let build = match resolve_build(machine_state, config, name, source).await {
+        Some(build) => build,
+        None => return Err(Box::from(format!("{} has no build section", name))),
+    };
+
+    let image = image_ref(config, name, source);
+    let hash = context_hash(&build)?;
+    Ok(format!(
+        "docker build {}",
+        join_sh(&build_args(&image, &build, &hash))
+    ))
+}
+
+/// `docker ps` filters as they are written on the command line
+fn filter_args(filters: &[String]) -> String {
+    let args: Vec<String> = filters
+        .iter()
+        .flat_map(|filter| ["--filter".to_string(), filter.clone()])
+        .collect();
+    join_sh(&args)
+}
+
+/// What `clean_service` runs, for dry runs
+pub fn clean_commands(config: &Config, name: &str) -> Vec<String> {
+    vec![format!(
+        "docker rm -f $(docker ps -aq {})",
+        filter_args(&service_filters(config, name))
+    )]
+}
+
+/// What `stop_service` runs, for dry runs
+pub async fn stop_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+) -> Vec<String> {
+    match &source.stop_command {
+        Some(stop_command) => {
+            vec![replace_placeholders(machine_state, config, stop_command, name, source).await]
         }
+        None => vec![format!(
+            "docker stop $(docker ps -q {})",
+            filter_args(&service_filters(config, name))
+        )],
     }
 }
 
+/// How `start_service` gets the image of the service, for dry runs
+pub async fn image_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+) -> Vec<String> {
+    let image = image_ref(config, name, source);
+
+    if source.build.is_some() {
+        return match build_command(machine_state, config, name, source).await {
+            Ok(build_command) => vec![format!(
+                "{}  # unless {} is up to date",
+                build_command, image
+            )],
+            Err(e) => vec![format!("# {} can't be built: {}", name, e)],
+        };
+    }
+
+    let pull = format!("docker pull {}", image);
+    match pull_policy(config, source) {
+        PullPolicy::Always => vec![pull],
+        PullPolicy::Missing => vec![format!("{}  # only when it isn't available locally", pull)],
+        PullPolicy::Never => vec![format!(
+            "# {} is never pulled, it has to be available locally",
+            image
+        )],
+    }
+}
+
+/// What `start_service` runs, for dry runs. The placeholders are replaced and the labels
+/// are added, like they are when the service starts.
+pub async fn start_commands(
+    machine_state: &MachineState,
+    config: &Config,
+    name: &str,
+    source: &ContainerSource,
+    ports: &[ServicePort],
+    clean_mode: bool,
+) -> Vec<String> {
+    let mut commands = vec![];
+    if clean_mode {
+        commands.extend(clean_commands(config, name));
+    } else {
+        commands.push(format!("# only when {} isn't running", name));
+    }
+
+    commands.extend(image_commands(machine_state, config, name, source).await);
+
+    if !clean_mode {
+        commands.push(format!(
+            "# a stopped {} container is resumed with `docker start` when it runs {}, otherwise it is removed",
+            name,
+            image_ref(config, name, source)
+        ));
+    }
+
+    if let Some(start_command) = &source.start_command {
+        let start_command =
+            replace_placeholders(machine_state, config, start_command, name, source).await;
+        commands.push(add_labels(&start_command, &labels(config, name)));
+    } else {
+        let args = run_args(machine_state, config, name, source, ports).await;
+        commands.push(format!("docker run {}", join_sh(&args)));
+    }
+
+    commands
+}
+
 async fn install() -> Result<(), Box<dyn Error>> {
     let (tx, mut rx) = watch::channel(false);
 
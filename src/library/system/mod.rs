@@ -6,6 +6,7 @@ pub mod custom_app;
 pub mod docker;
 pub mod pipx;
 pub mod poetry;
+pub mod probe;
 pub mod python;
 pub mod pythonpath;
 pub mod supabase_cli;
@@ -0,0 +1,120 @@
+use std::error::Error;
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+
+use crate::models::config::ReadyProbe;
+
+use super::command;
+
+/// A bare port means the port on localhost
+fn tcp_address(address: &str) -> String {
+    let address = address.trim();
+    if address.parse::<u16>().is_ok() {
+        format!("127.0.0.1:{}", address)
+    } else {
+        address.to_string()
+    }
+}
+
+async fn check_tcp(address: &str, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    let address = tcp_address(address);
+    match tokio::time::timeout(timeout, TcpStream::connect(&address)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(Box::from(format!(
+            "connecting to {} failed: {}",
+            address, e
+        ))),
+        Err(_) => Err(Box::from(format!(
+            "connecting to {} timed out after {}s",
+            address,
+            timeout.as_secs()
+        ))),
+    }
+}
+
+async fn check_http(
+    url: &str,
+    status: Option<u16>,
+    timeout: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let client = reqwest::Client::builder().timeout(timeout).build()?;
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(e) => return Err(Box::from(format!("requesting {} failed: {}", url, e))),
+    };
+
+    let response_status = response.status();
+    let ok = match status {
+        Some(status) => response_status.as_u16() == status,
+        None => response_status.is_success(),
+    };
+    if ok {
+        return Ok(());
+    }
+
+    match status {
+        Some(status) => Err(Box::from(format!(
+            "{} returned {} instead of {}",
+            url, response_status, status
+        ))),
+        None => Err(Box::from(format!("{} returned {}", url, response_status))),
+    }
+}
+
+async fn check_exec(exec: &str, timeout: Duration) -> Result<(), Box<dyn Error>> {
+    match tokio::time::timeout(timeout, command::run(exec)).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => {
+            let output = e.to_string().trim().to_string();
+            if output.is_empty() {
+                Err(Box::from(format!("`{}` failed", exec)))
+            } else {
+                Err(Box::from(output))
+            }
+        }
+        Err(_) => Err(Box::from(format!(
+            "`{}` timed out after {}s",
+            exec,
+            timeout.as_secs()
+        ))),
+    }
+}
+
+/// Runs the probe once
+pub async fn check(probe: &ReadyProbe) -> Result<(), Box<dyn Error>> {
+    let timeout = Duration::from_secs(probe.timeout.max(1));
+    if let Some(address) = &probe.tcp {
+        check_tcp(address, timeout).await
+    } else if let Some(url) = &probe.http {
+        check_http(url, probe.status, timeout).await
+    } else if let Some(exec) = &probe.exec {
+        check_exec(exec, timeout).await
+    } else {
+        Err(Box::from(
+            "the ready probe needs one of `tcp`, `http` or `exec`",
+        ))
+    }
+}
+
+/// Runs the probe until it passes, at most `retries` times, and returns the last error seen
+/// when it never does. The placeholders in the probe must already be replaced.
+pub async fn wait_until_ready(probe: &ReadyProbe) -> Result<(), Box<dyn Error>> {
+    let attempts = probe.retries.max(1);
+    let mut last_error = String::new();
+
+    for attempt in 1..=attempts {
+        match check(probe).await {
+            Ok(_) => return Ok(()),
+            Err(e) => last_error = e.to_string(),
+        }
+        if attempt < attempts {
+            tokio::time::sleep(Duration::from_secs(probe.interval)).await;
+        }
+    }
+
+    Err(Box::from(format!(
+        "not ready after {} attempts: {}",
+        attempts, last_error
+    )))
+}
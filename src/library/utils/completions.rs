@@ -0,0 +1,96 @@
+use clap_complete::Shell;
+
+/// Command the completion scripts run to list the services of the project in the current directory
+const SERVICES_COMMAND: &str = "wm config services 2>/dev/null";
+
+/// Applies `rewrite` to the lines of the case arms of the given subcommands. `arm` gives the
+/// label the generated script uses for a subcommand, the arm ends at its `;;`.
+fn rewrite_arms(
+    script: &str,
+    subcommands: &[String],
+    arm: impl Fn(&str) -> String,
+    rewrite: impl Fn(&str) -> String,
+) -> String {
+    let arms: Vec<String> = subcommands.iter().map(|name| arm(name)).collect();
+    let mut in_arm = false;
+    let mut result = String::with_capacity(script.len());
+
+    for line in script.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if arms.iter().any(|arm| arm == trimmed) {
+            in_arm = true;
+        } else if trimmed == ";;" {
+            in_arm = false;
+        }
+
+        if in_arm {
+            result.push_str(&rewrite(line));
+        } else {
+            result.push_str(line);
+        }
+    }
+
+    result
+}
+
+/// Makes the `service` argument of the given subcommands complete the service names of the
+/// project, by having the generated script ask `wm config services` when it completes.
+/// Only the completions of those subcommands change. Shells other than zsh, bash and fish keep
+/// the static script.
+pub fn add_service_completions(shell: Shell, script: &str, subcommands: &[String]) -> String {
+    match shell {
+        Shell::Zsh => {
+            // `'::service -- Service to stop:' \` gets the helper as its action
+            let script = rewrite_arms(
+                script,
+                subcommands,
+                |name| format!("({})", name),
+                |line| {
+                    let spec = line.trim_start().trim_start_matches('\'');
+                    if !spec.trim_start_matches(':').starts_with("service -- ") {
+                        return line.to_string();
+                    }
+                    match line.rsplit_once(":' \\") {
+                        Some((start, end)) => format!("{}:_wm_services' \\{}", start, end),
+                        None => line.to_string(),
+                    }
+                },
+            );
+            let helper = format!(
+                "_wm_services() {{\n    local -a services\n    services=(${{(f)\"$({})\"}})\n    _describe 'service' services\n}}\n",
+                SERVICES_COMMAND
+            );
+            match script.split_once('\n') {
+                Some((compdef, rest)) => format!("{}\n\n{}{}", compdef, helper, rest),
+                None => format!("{}{}", helper, script),
+            }
+        }
+        Shell::Bash => {
+            // The options of the subcommand list the argument as `[service]` or `<service>`
+            let services = format!("$({})", SERVICES_COMMAND);
+            rewrite_arms(
+                script,
+                subcommands,
+                |name| format!("wm__{})", name.replace('-', "__")),
+                |line| {
+                    if !line.trim_start().starts_with("opts=") {
+                        return line.to_string();
+                    }
+                    line.replace("[service]", &services)
+                        .replace("<service>", &services)
+                },
+            )
+        }
+        Shell::Fish => {
+            let mut script = script.to_string();
+            for subcommand in subcommands {
+                script.push_str(&format!(
+                    "complete -c wm -n \"__fish_wm_using_subcommand {}\" -f -a \"({})\"\n",
+                    subcommand, SERVICES_COMMAND
+                ));
+            }
+            script
+        }
+        _ => script.to_string(),
+    }
+}
@@ -185,3 +185,70 @@ pub async fn replace_env_vars(text: &str) -> String {
 
     result
 }
+
+/// Quotes a value for POSIX shells, single quotes can't be escaped inside single quotes
+/// so they are closed, escaped and reopened
+pub fn quote_sh(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// Joins the arguments into a command line for POSIX shells, only quoting the ones that need it
+pub fn join_sh(args: &[String]) -> String {
+    args.iter()
+        .map(|arg| {
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./:=@,+%".contains(c));
+            if plain {
+                arg.to_string()
+            } else {
+                quote_sh(arg)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Quotes a value for fish, where backslashes and single quotes are escaped inside single quotes
+fn quote_fish(value: &str) -> String {
+    format!("'{}'", value.replace('\\', r"\\").replace('\'', r"\'"))
+}
+
+/// Quotes a value for dotenv files
+fn quote_dotenv(value: &str) -> String {
+    let escaped = value
+        .replace('\\', r"\\")
+        .replace('"', r#"\""#)
+        .replace('\n', r"\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Formats the variables so they can be consumed by other tools.
+/// Supported formats are `dotenv`, `sh`, `fish`, `json` and `direnv`.
+pub fn format_variables(env_vars: &[(String, String)], format: &str) -> String {
+    match format {
+        "json" => {
+            let map: serde_json::Map<String, serde_json::Value> = env_vars
+                .iter()
+                .map(|(key, value)| (key.clone(), serde_json::Value::from(value.as_str())))
+                .collect();
+            serde_json::to_string_pretty(&map).unwrap()
+        }
+        "sh" | "direnv" => env_vars
+            .iter()
+            .map(|(key, value)| format!("export {}={}", key, quote_sh(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        "fish" => env_vars
+            .iter()
+            .map(|(key, value)| format!("set -gx {} {}", key, quote_fish(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => env_vars
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, quote_dotenv(value)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
@@ -1,8 +1,9 @@
 #![allow(unused)]
 use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use regex::Regex;
-use tokio::io::{self, stdout, AsyncWriteExt};
+use tokio::io::{self, stderr, stdout, AsyncWrite, AsyncWriteExt};
 
 use super::env_vars;
 
@@ -45,13 +46,41 @@ const LOG_ERROR: &str = "\x1b[1;31mERROR   \x1b[0m";
 const LOG_WARN: &str = "\x1b[1;33mWARN    \x1b[0m";
 const LOG_DEBUG: &str = "\x1b[1;34mDEBUG   \x1b[0m";
 
+// When set, all the logs go to stderr so stdout only has the output of the command
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Send all the logs to stderr from now on
+pub fn log_to_stderr() {
+    LOG_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub fn is_logging_to_stderr() -> bool {
+    LOG_TO_STDERR.load(Ordering::Relaxed)
+}
+
+fn output() -> Box<dyn AsyncWrite + Unpin + Send> {
+    if is_logging_to_stderr() {
+        Box::new(stderr())
+    } else {
+        Box::new(stdout())
+    }
+}
+
 // Function to print messages in color
 pub async fn print_color(color: &str, message: &str) {
     // println!("{}{}{}", color, message, NC);
     let parsed_message = format!("{}{}{}\n", color, message, NC);
-    let mut stdout = stdout();
-    stdout.write_all(parsed_message.as_bytes()).await.unwrap();
-    stdout.flush().await.unwrap();
+    let mut output = output();
+    output.write_all(parsed_message.as_bytes()).await.unwrap();
+    output.flush().await.unwrap();
+}
+
+/// Same as `print_color`, but always to stderr, so it never mixes with output meant for scripts
+pub async fn print_color_stderr(color: &str, message: &str) {
+    let parsed_message = format!("{}{}{}\n", color, message, NC);
+    let mut output = stderr();
+    output.write_all(parsed_message.as_bytes()).await.unwrap();
+    output.flush().await.unwrap();
 }
 
 // Logging functions
@@ -71,9 +100,67 @@ pub async fn error(message: &str) {
     print_color(LOG_ERROR, message).await;
 }
 
+/// Same as `warn`, but always to stderr
+pub async fn warn_stderr(message: &str) {
+    print_color_stderr(LOG_WARN, message).await;
+}
+
+/// Same as `error`, but always to stderr
+pub async fn error_stderr(message: &str) {
+    print_color_stderr(LOG_ERROR, message).await;
+}
+
 /// Print a newline
 pub async fn nl() {
-    let mut stdout = stdout();
-    stdout.write_all("\n".as_bytes()).await.unwrap();
-    stdout.flush().await.unwrap();
+    let mut output = output();
+    output.write_all("\n".as_bytes()).await.unwrap();
+    output.flush().await.unwrap();
+}
+
+/// The lines of a table with a border, each column as wide as its longest value
+pub fn table(header: &[String], rows: &[Vec<String>]) -> Vec<String> {
+    // We need to find the longest value of each column so we can align the table
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(Vec::as_slice)
+                .chain(std::iter::once(header))
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    // Using longer | character for sides: │
+    let margin = |left: &str, middle: &str, right: &str| {
+        let columns: Vec<String> = widths.iter().map(|width| "─".repeat(*width)).collect();
+        format!(
+            "{}─{}─{}",
+            left,
+            columns.join(&format!("─{}─", middle)),
+            right
+        )
+    };
+    let line = |row: &[String]| {
+        let columns: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect();
+        format!("│ {} │", columns.join(" │ "))
+    };
+
+    let mut lines = vec![margin("┌", "┬", "┐"), line(header), margin("├", "┼", "┤")];
+    lines.extend(rows.iter().map(|row| line(row)));
+    lines.push(margin("└", "┴", "┘"));
+    lines
+}
+
+/// Prints a title in the given color followed by the table
+pub async fn print_table(color: &str, title: &str, header: &[String], rows: &[Vec<String>]) {
+    nl().await;
+    print_color(color, title).await;
+    for line in table(header, rows) {
+        print_color(NC, &line).await;
+    }
 }
@@ -1,4 +1,5 @@
 pub mod bitwarden;
+pub mod completions;
 pub mod env_vars;
 pub mod logging;
 pub mod updater;
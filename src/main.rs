@@ -1,16 +1,20 @@
 use clap::{ArgMatches, Command};
-use clap_complete::{generate, Generator, Shell};
+use clap_complete::{generate, Shell};
 use futures::future;
 use lazy_static::lazy_static;
 use serde_json::{json, Value};
-use std::io;
 use std::path::PathBuf;
-use wm::library::commands::run;
+use wm::library::commands::dry_run::{self, PlanOptions};
+use wm::library::commands::{
+    all_ports, build, env, exec, export, graph, import_compose, init, lock, logs, ports, pull, run,
+    shell, status,
+};
 use wm::library::config::{commands, dependencies, features, requirements, services};
 use wm::library::machine;
 use wm::library::secrets::{generic, keyring};
 use wm::library::system::config;
-use wm::library::utils::{env_vars, logging, updater};
+use wm::library::utils::{completions, env_vars, logging, updater};
+use wm::models::config::{Config, Service};
 
 mod cli;
 
@@ -26,8 +30,26 @@ lazy_static! {
         Box::leak(BIND_ADDRESS.clone().into_boxed_str());
 }
 
-fn print_completions<G: Generator>(gen: G, cmd: &mut Command) {
-    generate(gen, cmd, cmd.get_name().to_string(), &mut io::stdout());
+fn print_completions(shell: Shell, cmd: &mut Command) {
+    let mut script: Vec<u8> = vec![];
+    generate(shell, cmd, cmd.get_name().to_string(), &mut script);
+
+    let subcommands: Vec<String> = cmd
+        .get_subcommands()
+        .filter(|subcommand| {
+            subcommand
+                .get_arguments()
+                .any(|arg| arg.get_id() == "service")
+        })
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+
+    let script = completions::add_service_completions(
+        shell,
+        &String::from_utf8_lossy(&script),
+        &subcommands,
+    );
+    print!("{}", script);
 }
 
 async fn handle_run_mode(matches: ArgMatches) {
@@ -36,6 +58,7 @@ async fn handle_run_mode(matches: ArgMatches) {
     let mut no_services = false;
     let mut no_features = false;
     let mut no_requirements = false;
+    let mut dry_run_mode = false;
     let mut config_path = CONFIG_PATH.clone();
 
     let mut command_args = "".to_string();
@@ -72,6 +95,8 @@ async fn handle_run_mode(matches: ArgMatches) {
             run_clean_mode = true;
         }
 
+        dry_run_mode = run_matches.get_flag("dry-run");
+
         if let Some(passed_command_args) = run_matches.get_many::<String>("command_args") {
             for arg in passed_command_args {
                 command_args = command_args + &arg + " ";
@@ -81,13 +106,42 @@ async fn handle_run_mode(matches: ArgMatches) {
 
     let config = config::parse(config_path).await;
 
-    let machine_state = machine::state::check(&config, run_clean_mode).await;
-
     // Check that the command is in the config
     if let Some(ref asserted_command) = command_name {
         commands::check(&config, &asserted_command).await;
     }
 
+    // A dry run only reads the state, it picks the ports it would use without saving them
+    if dry_run_mode {
+        if !no_services {
+            dependencies::check(&config).await;
+        }
+
+        let (machine_state, port_conflicts) =
+            machine::state::preview(&config, run_clean_mode).await;
+        let options = PlanOptions {
+            no_services,
+            no_features,
+            no_requirements,
+            clean_mode: run_clean_mode,
+        };
+        let mut plan = vec![dry_run::port_conflicts_section(port_conflicts)];
+        plan.extend(
+            dry_run::run_plan(
+                &machine_state,
+                &config,
+                &options,
+                command_name.as_deref(),
+                &command_args,
+            )
+            .await,
+        );
+        dry_run::print("run", &plan).await;
+        return;
+    }
+
+    let machine_state = machine::state::check(&config, run_clean_mode).await;
+
     let secrets = keyring::get_secrets().await;
 
     if !no_features {
@@ -114,9 +168,32 @@ async fn handle_run_mode(matches: ArgMatches) {
     .await;
 }
 
+/// Every service with `all`, or the named one. Exits when the service isn't in the config.
+async fn selected_services<'a>(
+    config: &'a Config,
+    all: bool,
+    service_name: Option<&str>,
+) -> Vec<&'a Service> {
+    if all {
+        return config.services.iter().collect();
+    }
+
+    match service_name {
+        Some(service_name) => match config.services.iter().find(|s| s.name == service_name) {
+            Some(service) => vec![service],
+            None => {
+                logging::error(&format!("Service {} not found", service_name)).await;
+                std::process::exit(1);
+            }
+        },
+        None => vec![],
+    }
+}
+
 async fn handle_clean_mode(matches: ArgMatches) {
     let mut config_path = CONFIG_PATH.clone();
     let mut clean_all = false;
+    let mut dry_run_mode = false;
 
     let mut service_name = None;
     if let Some(run_matches) = matches.subcommand_matches("clean") {
@@ -137,10 +214,21 @@ async fn handle_clean_mode(matches: ArgMatches) {
             logging::info(&format!("Config file: {}", passed_config_path.display())).await;
             config_path = passed_config_path.to_owned();
         }
+
+        dry_run_mode = run_matches.get_flag("dry-run");
     }
 
     let config = config::parse(config_path).await;
 
+    if dry_run_mode {
+        let (machine_state, port_conflicts) = machine::state::preview(&config, false).await;
+        let services = selected_services(&config, clean_all, service_name.as_deref()).await;
+        let mut plan = vec![dry_run::port_conflicts_section(port_conflicts)];
+        plan.extend(dry_run::clean_plan(&machine_state, &config, &services).await);
+        dry_run::print("clean", &plan).await;
+        return;
+    }
+
     let machine_state = machine::state::check(&config, false).await;
 
     if clean_all {
@@ -149,11 +237,17 @@ async fn handle_clean_mode(matches: ArgMatches) {
             tasks.push(services::clean(&machine_state, &config, service, true));
         }
         future::join_all(tasks).await;
+
+        let names: Vec<&str> = config.services.iter().map(|s| s.name.as_str()).collect();
+        machine::state::forget_definitions(&names).await;
     } else if let Some(service_name) = service_name {
         let service = config.services.iter().find(|s| s.name == service_name);
 
         match service {
-            Some(service) => services::clean(&machine_state, &config, &service, true).await,
+            Some(service) => {
+                services::clean(&machine_state, &config, &service, true).await;
+                machine::state::forget_definitions(&[service_name.as_str()]).await;
+            }
             None => {
                 logging::error(&format!("Service {} not found", service_name)).await;
                 std::process::exit(1);
@@ -162,6 +256,484 @@ async fn handle_clean_mode(matches: ArgMatches) {
     }
 }
 
+async fn handle_stop_mode(matches: ArgMatches) {
+    let mut config_path = CONFIG_PATH.clone();
+    let mut stop_all = false;
+    let mut dry_run_mode = false;
+
+    let mut service_name = None;
+    if let Some(stop_matches) = matches.subcommand_matches("stop") {
+        if stop_matches.get_flag("all") {
+            logging::warn("🛑 Stopping all services").await;
+            stop_all = true;
+        }
+        if let Some(passed_service_name) = stop_matches.get_one::<String>("service") {
+            service_name = Some(passed_service_name.to_owned());
+            logging::info(&format!("🛑 Stopping service: {}", passed_service_name)).await;
+        } else if !stop_all {
+            logging::error("No service name provided. Stop all services with the --all flag").await;
+            std::process::exit(1);
+        }
+
+        if let Some(passed_config_path) = stop_matches.get_one::<PathBuf>("config") {
+            logging::info(&format!("Config file: {}", passed_config_path.display())).await;
+            config_path = passed_config_path.to_owned();
+        }
+
+        dry_run_mode = stop_matches.get_flag("dry-run");
+    }
+
+    let config = config::parse(config_path).await;
+
+    // Stopping uses the ports the services were started with, allocating is left to `run`
+    let machine_state = machine::state::get_machine_state().await;
+
+    if dry_run_mode {
+        let services = selected_services(&config, stop_all, service_name.as_deref()).await;
+        let plan = dry_run::stop_plan(&machine_state, &config, &services).await;
+        dry_run::print("stop", &plan).await;
+        return;
+    }
+
+    if stop_all {
+        dependencies::check(&config).await;
+        services::stop_all(&machine_state, &config, true).await;
+    } else if let Some(service_name) = service_name {
+        let service = config.services.iter().find(|s| s.name == service_name);
+
+        match service {
+            Some(service) => services::stop(&machine_state, &config, service, true).await,
+            None => {
+                logging::error(&format!("Service {} not found", service_name)).await;
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn handle_status_mode(matches: ArgMatches) {
+    let mut config_path = CONFIG_PATH.clone();
+    let mut json_output = false;
+
+    if let Some(status_matches) = matches.subcommand_matches("status") {
+        if let Some(passed_config_path) = status_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        if let Some(output) = status_matches.get_one::<String>("output") {
+            json_output = output == "json";
+        }
+    }
+
+    // Keep stdout clean so the JSON can be parsed
+    if json_output {
+        logging::log_to_stderr();
+    }
+
+    let config = config::parse(config_path).await;
+
+    // Only read the state, checking it would allocate ports for services that never ran
+    let machine_state = machine::state::get_machine_state().await;
+
+    status(&machine_state, &config, json_output).await;
+}
+
+async fn handle_logs_mode(matches: ArgMatches) {
+    let mut config_path = CONFIG_PATH.clone();
+    let mut service_name = None;
+    let mut follow = false;
+    let mut since = None;
+    let mut tail = None;
+
+    if let Some(logs_matches) = matches.subcommand_matches("logs") {
+        if let Some(passed_service_name) = logs_matches.get_one::<String>("service") {
+            service_name = Some(passed_service_name.to_owned());
+        } else if !logs_matches.get_flag("all") {
+            logging::error(
+                "No service name provided. Show the logs of all services with the --all flag",
+            )
+            .await;
+            std::process::exit(1);
+        }
+
+        if let Some(passed_config_path) = logs_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        follow = logs_matches.get_flag("follow");
+        since = logs_matches.get_one::<String>("since").cloned();
+        tail = logs_matches.get_one::<String>("tail").cloned();
+    }
+
+    let config = config::parse(config_path).await;
+
+    logs(
+        &config,
+        service_name,
+        follow,
+        since.as_deref(),
+        tail.as_deref(),
+    )
+    .await;
+}
+
+async fn handle_env_mode(matches: ArgMatches) {
+    // Keep stdout clean so the output can be evaluated by a shell
+    logging::log_to_stderr();
+
+    let mut config_path = CONFIG_PATH.clone();
+    let mut format = "dotenv".to_string();
+    let mut no_services = false;
+    let mut no_features = false;
+    let mut no_requirements = false;
+
+    if let Some(env_matches) = matches.subcommand_matches("env") {
+        if let Some(passed_config_path) = env_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        if let Some(passed_format) = env_matches.get_one::<String>("format") {
+            format = passed_format.to_owned();
+        }
+
+        no_services = env_matches.get_flag("no-services");
+        no_features = env_matches.get_flag("no-features");
+        no_requirements = env_matches.get_flag("no-requirements");
+    }
+
+    let config = config::parse(config_path.clone()).await;
+
+    let machine_state = machine::state::check(&config, false).await;
+
+    let secrets = keyring::get_secrets().await;
+
+    if !no_features {
+        features::check(&config, &secrets).await;
+    }
+
+    if !no_requirements {
+        requirements::check(&config).await;
+    }
+
+    if !no_services {
+        dependencies::check(&config).await;
+    }
+
+    env(
+        machine_state,
+        config,
+        &config_path,
+        secrets,
+        &format,
+        no_services,
+        no_features,
+    )
+    .await;
+}
+
+async fn handle_shell_mode(matches: ArgMatches) {
+    let mut clean_mode = false;
+    let mut no_services = false;
+    let mut no_features = false;
+    let mut no_requirements = false;
+    let mut stop_on_exit = false;
+    let mut dry_run_mode = false;
+    let mut config_path = CONFIG_PATH.clone();
+
+    if let Some(shell_matches) = matches.subcommand_matches("shell") {
+        if let Some(passed_config_path) = shell_matches.get_one::<PathBuf>("config") {
+            logging::info(&format!("Config file: {}", passed_config_path.display())).await;
+            config_path = passed_config_path.to_owned();
+        }
+
+        if shell_matches.get_flag("no-services") {
+            logging::warn("Running without services").await;
+            no_services = true;
+        }
+
+        if shell_matches.get_flag("no-features") {
+            logging::warn("Running without features").await;
+            no_features = true;
+        }
+
+        if shell_matches.get_flag("no-requirements") {
+            logging::warn("Running without requirements").await;
+            no_requirements = true;
+        }
+
+        if shell_matches.get_flag("clean") {
+            logging::warn("Cleaning the docker environment before starting the shell").await;
+            clean_mode = true;
+        }
+
+        stop_on_exit = shell_matches.get_flag("stop");
+        dry_run_mode = shell_matches.get_flag("dry-run");
+    }
+
+    let config = config::parse(config_path).await;
+
+    // A dry run only reads the state, it picks the ports it would use without saving them
+    if dry_run_mode {
+        if !no_services {
+            dependencies::check(&config).await;
+        }
+
+        let (machine_state, port_conflicts) = machine::state::preview(&config, clean_mode).await;
+        let options = PlanOptions {
+            no_services,
+            no_features,
+            no_requirements,
+            clean_mode,
+        };
+        let mut plan = vec![dry_run::port_conflicts_section(port_conflicts)];
+        plan.extend(dry_run::shell_plan(&machine_state, &config, &options, stop_on_exit).await);
+        dry_run::print("shell", &plan).await;
+        return;
+    }
+
+    let machine_state = machine::state::check(&config, clean_mode).await;
+
+    let secrets = keyring::get_secrets().await;
+
+    if !no_features {
+        features::check(&config, &secrets).await;
+    }
+    if !no_requirements {
+        requirements::check(&config).await;
+    }
+
+    if !no_services {
+        dependencies::check(&config).await;
+    }
+
+    shell(
+        machine_state,
+        config,
+        secrets,
+        no_services,
+        no_features,
+        clean_mode,
+        stop_on_exit,
+    )
+    .await;
+}
+
+async fn handle_exec_mode(matches: ArgMatches) {
+    let mut config_path = CONFIG_PATH.clone();
+    let mut service_name = String::new();
+    let mut command_args: Vec<String> = vec![];
+
+    if let Some(exec_matches) = matches.subcommand_matches("exec") {
+        if let Some(passed_service_name) = exec_matches.get_one::<String>("service") {
+            service_name = passed_service_name.to_owned();
+        }
+
+        if let Some(passed_config_path) = exec_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        if let Some(passed_command_args) = exec_matches.get_many::<String>("command_args") {
+            command_args = passed_command_args.cloned().collect();
+        }
+    }
+
+    let config = config::parse(config_path).await;
+
+    // Only read the state, the service is expected to be running already
+    let machine_state = machine::state::get_machine_state().await;
+
+    exec(&machine_state, &config, &service_name, &command_args).await;
+}
+
+async fn handle_init_mode(matches: ArgMatches) {
+    if let Some(init_matches) = matches.subcommand_matches("init") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = init_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let presets: Vec<String> = init_matches
+            .get_many::<String>("preset")
+            .map(|presets| presets.cloned().collect())
+            .unwrap_or_default();
+
+        init(config_path, presets, init_matches.get_flag("force")).await;
+    }
+}
+
+async fn handle_import_mode(matches: ArgMatches) {
+    if let Some(import_matches) = matches.subcommand_matches("import") {
+        if let Some(compose_matches) = import_matches.subcommand_matches("compose") {
+            let mut config_path = CONFIG_PATH.clone();
+            if let Some(passed_config_path) = compose_matches.get_one::<PathBuf>("config") {
+                config_path = passed_config_path.to_owned();
+            }
+
+            let compose_path = compose_matches
+                .get_one::<PathBuf>("file")
+                .unwrap()
+                .to_owned();
+
+            import_compose(config_path, compose_path, compose_matches.get_flag("force")).await;
+        }
+    }
+}
+
+async fn handle_export_mode(matches: ArgMatches) {
+    // Keep stdout clean so the output can be redirected to a file
+    logging::log_to_stderr();
+
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = export_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let target = export_matches.get_one::<String>("target").unwrap();
+        let output = export_matches.get_one::<PathBuf>("output").cloned();
+
+        let config = config::parse(config_path).await;
+
+        // Makes sure every port has been assigned
+        let machine_state = machine::state::check(&config, false).await;
+
+        export(&machine_state, &config, target, output).await;
+    }
+}
+
+async fn handle_graph_mode(matches: ArgMatches) {
+    if let Some(graph_matches) = matches.subcommand_matches("graph") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = graph_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let format = graph_matches.get_one::<String>("format").unwrap();
+
+        let config = config::parse(config_path).await;
+
+        graph(&config, format).await;
+    }
+}
+
+async fn handle_ports_mode(matches: ArgMatches) {
+    if let Some(ports_matches) = matches.subcommand_matches("ports") {
+        if ports_matches.get_flag("all") {
+            all_ports().await;
+            return;
+        }
+
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = ports_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let config = config::parse(config_path).await;
+
+        ports(&config).await;
+    }
+}
+
+async fn handle_build_mode(matches: ArgMatches) {
+    if let Some(build_matches) = matches.subcommand_matches("build") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = build_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let config = config::parse(config_path).await;
+
+        // A dry run gets the port conflicts it would stop on instead of stopping
+        let (machine_state, dry_run) = if build_matches.get_flag("dry-run") {
+            let (machine_state, port_conflicts) = machine::state::preview(&config, false).await;
+            (machine_state, Some(port_conflicts))
+        } else {
+            (machine::state::check(&config, false).await, None)
+        };
+
+        let service_name = build_matches.get_one::<String>("service");
+        build(
+            &machine_state,
+            &config,
+            service_name.map(|name| name.as_str()),
+            dry_run,
+        )
+        .await;
+    }
+}
+
+async fn handle_pull_mode(matches: ArgMatches) {
+    if let Some(pull_matches) = matches.subcommand_matches("pull") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = pull_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let config = config::parse(config_path).await;
+
+        let service_name = pull_matches.get_one::<String>("service");
+        pull(
+            &config,
+            service_name.map(|name| name.as_str()),
+            pull_matches.get_flag("dry-run"),
+        )
+        .await;
+    }
+}
+
+async fn handle_lock_mode(matches: ArgMatches) {
+    if let Some(lock_matches) = matches.subcommand_matches("lock") {
+        let mut config_path = CONFIG_PATH.clone();
+        if let Some(passed_config_path) = lock_matches.get_one::<PathBuf>("config") {
+            config_path = passed_config_path.to_owned();
+        }
+
+        let config = config::parse(config_path.clone()).await;
+
+        let service_name = lock_matches.get_one::<String>("service");
+        lock(
+            &config,
+            &config_path,
+            service_name.map(|name| name.as_str()),
+            lock_matches.get_flag("update"),
+            lock_matches.get_flag("dry-run"),
+        )
+        .await;
+    }
+}
+
+async fn handle_config_mode(matches: ArgMatches) {
+    if let Some(config_matches) = matches.subcommand_matches("config") {
+        if let Some(validate_matches) = config_matches.subcommand_matches("validate") {
+            let mut config_path = CONFIG_PATH.clone();
+            if let Some(passed_config_path) = validate_matches.get_one::<PathBuf>("config") {
+                config_path = passed_config_path.to_owned();
+            }
+
+            // Parsing validates the file and exits on errors
+            config::parse(config_path.clone()).await;
+            logging::info(&format!("✅ {} is valid", config_path.display())).await;
+        }
+
+        if let Some(services_matches) = config_matches.subcommand_matches("services") {
+            let mut config_path = CONFIG_PATH.clone();
+            if let Some(passed_config_path) = services_matches.get_one::<PathBuf>("config") {
+                config_path = passed_config_path.to_owned();
+            }
+
+            // Completions run this on every tab, so a broken file just completes nothing
+            let config = std::fs::read_to_string(&config_path)
+                .ok()
+                .and_then(|content| toml::from_str::<Config>(&content).ok());
+            if let Some(config) = config {
+                for service in &config.services {
+                    println!("{}", service.name);
+                }
+            }
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let matches = cli::build().get_matches();
@@ -171,11 +743,56 @@ async fn main() {
     let completions_mode = matches.subcommand_matches("completions").is_some();
     let secrets_mode = matches.subcommand_matches("secret").is_some();
     let clean_mode = matches.subcommand_matches("clean").is_some();
+    let stop_mode = matches.subcommand_matches("stop").is_some();
+    let status_mode = matches.subcommand_matches("status").is_some();
+    let logs_mode = matches.subcommand_matches("logs").is_some();
+    let env_mode = matches.subcommand_matches("env").is_some();
+    let shell_mode = matches.subcommand_matches("shell").is_some();
+    let exec_mode = matches.subcommand_matches("exec").is_some();
+    let config_mode = matches.subcommand_matches("config").is_some();
+    let init_mode = matches.subcommand_matches("init").is_some();
+    let import_mode = matches.subcommand_matches("import").is_some();
+    let export_mode = matches.subcommand_matches("export").is_some();
+    let graph_mode = matches.subcommand_matches("graph").is_some();
+    let ports_mode = matches.subcommand_matches("ports").is_some();
+    let build_mode = matches.subcommand_matches("build").is_some();
+    let pull_mode = matches.subcommand_matches("pull").is_some();
+    let lock_mode = matches.subcommand_matches("lock").is_some();
 
     if run_mode {
         handle_run_mode(matches).await;
     } else if clean_mode {
         handle_clean_mode(matches).await;
+    } else if stop_mode {
+        handle_stop_mode(matches).await;
+    } else if status_mode {
+        handle_status_mode(matches).await;
+    } else if logs_mode {
+        handle_logs_mode(matches).await;
+    } else if env_mode {
+        handle_env_mode(matches).await;
+    } else if shell_mode {
+        handle_shell_mode(matches).await;
+    } else if exec_mode {
+        handle_exec_mode(matches).await;
+    } else if config_mode {
+        handle_config_mode(matches).await;
+    } else if init_mode {
+        handle_init_mode(matches).await;
+    } else if import_mode {
+        handle_import_mode(matches).await;
+    } else if export_mode {
+        handle_export_mode(matches).await;
+    } else if graph_mode {
+        handle_graph_mode(matches).await;
+    } else if ports_mode {
+        handle_ports_mode(matches).await;
+    } else if build_mode {
+        handle_build_mode(matches).await;
+    } else if pull_mode {
+        handle_pull_mode(matches).await;
+    } else if lock_mode {
+        handle_lock_mode(matches).await;
     } else if update_mode {
         updater::update().await;
     } else if completions_mode {
@@ -1,7 +1,10 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
+use super::lockfile::Lockfile;
+
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum Requirement {
@@ -12,19 +15,111 @@ pub enum Requirement {
     Poetry,
 }
 
+/// When images are pulled before their service starts
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PullPolicy {
+    /// On every start
+    Always,
+    /// Only when the image isn't available locally
+    Missing,
+    /// Never, the image has to be available locally
+    Never,
+}
+
+fn default_config_path() -> PathBuf {
+    PathBuf::from("war_machine.toml")
+}
+
+fn default_pull_policy() -> PullPolicy {
+    PullPolicy::Missing
+}
+
 fn default_registry() -> String {
     "docker.io".to_string()
 }
 
+fn default_container_env() -> HashMap<String, String> {
+    HashMap::new()
+}
+
+fn default_container_list() -> Vec<String> {
+    vec![]
+}
+
+/// Healthcheck docker runs in the container, passed as the `--health-*` flags
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ContainerHealthcheck {
+    pub command: String,
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+    pub retries: Option<u32>,
+    pub start_period: Option<String>,
+}
+
+fn default_build_args() -> HashMap<String, String> {
+    HashMap::new()
+}
+
+/// Builds the image from a Dockerfile instead of pulling it
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ContainerBuild {
+    pub context: String,
+
+    /// Relative to the context, `Dockerfile` when not set
+    pub dockerfile: Option<String>,
+
+    #[serde(default = "default_build_args")]
+    pub args: HashMap<String, String>,
+
+    pub target: Option<String>,
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct ContainerSource {
+    /// Not needed when the image is built, built images are named `<machine_name>/<service>`
+    #[serde(default)]
     pub image: String,
+
+    /// Required for pulled images, built images are tagged `latest` when it isn't set
+    #[serde(default)]
     pub tag: String,
+
+    pub build: Option<ContainerBuild>,
+
+    /// Overrides the `pull_policy` of the configuration
+    pub pull_policy: Option<PullPolicy>,
+
+    /// Runs the container instead of the command built from the fields below
     pub start_command: Option<String>,
     pub stop_command: Option<String>,
 
+    /// Used instead of the docker health status when waiting for the service to be healthy
+    pub health_check_command: Option<String>,
+
     #[serde(default = "default_registry")]
     pub registry: String,
+
+    #[serde(default = "default_container_env")]
+    pub env: HashMap<String, String>,
+
+    #[serde(default = "default_container_list")]
+    pub volumes: Vec<String>,
+
+    #[serde(default = "default_container_list")]
+    pub networks: Vec<String>,
+
+    /// Arguments passed to the image after its name
+    #[serde(default = "default_container_list")]
+    pub command: Vec<String>,
+
+    pub entrypoint: Option<String>,
+    pub healthcheck: Option<ContainerHealthcheck>,
+    pub user: Option<String>,
+
+    /// Passed to `docker run` as is, before the image
+    #[serde(default = "default_container_list")]
+    pub extra_args: Vec<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
@@ -40,8 +135,9 @@ pub struct AppSource {
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 #[serde(untagged)]
 pub enum Source {
-    Container(ContainerSource),
+    // Apps come first, their required fields tell them apart from containers
     App(AppSource),
+    Container(Box<ContainerSource>),
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -104,6 +200,61 @@ fn default_depends_on() -> Vec<String> {
     vec![]
 }
 
+fn default_service_ports() -> Vec<ServicePort> {
+    vec![]
+}
+
+/// A port of the service, allocated as `${port.<service>.<name>}`
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ServicePort {
+    pub name: String,
+
+    /// Port inside the container that the allocated port is published to
+    pub container: Option<u16>,
+}
+
+fn default_probe_interval() -> u64 {
+    1
+}
+
+fn default_probe_timeout() -> u64 {
+    2
+}
+
+fn default_probe_retries() -> u32 {
+    30
+}
+
+/// Checks that a service is ready to be used. Exactly one of `tcp`, `http` or `exec` is set.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ReadyProbe {
+    /// `host:port`, or just a port on localhost
+    pub tcp: Option<String>,
+    pub http: Option<String>,
+    /// Expected HTTP status, any 2xx status when not set
+    pub status: Option<u16>,
+    pub exec: Option<String>,
+
+    /// Seconds between attempts
+    #[serde(default = "default_probe_interval")]
+    pub interval: u64,
+
+    /// Seconds before a single attempt is given up
+    #[serde(default = "default_probe_timeout")]
+    pub timeout: u64,
+
+    #[serde(default = "default_probe_retries")]
+    pub retries: u32,
+}
+
+fn default_wait_for_healthy() -> bool {
+    false
+}
+
+fn default_health_timeout() -> u64 {
+    60
+}
+
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub struct Service {
     pub name: String,
@@ -117,6 +268,20 @@ pub struct Service {
 
     #[serde(default = "default_depends_on")]
     pub depends_on: Vec<String>,
+
+    /// Wait for the service to be healthy before its dependents and the command start
+    #[serde(default = "default_wait_for_healthy")]
+    pub wait_for_healthy: bool,
+
+    /// Seconds to wait for the service to be healthy
+    #[serde(default = "default_health_timeout")]
+    pub health_timeout: u64,
+
+    /// Probe that has to pass before the service counts as started
+    pub ready: Option<ReadyProbe>,
+
+    #[serde(default = "default_service_ports")]
+    pub ports: Vec<ServicePort>,
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
@@ -150,6 +315,32 @@ fn default_features() -> Vec<Feature> {
     vec![]
 }
 
+fn default_max_concurrent_starts() -> usize {
+    4
+}
+
+fn default_port_range() -> PortRange {
+    PortRange {
+        start: 49000,
+        end: 65535,
+    }
+}
+
+fn default_excluded_ports() -> Vec<u16> {
+    vec![]
+}
+
+fn default_pinned_ports() -> HashMap<String, u16> {
+    HashMap::new()
+}
+
+/// Inclusive range of ports that free ports are picked from
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
 fn default_registry_credentials() -> Vec<RegistryCredentials> {
     vec![]
 }
@@ -180,4 +371,31 @@ pub struct Config {
 
     #[serde(default = "default_registry_credentials")]
     pub registry_credentials: Vec<RegistryCredentials>,
+
+    /// How many services can be starting at the same time
+    #[serde(default = "default_max_concurrent_starts")]
+    pub max_concurrent_starts: usize,
+
+    #[serde(default = "default_port_range")]
+    pub port_range: PortRange,
+
+    /// Ports that are never picked
+    #[serde(default = "default_excluded_ports")]
+    pub excluded_ports: Vec<u16>,
+
+    /// Ports that are used as is instead of picking a free one
+    #[serde(default = "default_pinned_ports")]
+    pub ports: HashMap<String, u16>,
+
+    /// Pull policy of the container services that don't set their own
+    #[serde(default = "default_pull_policy")]
+    pub pull_policy: PullPolicy,
+
+    /// Read from the lockfile next to the configuration file, not from the configuration
+    #[serde(skip)]
+    pub lock: Lockfile,
+
+    /// The file the configuration was read from, the lockfile is next to it
+    #[serde(skip, default = "default_config_path")]
+    pub config_path: PathBuf,
 }
@@ -0,0 +1,16 @@
+use std::ops::Range;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A problem found in the configuration file, `span` is the byte range it points to
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<Range<usize>>,
+    pub help: Option<String>,
+}
@@ -0,0 +1,18 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// The digest an image reference resolved to when it was locked
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct LockedImage {
+    /// `registry/image:tag` as configured, the digest is only used while it doesn't change
+    pub image: String,
+    pub digest: String,
+}
+
+/// Contents of `war_machine.lock`, the locked images by service name
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub images: BTreeMap<String, LockedImage>,
+}
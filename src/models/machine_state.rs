@@ -1,9 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use serde::{Deserialize, Serialize};
 
+/// What a service was last started from, with its placeholders replaced
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct ServiceDefinition {
+    pub hash: String,
+    /// The values the hash is made of, kept to show what changed
+    pub fields: BTreeMap<String, String>,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct MachineState {
-    pub containers: HashMap<String, String>,
+    /// Definitions of the services that were started, by service name
+    pub containers: HashMap<String, ServiceDefinition>,
     pub ports: HashMap<String, i32>,
 }
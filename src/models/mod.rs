@@ -1,2 +1,7 @@
 pub mod config;
+pub mod diagnostic;
+pub mod lockfile;
 pub mod machine_state;
+pub mod port_registry;
+pub mod pull_error;
+pub mod service_status;
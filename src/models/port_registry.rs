@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct RegisteredPort {
+    pub port: u16,
+    pub name: String,
+    pub machine_name: String,
+    pub project_path: String,
+}
+
+/// Ports of every war machine project of the user
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct PortRegistry {
+    pub ports: Vec<RegisteredPort>,
+}
@@ -0,0 +1,41 @@
+use std::{error::Error, fmt};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum PullErrorKind {
+    /// `docker pull` failed, with what it printed
+    Failed(String),
+    /// The pull policy is `never` and the image isn't available locally
+    NotAvailable,
+}
+
+/// Why the image of a service couldn't be made available
+#[derive(Debug, PartialEq, Clone)]
+pub struct PullError {
+    pub service: String,
+    pub image: String,
+    pub kind: PullErrorKind,
+}
+
+impl fmt::Display for PullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            PullErrorKind::Failed(output) if output.trim().is_empty() => {
+                write!(f, "failed to pull {} for {}", self.image, self.service)
+            }
+            PullErrorKind::Failed(output) => write!(
+                f,
+                "failed to pull {} for {}: {}",
+                self.image,
+                self.service,
+                output.trim()
+            ),
+            PullErrorKind::NotAvailable => write!(
+                f,
+                "{} for {} isn't available locally and its pull policy is `never`",
+                self.image, self.service
+            ),
+        }
+    }
+}
+
+impl Error for PullError {}
@@ -0,0 +1,16 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ServiceStatus {
+    pub name: String,
+    pub running: bool,
+    pub health: Option<String>,
+    pub container_id: Option<String>,
+    pub pid: Option<u32>,
+    pub image: Option<String>,
+    pub ports: HashMap<String, i32>,
+    pub uptime: Option<String>,
+    pub error: Option<String>,
+}
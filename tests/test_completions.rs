@@ -0,0 +1,87 @@
+use clap_complete::Shell;
+use wm::library::utils::completions;
+
+/// The subcommands of `wm` that take a `service` argument
+const SERVICE_SUBCOMMANDS: [&str; 7] = ["clean", "stop", "logs", "exec", "build", "pull", "lock"];
+
+/// Generates the completion script for `shell` with the `wm` binary
+fn generate(shell: &str) -> String {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_wm"))
+        .args(["completions", shell])
+        .current_dir(std::env::temp_dir())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The lines of the case arm labeled `label`, up to its `;;`
+fn arm(script: &str, label: &str) -> String {
+    let start = script
+        .lines()
+        .position(|line| line.trim() == label)
+        .unwrap_or_else(|| panic!("no `{}` arm", label));
+    script
+        .lines()
+        .skip(start)
+        .take_while(|line| line.trim() != ";;")
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[test]
+fn test_service_completions_in_generated_scripts() {
+    let hook = "$(wm config services 2>/dev/null)";
+
+    let bash = generate("bash");
+    for subcommand in SERVICE_SUBCOMMANDS {
+        let arm = arm(&bash, &format!("wm__{})", subcommand));
+        assert!(arm.contains(hook), "bash `{}` has no hook", subcommand);
+        assert!(!arm.contains("[service]") && !arm.contains("<service>"));
+    }
+    // Subcommands without a service argument keep their options
+    assert!(!arm(&bash, "wm__env)").contains(hook));
+
+    let zsh = generate("zsh");
+    assert!(zsh.starts_with("#compdef wm\n\n_wm_services() {"));
+    for subcommand in SERVICE_SUBCOMMANDS {
+        let arm = arm(&zsh, &format!("({})", subcommand));
+        assert!(
+            arm.lines()
+                .any(|line| line.contains(":service -- ") && line.ends_with(":_wm_services' \\")),
+            "zsh `{}` has no hook",
+            subcommand
+        );
+    }
+
+    let fish = generate("fish");
+    assert!(fish.contains("function __fish_wm_using_subcommand"));
+    for subcommand in SERVICE_SUBCOMMANDS {
+        assert!(fish.contains(&format!(
+            "complete -c wm -n \"__fish_wm_using_subcommand {}\" -f -a \"(wm config services 2>/dev/null)\"\n",
+            subcommand
+        )));
+    }
+}
+
+#[test]
+fn test_add_service_completions_only_rewrites_listed_subcommands() {
+    let subcommands = vec!["stop".to_string()];
+
+    let bash = "        wm__stop)\n            opts=\"-a [service]\"\n            ;;\n        wm__init)\n            opts=\"[service]\"\n            ;;\n";
+    assert_eq!(
+        completions::add_service_completions(Shell::Bash, bash, &subcommands),
+        "        wm__stop)\n            opts=\"-a $(wm config services 2>/dev/null)\"\n            ;;\n        wm__init)\n            opts=\"[service]\"\n            ;;\n"
+    );
+
+    let zsh = "#compdef wm\n(stop)\n'::service -- Service to stop:' \\\n;;\n(init)\n'::service -- Service:' \\\n;;\n";
+    let zsh = completions::add_service_completions(Shell::Zsh, zsh, &subcommands);
+    assert!(zsh.contains("'::service -- Service to stop:_wm_services' \\"));
+    assert!(zsh.contains("'::service -- Service:' \\"));
+
+    // Other shells keep the static script
+    assert_eq!(
+        completions::add_service_completions(Shell::Elvish, "edit:completion", &subcommands),
+        "edit:completion"
+    );
+}
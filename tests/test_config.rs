@@ -1,6 +1,11 @@
 use std::path::PathBuf;
 
-use wm::library::system::config;
+use wm::library::commands::init;
+use wm::library::config::validation;
+use wm::library::system::{config, docker};
+use wm::models::config::{Config, Source};
+use wm::models::diagnostic::Severity;
+use wm::models::lockfile::LockedImage;
 
 #[tokio::test]
 async fn test_parse_config() {
@@ -10,3 +15,544 @@ async fn test_parse_config() {
     assert!(config.services.len() > 0);
     assert!(config.services[0].exposed_values.len() > 0);
 }
+
+#[test]
+fn test_validate_example_config() {
+    let content = std::fs::read_to_string("war_machine.toml").unwrap();
+    let diagnostics = validation::validate(&content);
+    assert_eq!(diagnostics, vec![]);
+}
+
+#[test]
+fn test_validate_suggests_source_keys() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+    tg    = "latest"
+"#;
+    let diagnostics = validation::validate(content);
+
+    let missing_tag = diagnostics
+        .iter()
+        .find(|diagnostic| diagnostic.message == "service `redis` source is missing `tag`")
+        .unwrap();
+    assert_eq!(missing_tag.severity, Severity::Error);
+    assert_eq!(missing_tag.help, Some("rename `tg` to `tag`".to_string()));
+}
+
+#[test]
+fn test_validate_semantics() {
+    let content = r#"
+machine_name = "test"
+
+[commands]
+  test = "pytest"
+
+[pre_commands]
+  tset = "poetry install"
+
+[[services]]
+  name = "redis"
+  depends_on = ["qdrnt"]
+  exposed_values = [{ name = "REDIS_PORT", value = "${port.redis}" }]
+  [services.source]
+    image = "redis"
+    tag   = "latest"
+
+[[services]]
+  name = "qdrant"
+  [services.source]
+    image = "qdrant/qdrant"
+    tag   = "latest"
+
+[[services]]
+  name = "qdrant"
+  [services.source]
+    image = "qdrant/qdrant"
+    tag   = "latest"
+"#;
+    let diagnostics = validation::validate(content);
+    let messages: Vec<&str> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+
+    assert!(messages.contains(&"service `redis` depends on unknown service `qdrnt`"));
+    assert!(messages.contains(&"service `qdrant` is defined more than once"));
+    assert!(messages
+        .contains(&"pre command `tset` has no matching entry in [commands] and will never run"));
+    let unallocated_port = diagnostics.iter().find(|diagnostic| {
+        diagnostic.message
+            == "port `redis` is never allocated, ports are only allocated when a service declares them or a service command uses them"
+    });
+    assert_eq!(
+        unallocated_port.map(|diagnostic| &diagnostic.severity),
+        Some(&Severity::Warning)
+    );
+}
+
+#[test]
+fn test_init_generates_valid_config() {
+    let dir = std::env::temp_dir().join(format!("wm_init_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("poetry.lock"), "").unwrap();
+    std::fs::write(dir.join("package.json"), r#"{"scripts": {"dev": "vite"}}"#).unwrap();
+
+    let presets: Vec<String> = init::PRESETS.iter().map(|p| p.to_string()).collect();
+    let project = init::detect(&dir, &presets);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(project.requirements, vec!["pipx", "poetry", "docker"]);
+    assert_eq!(project.services.len(), init::PRESETS.len());
+    assert!(project
+        .commands
+        .contains(&("dev".to_string(), "npm run dev".to_string())));
+
+    let content = init::render(&project);
+    let diagnostics = validation::validate(&content);
+    assert_eq!(diagnostics, vec![]);
+}
+
+#[test]
+fn test_validate_circular_dependencies() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "a"
+  depends_on = ["b"]
+  [services.source]
+    image = "a"
+    tag   = "1"
+
+[[services]]
+  name = "b"
+  depends_on = ["a"]
+  [services.source]
+    image = "b"
+    tag   = "1"
+"#;
+
+    let diagnostics = validation::validate(content);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(diagnostics[0].message, "circular dependency: a -> b -> a");
+}
+
+#[test]
+fn test_wait_for_healthy() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+    tag   = "7"
+
+[[services]]
+  name = "db"
+  wait_for_healthy = true
+  health_timeout = 10
+  [services.source]
+    image = "postgres"
+    tag   = "16"
+    start_command = "docker run -d --name db -p ${port.db}:5432 postgres:16"
+    health_check_command = "pg_isready -h localhost -p ${port.db}"
+"#;
+
+    assert_eq!(validation::validate(content), vec![]);
+
+    let config: Config = toml::from_str(content).unwrap();
+    assert!(!config.services[0].wait_for_healthy);
+    assert_eq!(config.services[0].health_timeout, 60);
+    assert!(config.services[1].wait_for_healthy);
+    assert_eq!(config.services[1].health_timeout, 10);
+}
+
+#[test]
+fn test_ready_probes() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "redis"
+  ready = { tcp = "${port.redis}" }
+  [services.source]
+    image = "redis"
+    tag   = "7"
+
+[[services]]
+  name = "meili"
+  ready = { http = "http://localhost:${port.meili}/health", status = 200, retries = 5 }
+  [services.source]
+    image = "getmeili/meilisearch"
+    tag   = "latest"
+
+[[services]]
+  name = "broken"
+  ready = { tcp = "6379", exec = "true", status = 200 }
+  [services.source]
+    image = "redis"
+    tag   = "7"
+"#;
+
+    let diagnostics = validation::validate(content);
+    assert_eq!(diagnostics.len(), 2);
+    assert_eq!(diagnostics[0].severity, Severity::Error);
+    assert_eq!(
+        diagnostics[0].message,
+        "the ready probe of service `broken` needs exactly one of `tcp`, `http` or `exec`"
+    );
+    assert_eq!(diagnostics[1].severity, Severity::Warning);
+
+    let config: Config = toml::from_str(content).unwrap();
+    let ready = config.services[1].ready.as_ref().unwrap();
+    assert_eq!(ready.status, Some(200));
+    assert_eq!(ready.retries, 5);
+    assert_eq!(ready.interval, 1);
+    assert_eq!(ready.timeout, 2);
+}
+
+#[test]
+fn test_port_settings() {
+    let content = r#"
+machine_name = "test"
+port_range = { start = 50000, end = 40000 }
+excluded_ports = [5432]
+
+[ports]
+  redis = 6379
+  cache = 6379
+  db    = 5432
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+    tag   = "7"
+    start_command = "docker run -d -p ${port.redis}:6379 redis:7"
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                Severity::Error,
+                "port range starts at 50000 but ends at 40000".to_string()
+            ),
+            (
+                Severity::Warning,
+                "pinned port `cache` is not used by any service".to_string()
+            ),
+            (
+                Severity::Warning,
+                "pinned port `db` is not used by any service".to_string()
+            ),
+            (
+                Severity::Error,
+                "pinned port `db` uses excluded port 5432".to_string()
+            ),
+            (
+                Severity::Error,
+                "ports `cache` and `redis` are both pinned to 6379".to_string()
+            ),
+        ]
+    );
+
+    let config: Config = toml::from_str("machine_name = \"test\"").unwrap();
+    assert_eq!(config.port_range.start, 49000);
+    assert_eq!(config.port_range.end, 65535);
+}
+
+#[test]
+fn test_service_ports() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "qdrant"
+  ports = [{ name = "http", container = 6333 }, { name = "grpc", container = 6334 }]
+  [services.source]
+    image = "qdrant/qdrant"
+    tag   = "latest"
+    start_command = "docker run -d -p ${service.ports.http}:6333 -p ${service.ports.grcp}:6334 qdrant/qdrant"
+  [[services.exposed_values]]
+    name  = "QDRANT_URL"
+    value = "http://localhost:${service.ports.http}"
+
+[[services]]
+  name = "api"
+  ports = [{ name = "http" }, { name = "http" }]
+  [services.source]
+    install_command       = "true"
+    install_check_command = "true"
+    health_check_command  = "curl localhost:${service.ports.http}"
+    start_command         = "QDRANT=localhost:${port.qdrant.http} serve --port ${service.ports.http}"
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                Severity::Error,
+                "port `http` is declared more than once in service `api`".to_string()
+            ),
+            (
+                Severity::Error,
+                "service `qdrant` does not declare a port named `grcp`".to_string()
+            ),
+        ]
+    );
+
+    let config: Config = toml::from_str(content).unwrap();
+    assert_eq!(config.services[0].ports[1].name, "grpc");
+    assert_eq!(config.services[0].ports[1].container, Some(6334));
+    assert_eq!(config.services[1].ports[0].container, None);
+}
+
+#[test]
+fn test_structured_container_fields() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image         = "redis"
+    tag           = "7"
+    start_command = "docker run -d redis:7"
+    env           = { A = "b" }
+    healthcheck   = { command = "redis-cli ping", interval = "5s", timeot = "1s" }
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].1.contains("`timeot`"));
+    assert_eq!(
+        messages[1],
+        (
+            Severity::Warning,
+            "service `redis` has a start_command, so its `env`, `healthcheck` are ignored"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_build_source() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "api"
+  [services.source]
+    build = { dockerfil = "Dockerfile" }
+
+[[services]]
+  name = "app"
+  [services.source]
+    install_command       = "true"
+    install_check_comand  = "true"
+    start_command         = "serve"
+    health_check_command  = "true"
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert!(messages.contains(&(
+        Severity::Warning,
+        "unknown key `dockerfil` in service `api` build".to_string()
+    )));
+    assert!(messages.contains(&(
+        Severity::Error,
+        "service `api` build is missing `context`".to_string()
+    )));
+    // A misspelled app source isn't mistaken for a container source
+    assert!(messages.contains(&(
+        Severity::Error,
+        "service `app` source is missing `install_check_command`".to_string()
+    )));
+
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "worker"
+  [services.source]
+    build = { context = "./worker", dockerfile = "Dockerfile.dev", args = { PYTHON = "3.12" }, target = "dev" }
+
+[[services]]
+  name = "api"
+  [services.source]
+    image = "api"
+    build = { context = "./api" }
+
+[[services]]
+  name = "cache"
+  [services.source]
+    tag = "7"
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            (
+                Severity::Warning,
+                "service `api` is built from a Dockerfile, so its `image` is ignored".to_string()
+            ),
+            (
+                Severity::Error,
+                "service `cache` source needs an `image` or a `build`".to_string()
+            ),
+        ]
+    );
+
+    let config: Config = toml::from_str(content).unwrap();
+    let source = match &config.services[0].source {
+        Source::Container(source) => source,
+        Source::App(_) => panic!("worker should be a container service"),
+    };
+    assert_eq!(source.tag, "");
+    assert_eq!(docker::image_tag(source), "latest");
+    let build = source.build.as_ref().unwrap();
+    assert_eq!(build.context, "./worker");
+    assert_eq!(build.dockerfile.as_deref(), Some("Dockerfile.dev"));
+    assert_eq!(build.args.get("PYTHON").map(String::as_str), Some("3.12"));
+    assert_eq!(build.target.as_deref(), Some("dev"));
+}
+
+#[test]
+fn test_pull_policy() {
+    let content = r#"
+machine_name = "test"
+pull_policy  = "never"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image       = "redis"
+    pull_policy = "alway"
+"#;
+
+    let diagnostics = validation::validate(content);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(
+        diagnostics[0].message,
+        "unknown pull_policy `alway` in service `redis` source, expected one of `always`, `missing`, `never`"
+    );
+    assert_eq!(
+        diagnostics[0].help,
+        Some("did you mean `always`?".to_string())
+    );
+
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name = "worker"
+  [services.source]
+    build       = { context = "./worker" }
+    pull_policy = "always"
+"#;
+
+    let messages: Vec<(Severity, String)> = validation::validate(content)
+        .into_iter()
+        .map(|diagnostic| (diagnostic.severity, diagnostic.message))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![(
+            Severity::Warning,
+            "service `worker` is built from a Dockerfile, so its `pull_policy` is ignored"
+                .to_string()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn test_lockfile() {
+    let dir = std::env::temp_dir().join(format!("wm_lock_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let config_path = dir.join("war_machine.toml");
+    std::fs::write(
+        &config_path,
+        "machine_name = \"test\"\n\n[[services]]\n  name = \"redis\"\n  [services.source]\n    image = \"redis\"\n    tag = \"latest\"\n",
+    )
+    .unwrap();
+
+    assert_eq!(
+        config::lock_path(&config_path),
+        dir.join("war_machine.lock")
+    );
+    let mut parsed_config = config::parse(config_path.clone()).await;
+    assert!(parsed_config.lock.images.is_empty());
+
+    parsed_config.lock.images.insert(
+        "redis".to_string(),
+        LockedImage {
+            image: "docker.io/redis:latest".to_string(),
+            digest: "sha256:aaa".to_string(),
+        },
+    );
+    config::write_lock(&config_path, &parsed_config.lock)
+        .await
+        .unwrap();
+    let content = std::fs::read_to_string(dir.join("war_machine.lock")).unwrap();
+    assert!(content.starts_with("# Generated by `wm lock`"));
+
+    let reparsed_config = config::parse(config_path).await;
+    assert_eq!(reparsed_config.lock, parsed_config.lock);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_placeholders_in_comments_are_ignored() {
+    let content = r#"
+machine_name = "test"
+
+[[services]]
+  name  = "web"
+  ports = [{ name = "http" }] # serves ${service.ports.https} later
+  # start_command = "serve --port ${port.old}"
+  [services.source]
+    image   = "nginx"
+    tag     = "1"
+    command = ["--port", "${service.ports.htp}"] # ${port.bad name}
+"#;
+
+    let diagnostics = validation::validate(content);
+    let messages: Vec<&str> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        vec!["service `web` does not declare a port named `htp`"]
+    );
+
+    let span = diagnostics[0].span.clone().unwrap();
+    assert_eq!(&content[span], "${service.ports.htp}");
+}
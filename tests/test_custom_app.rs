@@ -0,0 +1,10 @@
+use wm::library::system::custom_app;
+
+#[test]
+fn test_format_uptime() {
+    assert_eq!(custom_app::format_uptime(1), "1 second");
+    assert_eq!(custom_app::format_uptime(42), "42 seconds");
+    assert_eq!(custom_app::format_uptime(60), "1 minute");
+    assert_eq!(custom_app::format_uptime(3 * 3600 + 20), "3 hours");
+    assert_eq!(custom_app::format_uptime(2 * 86400), "2 days");
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+
+use wm::library::machine::registry;
+use wm::library::system::docker;
+use wm::models::config::{Config, ContainerBuild, PullPolicy, Source};
+use wm::models::lockfile::LockedImage;
+use wm::models::machine_state::MachineState;
+use wm::models::pull_error::{PullError, PullErrorKind};
+
+#[tokio::test]
+async fn test_run_args() {
+    let config: Config = toml::from_str(
+        r#"
+machine_name = "shop"
+
+[[services]]
+  name = "search"
+  ports = [{ name = "http", container = 7700 }]
+  [services.source]
+    image       = "getmeili/meilisearch"
+    tag         = "v1.11"
+    env         = { MEILI_MASTER_KEY = "${env.WM_TEST_MEILISEARCH_KEY}", MEILI_HTTP_ADDR = "0.0.0.0:7700" }
+    volumes     = ["${machine_name}-search-data:/meili_data", "./dumps:/dumps"]
+    networks    = ["${machine_name}_default"]
+    user        = "1000:1000"
+    entrypoint  = "/bin/meilisearch"
+    healthcheck = { command = "curl -f localhost:7700/health", interval = "5s", retries = 3 }
+    extra_args  = ["--restart", "unless-stopped"]
+    command     = ["--env", "development"]
+"#,
+    )
+    .unwrap();
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::from([("search.http".to_string(), 49020)]),
+    };
+    let service = &config.services[0];
+    let source = match &service.source {
+        Source::Container(source) => source,
+        Source::App(_) => unreachable!(),
+    };
+
+    let args = docker::run_args(&machine_state, &config, "search", source, &service.ports).await;
+    let dumps = format!(
+        "{}/dumps:/dumps",
+        std::env::current_dir().unwrap().display()
+    );
+    let project_label = format!("wm.project={}", registry::project_path());
+    let hash_label = format!(
+        "wm.config-hash={}",
+        docker::service_config_hash(&config, "search")
+    );
+    assert_eq!(
+        args,
+        vec![
+            "-d",
+            "--name",
+            "shop-search",
+            "--label",
+            "wm.machine=shop",
+            "--label",
+            "wm.service=search",
+            "--label",
+            &project_label,
+            "--label",
+            &hash_label,
+            "--network",
+            "shop_default",
+            "-p",
+            "49020:7700",
+            "-e",
+            "MEILI_HTTP_ADDR=0.0.0.0:7700",
+            "-e",
+            "MEILI_MASTER_KEY=${env.WM_TEST_MEILISEARCH_KEY}",
+            "-v",
+            "shop-search-data:/meili_data",
+            "-v",
+            &dumps,
+            "--user",
+            "1000:1000",
+            "--entrypoint",
+            "/bin/meilisearch",
+            "--health-cmd",
+            "curl -f localhost:7700/health",
+            "--health-interval",
+            "5s",
+            "--health-retries",
+            "3",
+            "--restart",
+            "unless-stopped",
+            "docker.io/getmeili/meilisearch:v1.11",
+            "--env",
+            "development",
+        ]
+    );
+
+    // Structured fields don't go through a shell, the environment is read right before starting
+    std::env::set_var("WM_TEST_MEILISEARCH_KEY", "key");
+    assert_eq!(
+        docker::replace_env_placeholders("${env.WM_TEST_MEILISEARCH_KEY} ${env.WM_TEST_UNSET}"),
+        "key ${env.WM_TEST_UNSET}"
+    );
+}
+
+#[tokio::test]
+async fn test_built_image() {
+    let config: Config = toml::from_str(
+        r#"
+machine_name = "Shop"
+
+[[services]]
+  name = "worker"
+  [services.source]
+    build   = { context = "./worker", dockerfile = "Dockerfile.dev", args = { B = "2", A = "1" }, target = "dev" }
+    command = ["${service.source.image}"]
+"#,
+    )
+    .unwrap();
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::new(),
+    };
+    let source = match &config.services[0].source {
+        Source::Container(source) => source,
+        Source::App(_) => unreachable!(),
+    };
+
+    assert_eq!(
+        docker::image_ref(&config, "worker", source),
+        "shop/worker:latest"
+    );
+    let args = docker::run_args(&machine_state, &config, "worker", source, &[]).await;
+    assert_eq!(
+        &args[args.len() - 2..],
+        ["shop/worker:latest", "shop/worker"]
+    );
+
+    let build = source.build.as_ref().unwrap();
+    assert_eq!(
+        docker::build_args("shop/worker:latest", build, "abc"),
+        vec![
+            "-t",
+            "shop/worker:latest",
+            "-f",
+            "./worker/Dockerfile.dev",
+            "--target",
+            "dev",
+            "--build-arg",
+            "A=1",
+            "--build-arg",
+            "B=2",
+            "--label",
+            "wm.context-hash=abc",
+            "./worker",
+        ]
+    );
+}
+
+#[test]
+fn test_context_hash() {
+    let context = std::env::temp_dir().join(format!("wm-test-build-{}", std::process::id()));
+    std::fs::create_dir_all(context.join("src")).unwrap();
+    std::fs::create_dir_all(context.join("node_modules")).unwrap();
+    std::fs::write(context.join("Dockerfile"), "FROM alpine").unwrap();
+    std::fs::write(context.join("src/main.py"), "print(1)").unwrap();
+    std::fs::write(context.join(".dockerignore"), "node_modules\n*.log\n").unwrap();
+
+    let mut build: ContainerBuild =
+        toml::from_str(&format!("context = \"{}\"", context.display())).unwrap();
+    let hash = docker::context_hash(&build).unwrap();
+
+    // Ignored files don't matter
+    std::fs::write(context.join("node_modules/dep.js"), "1").unwrap();
+    assert_eq!(docker::context_hash(&build).unwrap(), hash);
+
+    std::fs::write(context.join("src/main.py"), "print(2)").unwrap();
+    let changed_hash = docker::context_hash(&build).unwrap();
+    assert_ne!(changed_hash, hash);
+
+    build.args.insert("DEBUG".to_string(), "1".to_string());
+    assert_ne!(docker::context_hash(&build).unwrap(), changed_hash);
+
+    std::fs::remove_dir_all(&context).unwrap();
+    assert!(docker::context_hash(&build).is_err());
+}
+
+#[test]
+fn test_pull_policy() {
+    let config: Config = toml::from_str(
+        r#"
+machine_name = "shop"
+pull_policy  = "always"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+
+[[services]]
+  name = "search"
+  [services.source]
+    image       = "getmeili/meilisearch"
+    pull_policy = "never"
+"#,
+    )
+    .unwrap();
+    let policies: Vec<PullPolicy> = config
+        .services
+        .iter()
+        .map(|service| match &service.source {
+            Source::Container(source) => docker::pull_policy(&config, source),
+            Source::App(_) => unreachable!(),
+        })
+        .collect();
+    assert_eq!(policies, vec![PullPolicy::Always, PullPolicy::Never]);
+
+    let config: Config = toml::from_str("machine_name = \"shop\"").unwrap();
+    assert_eq!(config.pull_policy, PullPolicy::Missing);
+
+    let error = PullError {
+        service: "search".to_string(),
+        image: "docker.io/getmeili/meilisearch:latest".to_string(),
+        kind: PullErrorKind::NotAvailable,
+    };
+    assert_eq!(
+        error.to_string(),
+        "docker.io/getmeili/meilisearch:latest for search isn't available locally and its pull policy is `never`"
+    );
+}
+
+#[tokio::test]
+async fn test_locked_images() {
+    let mut config: Config = toml::from_str(
+        r#"
+machine_name = "shop"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+    tag   = "7"
+
+[[services]]
+  name = "search"
+  [services.source]
+    image = "getmeili/meilisearch"
+    tag   = "v1.12"
+"#,
+    )
+    .unwrap();
+    config.lock = toml::from_str(
+        r#"
+[images.redis]
+image  = "docker.io/redis:7"
+digest = "sha256:aaa"
+
+[images.search]
+image  = "docker.io/getmeili/meilisearch:v1.11"
+digest = "sha256:bbb"
+"#,
+    )
+    .unwrap();
+    let sources: Vec<_> = config
+        .services
+        .iter()
+        .map(|service| match &service.source {
+            Source::Container(source) => source,
+            Source::App(_) => unreachable!(),
+        })
+        .collect();
+
+    assert_eq!(
+        docker::image_ref(&config, "redis", sources[0]),
+        "docker.io/redis@sha256:aaa"
+    );
+    // The lock is for another tag, so it's not used
+    assert_eq!(docker::locked_digest(&config, "search", sources[1]), None);
+    assert_eq!(
+        docker::image_ref(&config, "search", sources[1]),
+        "docker.io/getmeili/meilisearch:v1.12"
+    );
+
+    // Start commands run the locked digest through `${service.source.ref}`
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::new(),
+    };
+    let command = "docker run -d ${service.source.ref}".to_string();
+    assert_eq!(
+        docker::replace_placeholders(&machine_state, &config, &command, "redis", sources[0]).await,
+        "docker run -d docker.io/redis@sha256:aaa"
+    );
+    assert_eq!(
+        docker::replace_placeholders(&machine_state, &config, &command, "search", sources[1]).await,
+        "docker run -d docker.io/getmeili/meilisearch:v1.12"
+    );
+
+    assert_eq!(
+        config.lock.images.get("redis"),
+        Some(&LockedImage {
+            image: "docker.io/redis:7".to_string(),
+            digest: "sha256:aaa".to_string(),
+        })
+    );
+}
+
+#[test]
+fn test_parse_repo_digest() {
+    assert_eq!(
+        docker::parse_repo_digest("[\"redis@sha256:aaa\"]\n", "docker.io", "redis"),
+        Some("sha256:aaa".to_string())
+    );
+    assert_eq!(
+        docker::parse_repo_digest(
+            "[\"localhost:5000/meilisearch@sha256:aaa\",\"getmeili/meilisearch@sha256:bbb\"]",
+            "docker.io",
+            "getmeili/meilisearch"
+        ),
+        Some("sha256:bbb".to_string())
+    );
+    assert_eq!(
+        docker::parse_repo_digest("[\"ghcr.io/acme/api@sha256:aaa\"]", "ghcr.io", "acme/api"),
+        Some("sha256:aaa".to_string())
+    );
+    // Digests of other repos with the same image are not the locked image
+    assert_eq!(
+        docker::parse_repo_digest(
+            "[\"localhost:5000/redis@sha256:aaa\",\"acme/redis@sha256:bbb\"]",
+            "docker.io",
+            "redis"
+        ),
+        None
+    );
+    assert_eq!(docker::parse_repo_digest("[]", "docker.io", "redis"), None);
+    assert_eq!(docker::parse_repo_digest("", "docker.io", "redis"), None);
+}
+
+#[test]
+fn test_labels() {
+    let config: Config = toml::from_str(
+        r#"
+machine_name = "shop"
+
+[[services]]
+  name = "redis"
+  [services.source]
+    image = "redis"
+    env   = { A = "1", B = "2", C = "3" }
+"#,
+    )
+    .unwrap();
+
+    // The hash doesn't depend on the order maps are iterated in
+    let hash = docker::service_config_hash(&config, "redis");
+    assert_eq!(hash.len(), 12);
+    let mut reordered = toml::from_str::<Config>(&toml::to_string(&config).unwrap()).unwrap();
+    assert_eq!(docker::service_config_hash(&reordered, "redis"), hash);
+    if let Source::Container(source) = &mut reordered.services[0].source {
+        source.tag = "7".to_string();
+    }
+    assert_ne!(docker::service_config_hash(&reordered, "redis"), hash);
+
+    let labels = vec![
+        ("wm.service".to_string(), "redis".to_string()),
+        ("wm.project".to_string(), "/home/me/it's here".to_string()),
+    ];
+    assert_eq!(
+        docker::add_labels("docker pull redis && docker run -d redis:7", &labels),
+        "docker pull redis && docker run --label 'wm.service=redis' --label 'wm.project=/home/me/it'\\''s here' -d redis:7"
+    );
+    assert_eq!(
+        docker::add_labels("docker  container run redis", &labels[..1]),
+        "docker  container run --label 'wm.service=redis' redis"
+    );
+    assert!(!docker::runs_container("docker compose up -d redis"));
+    assert_eq!(
+        docker::add_labels("docker compose up -d redis", &labels),
+        "docker compose up -d redis"
+    );
+}
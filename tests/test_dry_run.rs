@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use wm::library::commands::dry_run::{self, PlanOptions};
+use wm::library::config::services;
+use wm::models::config::Config;
+use wm::models::machine_state::MachineState;
+
+fn config() -> Config {
+    toml::from_str(
+        r#"
+machine_name = "shop"
+requirements = ["docker"]
+networks = ["shop"]
+registry_credentials = [{ registry = "ghcr.io", username = "GH_USER", password = "GH_TOKEN" }]
+
+[[services]]
+  name  = "redis"
+  ports = [{ name = "redis", container = 6379 }]
+  exposed_values = [{ name = "redis_url", value = "redis://localhost:${service.ports.redis}" }]
+  [services.source]
+    image    = "redis"
+    tag      = "7.2"
+    env      = { GREETING = "hello world" }
+    networks = ["${machine_name}"]
+
+[[services]]
+  name       = "worker"
+  depends_on = ["redis"]
+  exposed_values = [{ command = "cat .env.worker" }]
+  [services.source]
+    install_command       = "pip install worker"
+    install_check_command = "which worker"
+    start_command         = "worker --redis ${port.redis.redis}"
+    health_check_command  = "worker ping"
+    clean_command         = "rm -rf .worker"
+"#,
+    )
+    .unwrap()
+}
+
+fn options(clean_mode: bool) -> PlanOptions {
+    PlanOptions {
+        no_services: false,
+        no_features: false,
+        no_requirements: false,
+        clean_mode,
+    }
+}
+
+#[test]
+fn test_plan_steps() {
+    let config = config();
+
+    assert_eq!(
+        dry_run::requirement_steps(&config),
+        vec!["# check that docker is installed, install it when it isn't"]
+    );
+    assert_eq!(
+        dry_run::network_steps(&config),
+        vec!["docker network create shop  # unless it exists"]
+    );
+    // Only the names of the credentials are shown
+    assert_eq!(
+        dry_run::registry_steps(&config),
+        vec![
+            "docker login ghcr.io --username $GH_USER --password-stdin  # password from $GH_TOKEN",
+            "docker logout ghcr.io  # once the services started",
+        ]
+    );
+    assert_eq!(dry_run::wave_steps(&config), vec!["1. redis", "2. worker"]);
+
+    // The keyring isn't read, the secrets are one entry
+    assert_eq!(
+        dry_run::environment_keys(&config, &options(false)),
+        vec![
+            ("$(cat .env.worker)".to_string(), "worker".to_string()),
+            ("$(war machine secrets)".to_string(), "keyring".to_string()),
+            ("REDIS_URL".to_string(), "redis".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_start_commands() {
+    let config = config();
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::from([("redis.redis".to_string(), 49000)]),
+    };
+
+    let redis =
+        services::start_commands(&machine_state, &config, &config.services[0], false, false).await;
+    assert_eq!(redis.len(), 4);
+    assert_eq!(
+        redis[1],
+        "docker pull docker.io/redis:7.2  # only when it isn't available locally"
+    );
+    // Stopped containers are only resumed once the image was checked
+    assert_eq!(
+        redis[2],
+        "# a stopped redis container is resumed with `docker start` when it runs docker.io/redis:7.2, otherwise it is removed"
+    );
+    assert!(redis[3].starts_with("docker run -d --name shop-redis --label wm.machine=shop"));
+    assert!(redis[3]
+        .ends_with("--network shop -p 49000:6379 -e 'GREETING=hello world' docker.io/redis:7.2"));
+
+    let worker =
+        services::start_commands(&machine_state, &config, &config.services[1], false, false).await;
+    assert_eq!(
+        worker,
+        vec![
+            "pip install worker  # only when `which worker` fails",
+            "worker --redis 49000  # only when `worker ping` fails",
+        ]
+    );
+
+    // Cleaning runs the clean command before starting again
+    let worker =
+        services::start_commands(&machine_state, &config, &config.services[1], true, false).await;
+    assert_eq!(
+        worker,
+        vec![
+            "pip install worker  # only when `which worker` fails",
+            "rm -rf .worker",
+            "worker --redis 49000",
+        ]
+    );
+}
@@ -25,3 +25,44 @@ async fn test_replace_env_vars_brackets() {
 
     assert_eq!(test_string_with_values, "Hi there beautiful world");
 }
+
+#[test]
+fn test_format_variables() {
+    let variables = vec![
+        ("REDIS_PORT".to_string(), "49000".to_string()),
+        ("GREETING".to_string(), "it's \"here\"".to_string()),
+    ];
+
+    assert_eq!(
+        env_vars::format_variables(&variables, "sh"),
+        "export REDIS_PORT='49000'\nexport GREETING='it'\\''s \"here\"'"
+    );
+    assert_eq!(
+        env_vars::format_variables(&variables, "fish"),
+        "set -gx REDIS_PORT '49000'\nset -gx GREETING 'it\\'s \"here\"'"
+    );
+    assert_eq!(
+        env_vars::format_variables(&variables, "dotenv"),
+        "REDIS_PORT=\"49000\"\nGREETING=\"it's \\\"here\\\"\""
+    );
+}
+
+#[test]
+fn test_join_sh() {
+    let args: Vec<String> = [
+        "run",
+        "-e",
+        "GREETING=hello world",
+        "--label",
+        "wm.service=api",
+        "",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+
+    assert_eq!(
+        env_vars::join_sh(&args),
+        "run -e 'GREETING=hello world' --label wm.service=api ''"
+    );
+}
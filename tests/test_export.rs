@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+
+use wm::library::commands::export;
+use wm::models::{config::Config, machine_state::MachineState};
+
+const CONFIG: &str = r#"
+machine_name = "shop"
+
+[[services]]
+  name = "db"
+  exposed_values = [
+    { name = "DATABASE_URL", value = "postgresql://localhost:${port.db}/shop" },
+  ]
+
+  [services.source]
+    image = "postgres"
+    tag   = "16"
+
+    start_command = """
+    docker run -d \
+    --name ${machine_name}-${service.name} \
+    -p ${port.db}:5432 \
+    -e POSTGRES_PASSWORD='pa ss' \
+    -v $(pwd)/init.sql:/docker-entrypoint-initdb.d/init.sql \
+    --health-cmd "pg_isready -U postgres" \
+    ${service.source.image}:${service.source.tag} \
+    postgres -c log_statement=all
+    """
+"#;
+
+#[tokio::test]
+async fn test_export() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::from([("db".to_string(), 49010)]),
+    };
+
+    let (compose, notes) = export::render(&machine_state, &config, "compose").await;
+    assert_eq!(notes, Vec::<String>::new());
+    let compose: serde_yaml_ng::Value = serde_yaml_ng::from_str(&compose).unwrap();
+    let db = &compose["services"]["db"];
+    assert_eq!(db["image"], "docker.io/postgres:16");
+    assert_eq!(db["ports"][0], "49010:5432");
+    assert_eq!(db["environment"]["POSTGRES_PASSWORD"], "pa ss");
+    assert_eq!(
+        db["volumes"][0],
+        "./init.sql:/docker-entrypoint-initdb.d/init.sql"
+    );
+    assert_eq!(db["healthcheck"]["test"][1], "pg_isready -U postgres");
+    assert_eq!(db["command"][2], "log_statement=all");
+    assert_eq!(
+        compose["x-environment"]["DATABASE_URL"],
+        "postgresql://localhost:49010/shop"
+    );
+
+    let (github_actions, notes) = export::render(&machine_state, &config, "github-actions").await;
+    assert_eq!(notes.len(), 1);
+    let github_actions: serde_yaml_ng::Value = serde_yaml_ng::from_str(&github_actions).unwrap();
+    assert_eq!(
+        github_actions["env"]["DATABASE_URL"],
+        "postgresql://localhost:49010/shop"
+    );
+
+    let (devcontainer, _) = export::render(&machine_state, &config, "devcontainer").await;
+    assert!(devcontainer.contains("\"forwardPorts\": [\n    49010\n  ]"));
+}
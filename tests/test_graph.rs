@@ -0,0 +1,119 @@
+use wm::library::commands::graph;
+use wm::library::config::dependencies;
+use wm::models::config::Config;
+
+const CONFIG: &str = r#"
+machine_name = "shop"
+
+[[services]]
+  name = "db"
+  exposed_values = [{ name = "db_port", value = "${port.db}" }]
+  [services.source]
+    image = "postgres"
+    tag   = "16"
+    start_command = "docker run -d --network ${machine_name}_backend postgres:16"
+
+[[services]]
+  name = "api"
+  depends_on = ["db"]
+  [services.source]
+    image = "acme/api"
+    tag   = "1"
+
+[[services]]
+  name = "a"
+  depends_on = ["b"]
+  [services.source]
+    image = "a"
+    tag   = "1"
+
+[[services]]
+  name = "b"
+  depends_on = ["a"]
+  [services.source]
+    image = "b"
+    tag   = "1"
+"#;
+
+#[test]
+fn test_startup_waves() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+    let (waves, unstartable_services) = dependencies::startup_waves(&config);
+
+    let names: Vec<Vec<&str>> = waves
+        .iter()
+        .map(|wave| wave.iter().map(|service| service.name.as_str()).collect())
+        .collect();
+    assert_eq!(names, vec![vec!["db"], vec!["api"]]);
+
+    let unstartable: Vec<&str> = unstartable_services
+        .iter()
+        .map(|service| service.name.as_str())
+        .collect();
+    assert_eq!(unstartable, vec!["a", "b"]);
+}
+
+#[test]
+fn test_graph_formats() {
+    let config: Config = toml::from_str(CONFIG).unwrap();
+
+    let text = graph::render(&config, "text");
+    assert!(text.contains("Wave 2\n  api (container acme/api:1)\n    depends on: db"));
+    assert!(text.contains("networks:   shop_backend"));
+    assert!(text.contains("exposes:    DB_PORT"));
+
+    let dot = graph::render(&config, "dot");
+    assert!(dot.contains("\"db\" -> \"api\";"));
+    assert!(dot.contains("subgraph cluster_unstartable"));
+
+    let mermaid = graph::render(&config, "mermaid");
+    assert!(mermaid.contains("service_db --> service_api"));
+    assert!(mermaid.contains("service_db -.- network_shop_backend"));
+}
+
+#[test]
+fn test_find_cycles() {
+    let config: Config = toml::from_str(
+        r#"
+machine_name = "cycles"
+
+[[services]]
+  name = "a"
+  depends_on = ["b"]
+  [services.source]
+    image = "a"
+    tag   = "1"
+
+[[services]]
+  name = "b"
+  depends_on = ["c"]
+  [services.source]
+    image = "b"
+    tag   = "1"
+
+[[services]]
+  name = "c"
+  depends_on = ["a"]
+  [services.source]
+    image = "c"
+    tag   = "1"
+
+[[services]]
+  name = "d"
+  depends_on = ["a"]
+  [services.source]
+    image = "d"
+    tag   = "1"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        dependencies::find_cycles(&config),
+        vec![vec!["a", "b", "c", "a"]]
+    );
+
+    let (waves, unstartable_services) = dependencies::startup_waves(&config);
+    assert!(waves.is_empty());
+    assert_eq!(unstartable_services.len(), 4);
+}
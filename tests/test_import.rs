@@ -0,0 +1,50 @@
+use wm::library::commands::import;
+use wm::library::config::validation;
+
+#[test]
+fn test_translate_compose() {
+    let compose = r#"
+services:
+  db:
+    image: postgres:16
+    environment:
+      POSTGRES_PASSWORD: postgres
+    ports:
+      - "5432:5432"
+    volumes:
+      - data:/var/lib/postgresql/data
+    healthcheck:
+      test: ["CMD-SHELL", "pg_isready"]
+  api:
+    image: ghcr.io/acme/api:1.2
+    depends_on: [db]
+    ports:
+      - "127.0.0.1:8080:80"
+    deploy:
+      replicas: 2
+volumes:
+  data:
+"#;
+
+    let import = import::translate_compose(compose, "shop").unwrap();
+    assert_eq!(import.service_names, vec!["db", "api"]);
+    assert_eq!(import.networks, vec!["shop_default"]);
+    assert_eq!(import.notes, vec!["api: `deploy` was not translated"]);
+
+    let db = &import.services[0];
+    assert!(db.contains("-p ${port.db}:5432"));
+    assert!(db.contains("-e POSTGRES_PASSWORD=postgres"));
+    assert!(db.contains("-v shop-data:/var/lib/postgresql/data"));
+    assert!(db.contains("--health-cmd pg_isready"));
+
+    let api = &import.services[1];
+    assert!(api.contains("registry = \"ghcr.io\""));
+    assert!(api.contains("depends_on = [\"db\"]"));
+    assert!(api.contains("-p 127.0.0.1:${port.api}:80"));
+
+    let content = format!(
+        "machine_name = \"shop\"\nnetworks = [\"shop_default\"]\n\n{}",
+        import.services.join("\n")
+    );
+    assert_eq!(validation::validate(&content), vec![]);
+}
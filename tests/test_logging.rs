@@ -0,0 +1,22 @@
+use wm::library::utils::logging;
+
+#[test]
+fn test_table() {
+    let header = vec!["Name".to_string(), "Port".to_string()];
+    let rows = vec![
+        vec!["redis".to_string(), "49000".to_string()],
+        vec!["db".to_string(), "5432".to_string()],
+    ];
+
+    assert_eq!(
+        logging::table(&header, &rows),
+        vec![
+            "┌───────┬───────┐",
+            "│ Name  │ Port  │",
+            "├───────┼───────┤",
+            "│ redis │ 49000 │",
+            "│ db    │ 5432  │",
+            "└───────┴───────┘",
+        ]
+    );
+}
@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::net::TcpListener;
+
+use wm::library::machine::ports;
+use wm::library::system::docker;
+use wm::models::config::{PortRange, Service};
+use wm::models::machine_state::MachineState;
+
+#[test]
+fn test_get_ports() {
+    let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+    let taken_port = listener.local_addr().unwrap().port();
+    assert!(!ports::is_port_free(taken_port));
+
+    // The taken port and the excluded one are skipped
+    let port_range = PortRange {
+        start: taken_port - 2,
+        end: taken_port + 2,
+    };
+    let excluded_ports = HashSet::from([taken_port - 1]);
+    let found_ports = ports::get_ports(2, &port_range, &excluded_ports).unwrap();
+    assert_eq!(found_ports.len(), 2);
+    assert!(!found_ports.contains(&taken_port));
+    assert!(!found_ports.contains(&(taken_port - 1)));
+
+    let port_range = PortRange {
+        start: taken_port,
+        end: taken_port,
+    };
+    assert_eq!(
+        ports::get_ports(1, &port_range, &HashSet::new())
+            .unwrap_err()
+            .to_string(),
+        format!(
+            "only 0 of the 1 ports needed are free between {} and {}",
+            taken_port, taken_port
+        )
+    );
+}
+
+#[test]
+fn test_parse_published_ports() {
+    let output = "redis\t0.0.0.0:49000->6379/tcp, :::49000->6379/tcp\n\
+                  db\t0.0.0.0:49001->5432/tcp, 0.0.0.0:49002->5433/tcp\n\
+                  removed\t0.0.0.0:49100->6379/tcp\n\
+                  worker\t\n";
+    let service_names =
+        HashSet::from(["redis".to_string(), "db".to_string(), "worker".to_string()]);
+
+    assert_eq!(
+        docker::parse_published_ports(output, &service_names),
+        HashSet::from([49000, 49001, 49002])
+    );
+}
+
+#[tokio::test]
+async fn test_service_ports() {
+    let machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::from([
+            ("qdrant.http".to_string(), 49000),
+            ("qdrant.grpc".to_string(), 49001),
+            ("api.http".to_string(), 49002),
+        ]),
+    };
+    let service: Service = toml::from_str(
+        r#"
+name = "qdrant"
+ports = [{ name = "http", container = 6333 }, { name = "grpc", container = 6334 }, { name = "metrics" }]
+[source]
+  image = "qdrant/qdrant"
+  tag   = "latest"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        ports::get_service_ports_needed(&service),
+        vec!["qdrant.http", "qdrant.grpc", "qdrant.metrics"]
+    );
+
+    let text = "${service.ports.http} ${port.qdrant.grpc} ${port.api.http}";
+    let text = ports::replace_service_ports_in_text(&machine_state, "qdrant", text).await;
+    assert_eq!(text, "49000 ${port.qdrant.grpc} ${port.api.http}");
+    assert_eq!(
+        ports::replace_ports_in_text(&machine_state, &text).await,
+        "49000 49001 49002"
+    );
+
+    // Ports without a container port are not published
+    assert_eq!(
+        docker::publish_args(&machine_state, "qdrant", &service.ports),
+        vec!["49000:6333", "49001:6334"]
+    );
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeSet;
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use wm::library::commands::dry_run::{self, PlanOptions};
+use wm::library::machine::state;
+use wm::models::config::Config;
+
+/// Every file and directory under `dir`
+fn files(dir: &Path) -> BTreeSet<PathBuf> {
+    let mut files = BTreeSet::new();
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            files.extend(self::files(&path));
+        }
+        files.insert(path);
+    }
+    files
+}
+
+/// Runs in its own test binary, it changes the working directory and PATH of the process
+#[tokio::test]
+async fn test_preview_is_side_effect_free() {
+    let root = std::env::temp_dir().join(format!("wm-test-preview-{}", std::process::id()));
+    let project = root.join("shop");
+    let other_project = root.join("blog");
+    let bin = root.join("bin");
+    let state_home = root.join("state");
+    let executed = root.join("executed");
+    std::fs::create_dir_all(project.join(".war_machine")).unwrap();
+    std::fs::create_dir_all(&other_project).unwrap();
+    std::fs::create_dir_all(&bin).unwrap();
+    std::fs::create_dir_all(state_home.join("wm")).unwrap();
+
+    // Anything that runs a command goes through these and leaves a trace
+    for command in ["sh", "bash", "docker", "touch", "kill"] {
+        let path = bin.join(command);
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho \"{} $*\" >> {}\n",
+                command,
+                executed.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    // Another project registered the port redis had, and something holds the pinned port
+    std::fs::write(
+        state_home.join("wm/ports.json"),
+        format!(
+            r#"{{"ports": [{{"port": 49000, "name": "redis.redis", "machine_name": "blog", "project_path": "{}"}}]}}"#,
+            other_project.canonicalize().unwrap().display()
+        ),
+    )
+    .unwrap();
+    std::fs::write(
+        project.join(".war_machine/state.json"),
+        r#"{"containers": {}, "ports": {"redis.redis": 49000}}"#,
+    )
+    .unwrap();
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let pinned_port = listener.local_addr().unwrap().port();
+
+    let config: Config = toml::from_str(&format!(
+        r#"
+machine_name = "shop"
+requirements = ["docker"]
+
+[ports]
+"web.http" = {}
+
+[[services]]
+  name  = "redis"
+  ports = [{{ name = "redis", container = 6379 }}]
+  [services.source]
+    image = "redis"
+    tag   = "7"
+
+[[services]]
+  name  = "web"
+  ports = [{{ name = "http", container = 80 }}]
+  [services.source]
+    image = "nginx"
+    tag   = "1"
+
+[[services]]
+  name = "worker"
+  [services.source]
+    install_command       = "touch installed"
+    install_check_command = "touch checked"
+    start_command         = "touch started ${{port.worker}}"
+    health_check_command  = "touch healthy"
+    stop_command          = "touch stopped"
+"#,
+        pinned_port
+    ))
+    .unwrap();
+
+    std::env::set_current_dir(&project).unwrap();
+    std::env::set_var("XDG_STATE_HOME", &state_home);
+    std::env::set_var("PATH", &bin);
+    let before = files(&root);
+
+    let (machine_state, port_conflicts) = state::preview(&config, false).await;
+    let options = PlanOptions {
+        no_services: false,
+        no_features: false,
+        no_requirements: false,
+        clean_mode: false,
+    };
+    dry_run::run_plan(&machine_state, &config, &options, None, "").await;
+    let services: Vec<_> = config.services.iter().collect();
+    dry_run::stop_plan(&machine_state, &config, &services).await;
+    dry_run::clean_plan(&machine_state, &config, &services).await;
+
+    // The conflicts `wm run` would stop on or resolve are reported instead
+    assert!(port_conflicts.contains(&format!(
+        "# port {} pinned for `web.http` is in use, wm stops unless a service of this project holds it",
+        pinned_port
+    )));
+    assert!(port_conflicts.contains(
+        &"# port 49000 of `redis.redis` is registered by another project, it moves".to_string()
+    ));
+    assert_ne!(machine_state.ports["redis.redis"], 49000);
+    assert!(machine_state.ports.contains_key("worker"));
+
+    // Nothing ran, and no file was created or changed, the registry lock included
+    assert!(!executed.exists());
+    assert_eq!(files(&root), before);
+    assert_eq!(
+        std::fs::read_to_string(project.join(".war_machine/state.json")).unwrap(),
+        r#"{"containers": {}, "ports": {"redis.redis": 49000}}"#
+    );
+
+    drop(listener);
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,89 @@
+use tokio::net::TcpListener;
+use wm::library::system::probe;
+use wm::models::config::ReadyProbe;
+
+fn ready_probe() -> ReadyProbe {
+    ReadyProbe {
+        tcp: None,
+        http: None,
+        status: None,
+        exec: None,
+        interval: 0,
+        timeout: 1,
+        retries: 2,
+    }
+}
+
+#[tokio::test]
+async fn test_tcp_probe() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    let probe = ReadyProbe {
+        tcp: Some(port.to_string()),
+        ..ready_probe()
+    };
+    assert!(probe::wait_until_ready(&probe).await.is_ok());
+
+    drop(listener);
+    let error = probe::wait_until_ready(&probe).await.unwrap_err();
+    let error = error.to_string();
+    assert!(error.starts_with("not ready after 2 attempts: connecting to 127.0.0.1:"));
+}
+
+#[tokio::test]
+async fn test_exec_probe() {
+    let probe = ReadyProbe {
+        exec: Some("true".to_string()),
+        ..ready_probe()
+    };
+    assert!(probe::wait_until_ready(&probe).await.is_ok());
+
+    let probe = ReadyProbe {
+        exec: Some("echo 'still starting' >&2; false".to_string()),
+        ..ready_probe()
+    };
+    assert_eq!(
+        probe::wait_until_ready(&probe)
+            .await
+            .unwrap_err()
+            .to_string(),
+        "not ready after 2 attempts: still starting"
+    );
+}
+
+#[tokio::test]
+async fn test_http_probe() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).await;
+            let _ = stream
+                .write_all(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n")
+                .await;
+        }
+    });
+
+    let probe = ReadyProbe {
+        http: Some(format!("http://127.0.0.1:{}/health", port)),
+        status: Some(503),
+        ..ready_probe()
+    };
+    assert!(probe::wait_until_ready(&probe).await.is_ok());
+
+    let probe = ReadyProbe {
+        status: None,
+        ..probe
+    };
+    assert_eq!(
+        probe::wait_until_ready(&probe).await.unwrap_err().to_string(),
+        format!(
+            "not ready after 2 attempts: http://127.0.0.1:{}/health returned 503 Service Unavailable",
+            port
+        )
+    );
+}
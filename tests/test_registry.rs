@@ -0,0 +1,63 @@
+use std::collections::{HashMap, HashSet};
+
+use wm::library::machine::registry::{self, LockedRegistry};
+use wm::models::machine_state::MachineState;
+
+#[tokio::test]
+async fn test_port_registry() {
+    let root = std::env::temp_dir().join(format!("wm-test-registry-{}", std::process::id()));
+    let shop = root.join("shop");
+    let blog = root.join("blog");
+    std::fs::create_dir_all(&shop).unwrap();
+    std::fs::create_dir_all(&blog).unwrap();
+    std::env::set_var("XDG_STATE_HOME", root.join("state"));
+
+    let machine_state = |ports: &[(&str, i32)]| MachineState {
+        containers: HashMap::new(),
+        ports: ports
+            .iter()
+            .map(|(name, port)| (name.to_string(), *port))
+            .collect(),
+    };
+    let shop_path = shop.display().to_string();
+    let blog_path = blog.display().to_string();
+
+    let mut locked_registry = LockedRegistry::open().await.unwrap();
+    locked_registry.register(
+        &shop_path,
+        "shop",
+        &machine_state(&[("redis", 49000), ("db", 49001)]),
+    );
+    locked_registry.register(&blog_path, "blog", &machine_state(&[("redis", 49002)]));
+    locked_registry.save().unwrap();
+    drop(locked_registry);
+
+    // Registering again replaces the ports of the project
+    let mut locked_registry = LockedRegistry::open().await.unwrap();
+    assert_eq!(
+        locked_registry.foreign_ports(&shop_path),
+        HashSet::from([49002])
+    );
+    locked_registry.register(&shop_path, "shop", &machine_state(&[("redis", 49003)]));
+    locked_registry.save().unwrap();
+    drop(locked_registry);
+
+    let ports: Vec<(u16, String)> = registry::load()
+        .unwrap()
+        .ports
+        .into_iter()
+        .map(|port| (port.port, port.machine_name))
+        .collect();
+    assert_eq!(
+        ports,
+        vec![(49002, "blog".to_string()), (49003, "shop".to_string())]
+    );
+
+    // Deleted projects are forgotten
+    std::fs::remove_dir_all(&blog).unwrap();
+    let locked_registry = LockedRegistry::open().await.unwrap();
+    assert_eq!(locked_registry.foreign_ports(&shop_path), HashSet::new());
+    drop(locked_registry);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use wm::library::config::services;
+use wm::library::system::docker;
+use wm::models::config::Config;
+use wm::models::machine_state::MachineState;
+
+fn config(tag: &str, env: &str) -> Config {
+    toml::from_str(&format!(
+        r#"
+machine_name = "shop"
+
+[[services]]
+  name  = "redis"
+  ports = [{{ name = "redis", container = 6379 }}]
+  [services.source]
+    image = "redis"
+    tag   = "{}"
+    env   = {{ {} }}
+"#,
+        tag, env
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_definition_changes() {
+    let mut machine_state = MachineState {
+        containers: HashMap::new(),
+        ports: HashMap::from([("redis.redis".to_string(), 49000)]),
+    };
+
+    let old_config = config("latest", "A = \"1\", B = \"2\"");
+    let old = services::definition(&machine_state, &old_config, &old_config.services[0]).await;
+    assert_eq!(old.fields["image"], "docker.io/redis");
+    assert_eq!(old.fields["ports"], "49000:6379");
+    assert_eq!(old.fields["env"], "A=1, B=2");
+
+    // The order of the env values doesn't matter
+    let same_config = config("latest", "B = \"2\", A = \"1\"");
+    let same = services::definition(&machine_state, &same_config, &same_config.services[0]).await;
+    assert_eq!(same.hash, old.hash);
+
+    let new_config = config("7.2", "A = \"1\"");
+    let new = services::definition(&machine_state, &new_config, &new_config.services[0]).await;
+    assert_ne!(new.hash, old.hash);
+    assert_eq!(
+        services::describe_changes(&old, &new),
+        vec!["env A=1, B=2 → A=1", "tag latest → 7.2"]
+    );
+
+    let definitions = HashMap::from([("redis".to_string(), new.clone())]);
+    let plan = |machine_state: &MachineState, started: &HashMap<String, Option<String>>| {
+        services::plan(machine_state, &new_config, &definitions, started)
+    };
+    // Services that were never started have nothing to compare with
+    assert_eq!(plan(&machine_state, &HashMap::new()), vec![]);
+
+    // Without a saved definition, the container label is compared with the configuration
+    let config_hash = docker::service_config_hash(&new_config, "redis");
+    let started = HashMap::from([("redis".to_string(), Some(config_hash.clone()))]);
+    assert_eq!(plan(&machine_state, &started), vec![]);
+    let started = HashMap::from([("redis".to_string(), Some("0123456789ab".to_string()))]);
+    assert_eq!(
+        plan(&machine_state, &started),
+        vec![(
+            "redis".to_string(),
+            vec![format!("config hash 0123456789ab → {}", config_hash)]
+        )]
+    );
+    let started = HashMap::from([("redis".to_string(), None)]);
+    assert_eq!(
+        plan(&machine_state, &started),
+        vec![("redis".to_string(), vec!["no saved definition".to_string()])]
+    );
+
+    let started = HashMap::new();
+    machine_state.containers.insert("redis".to_string(), old);
+    assert_eq!(
+        plan(&machine_state, &started),
+        vec![(
+            "redis".to_string(),
+            vec![
+                "env A=1, B=2 → A=1".to_string(),
+                "tag latest → 7.2".to_string()
+            ]
+        )]
+    );
+
+    machine_state.containers.insert("redis".to_string(), new);
+    assert_eq!(plan(&machine_state, &started), vec![]);
+}